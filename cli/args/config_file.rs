@@ -4,6 +4,10 @@ use crate::util::fs::canonicalize_path;
 use crate::util::path::specifier_to_file_path;
 
 use berlin_core::ModuleSpecifier;
+use berlin_core::PermalinkOptions;
+use berlin_core::Taxonomies;
+use images::ImageFormat;
+use images::ImageOptions;
 use libs::anyhow::anyhow;
 use libs::anyhow::bail;
 use libs::anyhow::Context;
@@ -11,8 +15,11 @@ use libs::anyhow::Error;
 use libs::log;
 use libs::toml;
 use libs::toml::Value;
+use markdown::FrontMatterSchema;
+use markdown::MarkdownOptions;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::Path;
@@ -79,6 +86,203 @@ impl ProfilesConfig {
     }
 }
 
+/// Settings for the compiled stylesheet, read from the `[css]` section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CssConfig {
+    /// Inline the rules matching the above-the-fold markup of every page.
+    pub critical: bool,
+    /// The stylesheet in `css/` whose rules are inlined.
+    pub stylesheet: String,
+    /// Marks the end of the above-the-fold markup in a rendered page.
+    pub fold_marker: String,
+}
+
+impl Default for CssConfig {
+    fn default() -> Self {
+        Self {
+            critical: false,
+            stylesheet: "styles.css".to_string(),
+            fold_marker: "<!-- fold -->".to_string(),
+        }
+    }
+}
+
+/// Settings for static assets, read from the `[assets]` section.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetsConfig {
+    /// Insert a content hash into the names of written assets.
+    pub fingerprint: bool,
+}
+
+/// Settings for the image pipeline, read from the `[images]` section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// The widths of the responsive variants generated for every image.
+    pub widths: Vec<u32>,
+    /// The formats of the responsive variants, e.g. `avif` or `webp`.
+    pub formats: Vec<String>,
+    /// The quality used by lossy encoders, from 1 to 100.
+    pub quality: u8,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        let ImageOptions {
+            widths,
+            formats,
+            quality,
+        } = ImageOptions::default();
+        Self {
+            widths,
+            formats: formats.iter().map(|f| f.to_string()).collect(),
+            quality,
+        }
+    }
+}
+
+impl ImagesConfig {
+    pub fn to_image_options(&self) -> Result<ImageOptions, Error> {
+        Ok(ImageOptions {
+            widths: self.widths.clone(),
+            formats: self
+                .formats
+                .iter()
+                .map(|f| f.parse())
+                .collect::<Result<Vec<ImageFormat>, Error>>()?,
+            quality: self.quality,
+        })
+    }
+}
+
+/// Settings for the photostream, read from the `[photostream]` section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotostreamConfig {
+    /// The directory in `static/` the photos are read from, unless they are
+    /// listed in `data/photos.toml`.
+    pub directory: String,
+    /// Expose the GPS position recorded in the EXIF data of a photo.
+    pub include_gps: bool,
+    /// The width of the thumbnails shown in the photostream.
+    pub thumbnail_width: u32,
+}
+
+impl Default for PhotostreamConfig {
+    fn default() -> Self {
+        Self {
+            directory: "pics/photostream".to_string(),
+            include_gps: false,
+            thumbnail_width: 490,
+        }
+    }
+}
+
+/// Settings for the generated social preview images, read from the
+/// `[og_image]` section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OgImageConfig {
+    /// Generate an image for every page without a hand-made one.
+    pub generate: bool,
+    /// The SVG template in `pages/` the images are rendered from. A built-in
+    /// template is used if it does not exist.
+    pub template: String,
+    /// Directories with additional fonts used by the template.
+    pub fonts: Vec<String>,
+}
+
+impl Default for OgImageConfig {
+    fn default() -> Self {
+        Self {
+            generate: true,
+            template: "og_image.svg.tera".to_string(),
+            fonts: vec![],
+        }
+    }
+}
+
+/// Redirects from old URLs, read from the `[redirects]` section:
+///
+/// ```toml
+/// [redirects]
+/// nginx = true
+/// paths = { "/notes/old-title.html" = "/notes/new-title.html" }
+/// ```
+///
+/// Pages can list their old URLs in the `aliases` of their front matter.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedirectsConfig {
+    /// Old URLs mapped to the URLs they moved to.
+    pub paths: BTreeMap<String, String>,
+    /// Write the redirects to `_redirects`, as read by Netlify and Cloudflare
+    /// Pages.
+    pub netlify: bool,
+    /// Write the redirects to `redirects.map`, to be included in an nginx
+    /// `map` block.
+    pub nginx: bool,
+    /// Redirect the old URL of a page whose permalink changed since the last
+    /// build.
+    pub track_moves: bool,
+}
+
+impl Default for RedirectsConfig {
+    fn default() -> Self {
+        Self {
+            paths: BTreeMap::new(),
+            netlify: false,
+            nginx: false,
+            track_moves: true,
+        }
+    }
+}
+
+/// Settings for the conversion of markdown, read from the `[markdown]` section.
+///
+/// Pages can override all settings but `highlight` in their front matter.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownConfig {
+    #[serde(flatten)]
+    pub options: MarkdownOptions,
+    pub highlight: HighlightConfig,
+}
+
+/// Settings for the highlighting of code blocks, read from the
+/// `[markdown.highlight]` section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightConfig {
+    /// The name of a built-in theme, or the path of a `.tmTheme` file
+    /// relative to the site.
+    pub theme: String,
+    /// A directory of the site with additional `.sublime-syntax` files.
+    pub syntaxes: Option<String>,
+    /// Emit classes instead of inline styles and write the colors of the
+    /// theme to `stylesheet`.
+    pub classes: bool,
+    /// The stylesheet written when `classes` is set, relative to the target
+    /// directory.
+    pub stylesheet: String,
+    /// Number the lines of every code block.
+    pub line_numbers: bool,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            theme: "base16-ocean.dark".to_string(),
+            syntaxes: None,
+            classes: false,
+            stylesheet: "css/highlight.css".to_string(),
+            line_numbers: false,
+        }
+    }
+}
+
 impl SiteConfig {
     pub fn empty() -> SiteConfig {
         SiteConfig {
@@ -103,6 +307,17 @@ impl ConfigFile {
             toml: ConfigFileToml {
                 site: None,
                 profiles: None,
+                css: None,
+                assets: None,
+                images: None,
+                photostream: None,
+                og_image: None,
+                markdown: None,
+                schemas: None,
+                permalinks: None,
+                redirects: None,
+                templates: None,
+                taxonomies: None,
             },
         }
     }
@@ -259,6 +474,113 @@ impl ConfigFile {
             Ok(ProfilesConfig::empty())
         }
     }
+
+    pub fn to_css_config(&self) -> Result<CssConfig, Error> {
+        match self.toml.css.clone() {
+            Some(css_config) => css_config
+                .try_into()
+                .context("css config should be an object"),
+            None => Ok(CssConfig::default()),
+        }
+    }
+
+    pub fn to_assets_config(&self) -> Result<AssetsConfig, Error> {
+        match self.toml.assets.clone() {
+            Some(assets_config) => assets_config
+                .try_into()
+                .context("assets config should be an object"),
+            None => Ok(AssetsConfig::default()),
+        }
+    }
+
+    pub fn to_images_config(&self) -> Result<ImagesConfig, Error> {
+        match self.toml.images.clone() {
+            Some(images_config) => images_config
+                .try_into()
+                .context("images config should be an object"),
+            None => Ok(ImagesConfig::default()),
+        }
+    }
+
+    pub fn to_photostream_config(&self) -> Result<PhotostreamConfig, Error> {
+        match self.toml.photostream.clone() {
+            Some(photostream_config) => photostream_config
+                .try_into()
+                .context("photostream config should be an object"),
+            None => Ok(PhotostreamConfig::default()),
+        }
+    }
+
+    pub fn to_og_image_config(&self) -> Result<OgImageConfig, Error> {
+        match self.toml.og_image.clone() {
+            Some(og_image_config) => og_image_config
+                .try_into()
+                .context("og_image config should be an object"),
+            None => Ok(OgImageConfig::default()),
+        }
+    }
+
+    pub fn to_markdown_config(&self) -> Result<MarkdownConfig, Error> {
+        match self.toml.markdown.clone() {
+            Some(markdown_config) => markdown_config
+                .try_into()
+                .context("markdown config should be an object"),
+            None => Ok(MarkdownConfig::default()),
+        }
+    }
+
+    /// Returns the front matter schemas of the `[schemas.<collection>]`
+    /// sections by collection.
+    pub fn to_schemas_config(&self) -> Result<HashMap<String, FrontMatterSchema>, Error> {
+        match self.toml.schemas.clone() {
+            Some(schemas_config) => schemas_config
+                .try_into()
+                .context("schemas config should be an object of collections"),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Returns how the permalinks of the pages are built, from the
+    /// `[permalinks]` section.
+    pub fn to_permalinks_config(&self) -> Result<PermalinkOptions, Error> {
+        match self.toml.permalinks.clone() {
+            Some(permalinks_config) => permalinks_config
+                .try_into()
+                .context("permalinks config should be an object"),
+            None => Ok(PermalinkOptions::default()),
+        }
+    }
+
+    /// Returns the templates the pages of a collection are rendered with by
+    /// default, from the `[templates]` section, e.g. `talks = "talk.tera"`.
+    pub fn to_templates_config(&self) -> Result<HashMap<String, String>, Error> {
+        match self.toml.templates.clone() {
+            Some(templates_config) => templates_config
+                .try_into()
+                .context("templates config should map collections to templates"),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Returns the taxonomies the pages are listed under, from the
+    /// `[taxonomies]` section, or only `tags` without one.
+    pub fn to_taxonomies_config(&self) -> Result<Taxonomies, Error> {
+        match self.toml.taxonomies.clone() {
+            Some(taxonomies_config) => taxonomies_config
+                .try_into()
+                .context("taxonomies config should be an object of taxonomies"),
+            None => Ok(berlin_core::default_taxonomies()),
+        }
+    }
+
+    pub fn to_redirects_config(&self) -> Result<RedirectsConfig, Error> {
+        match self.toml.redirects.clone() {
+            Some(redirects_config) => redirects_config
+                .try_into()
+                .context("redirects config should be an object"),
+            None => Ok(RedirectsConfig::default()),
+        }
+    }
 }
 
 /// A structure for managing the configuration of Berlin
@@ -266,6 +588,17 @@ impl ConfigFile {
 pub struct ConfigFileToml {
     pub site: Option<Value>,
     pub profiles: Option<Value>,
+    pub css: Option<Value>,
+    pub assets: Option<Value>,
+    pub images: Option<Value>,
+    pub photostream: Option<Value>,
+    pub og_image: Option<Value>,
+    pub markdown: Option<Value>,
+    pub schemas: Option<Value>,
+    pub permalinks: Option<Value>,
+    pub redirects: Option<Value>,
+    pub templates: Option<Value>,
+    pub taxonomies: Option<Value>,
 }
 
 #[cfg(test)]
@@ -299,6 +632,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_css_config() {
+        let config_text = r#"
+            [css]
+            critical = true
+            stylesheet = "main.css"
+        "#;
+
+        let config_specifier = ModuleSpecifier::parse("file:///berlin/berlin.toml").unwrap();
+        let config_file = ConfigFile::new(config_text, &config_specifier).unwrap();
+        let css_config = config_file.to_css_config().expect("error parsing");
+        assert!(css_config.critical);
+        assert_eq!(css_config.stylesheet, "main.css");
+        assert_eq!(css_config.fold_marker, "<!-- fold -->");
+    }
+
+    #[test]
+    fn test_parse_markdown_config() {
+        let config_text = r#"
+            [markdown]
+            smart_punctuation = true
+            unsafe_html = false
+
+            [markdown.highlight]
+            classes = true
+        "#;
+
+        let config_specifier = ModuleSpecifier::parse("file:///berlin/berlin.toml").unwrap();
+        let config_file = ConfigFile::new(config_text, &config_specifier).unwrap();
+        let markdown_config = config_file.to_markdown_config().expect("error parsing");
+        assert!(markdown_config.options.smart_punctuation);
+        assert!(!markdown_config.options.unsafe_html);
+        assert!(markdown_config.options.header_ids);
+        assert!(markdown_config.highlight.classes);
+
+        let config_text = "[markdown]\nsmart_quotes = true";
+        let config_file = ConfigFile::new(config_text, &config_specifier).unwrap();
+        assert!(config_file.to_markdown_config().is_err());
+    }
+
     #[test]
     fn test_parse_config_with_empty_file() {
         let config_text = "";
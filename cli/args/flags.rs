@@ -17,7 +17,9 @@ static SHORT_VERSION: Lazy<String> = Lazy::new(|| {
 });
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct BuildFlags {}
+pub struct BuildFlags {
+    pub strict: bool,
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct InitFlags {
@@ -143,6 +145,12 @@ fn build_subcommand() -> Command {
     Command::new("build")
         .about("Compile the data")
         .arg(config_arg())
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Fail the build if errors are reported for the content")
+                .action(clap::ArgAction::SetTrue),
+        )
 }
 
 fn serve_subcommand() -> Command {
@@ -231,7 +239,9 @@ fn init(flags: &mut Flags, matches: &clap::ArgMatches) {
 
 fn build(flags: &mut Flags, matches: &clap::ArgMatches) {
     config_args_parse(flags, matches);
-    flags.subcommand = BerlinSubcommand::Build(BuildFlags {});
+    flags.subcommand = BerlinSubcommand::Build(BuildFlags {
+        strict: matches.get_flag("strict"),
+    });
 }
 
 fn serve(flags: &mut Flags, matches: &clap::ArgMatches) {
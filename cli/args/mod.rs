@@ -4,7 +4,14 @@ mod flags;
 use std::env;
 use std::path::PathBuf;
 
+pub use config_file::AssetsConfig;
 pub use config_file::ConfigFile;
+pub use config_file::ImagesConfig;
+pub use config_file::MarkdownConfig;
+pub use config_file::OgImageConfig;
+pub use config_file::PhotostreamConfig;
+pub use config_file::RedirectsConfig;
+pub use config_file::SiteConfig;
 pub use flags::*;
 
 use berlin_core::ModuleSpecifier;
@@ -38,6 +45,10 @@ impl CliOptions {
         self.maybe_config_file.as_ref().map(|f| f.specifier.clone())
     }
 
+    pub fn maybe_config_file(&self) -> &Option<ConfigFile> {
+        &self.maybe_config_file
+    }
+
     pub fn resolve_berlin_dir(&self) -> Result<BerlinDir, Error> {
         Ok(BerlinDir::new(self.maybe_custom_root())?)
     }
@@ -49,6 +60,14 @@ impl CliOptions {
             .or_else(|| env::var("BERLIN_DIR").map(String::into).ok())
     }
 
+    /// Whether errors reported for the content fail the build.
+    pub fn strict(&self) -> bool {
+        matches!(
+            self.flags.subcommand,
+            BerlinSubcommand::Build(BuildFlags { strict: true })
+        )
+    }
+
     pub fn watch_paths(&self) -> &Option<Vec<PathBuf>> {
         &self.flags.watch
     }
@@ -36,6 +36,10 @@ impl BerlinDir {
         self.root.join("pages")
     }
 
+    pub fn shortcodes_file_path(&self) -> PathBuf {
+        self.root.join("shortcodes")
+    }
+
     pub fn content_file_path(&self) -> PathBuf {
         self.root.join("content")
     }
@@ -52,6 +56,10 @@ impl BerlinDir {
         self.root.join("data")
     }
 
+    pub fn cache_file_path(&self) -> PathBuf {
+        self.root.join(".cache")
+    }
+
     pub fn target_file_path(&self) -> PathBuf {
         self.root.join("target")
     }
@@ -2,7 +2,7 @@ use berlin_core::ModuleSpecifier;
 use berlin_core::ParsedSource;
 use libs::parking_lot::Mutex;
 
-use parser::{CapturingParser, ParsedSourceStore};
+use parser::{CapturingParser, DefaultMarkdownParser, ParsedSourceStore};
 
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -28,13 +28,15 @@ impl ParsedSourceStore for ParsedSourceCacheSources {
 #[derive(Clone)]
 pub struct ParsedSourceCache {
     _db_cache_path: Option<PathBuf>,
+    markdown_parser: DefaultMarkdownParser,
     sources: ParsedSourceCacheSources,
 }
 
 impl ParsedSourceCache {
-    pub fn new(sql_cache_path: Option<PathBuf>) -> Self {
+    pub fn new(sql_cache_path: Option<PathBuf>, markdown_parser: DefaultMarkdownParser) -> Self {
         Self {
             _db_cache_path: sql_cache_path,
+            markdown_parser,
             sources: Default::default(),
         }
     }
@@ -44,9 +46,14 @@ impl ParsedSourceCache {
         self.sources.0.lock().remove(specifier);
     }
 
+    /// Frees all parsed sources from memory.
+    pub fn clear(&self) {
+        self.sources.0.lock().clear();
+    }
+
     /// Creates a parser that will reuse a ParsedSource from the store
     /// if it exists, or else parse.
     pub fn as_capturing_parser(&self) -> CapturingParser {
-        CapturingParser::new(None, &self.sources)
+        CapturingParser::new(None, &self.markdown_parser, &self.sources)
     }
 }
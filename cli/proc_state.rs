@@ -1,17 +1,41 @@
+use crate::args::AssetsConfig;
 use crate::args::CliOptions;
 use crate::args::Flags;
+use crate::args::ImagesConfig;
+use crate::args::MarkdownConfig;
 use crate::cache::{BerlinDir, ParsedSourceCache};
+use crate::tasks::functions::load_feed;
+use crate::tasks::model::{Feed, Photo};
+use crate::tasks::photostream::load_photos;
+use crate::tasks::taxonomies::load_pages;
 use crate::util::fs::load_files;
 use berlin_core::normalize_path;
+use berlin_core::ContentIndex;
+use berlin_core::Diagnostics;
 use berlin_core::ModuleSpecifier;
 use berlin_core::ParsedSource;
+use berlin_core::PermalinkOptions;
 use berlin_core::Resolutions;
 use berlin_core::ResolutionsBuilder;
+use berlin_core::SharedContentIndex;
+use berlin_core::Taxonomies;
+use errors::error::generic_error;
+use images::ImageProcessor;
 use libs::anyhow::Error;
 use libs::parking_lot::Mutex;
+use libs::parking_lot::RwLock;
+use markdown::HighlightOptions;
+use markdown::Highlighter;
+use markdown::ShortcodeContext;
+use markdown::ShortcodeTemplates;
+use markdown::{BodyLinks, RawHtml};
+use parser::DefaultMarkdownParser;
+use templates::AssetManifest;
 use templates::Hera;
+use templates::SharedAssetManifest;
 
 use core::fmt;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -33,6 +57,24 @@ pub struct Inner {
     maybe_file_watcher_reporter: Option<FileWatcherReporter>,
     pub maybe_css_resolutions: Option<Resolutions>,
     pub hera: Arc<Mutex<Hera>>,
+    pub assets_config: AssetsConfig,
+    pub asset_manifest: SharedAssetManifest,
+    pub image_processor: Arc<ImageProcessor>,
+    pub diagnostics: Diagnostics,
+    pub content_index: SharedContentIndex,
+    pub permalink_options: PermalinkOptions,
+    /// The photos of the photostream, loaded once per build.
+    photos: Mutex<Option<Arc<Vec<Photo>>>>,
+    /// The pages below the content directory, loaded once per build.
+    pages: Mutex<Option<Arc<Vec<ParsedSource>>>>,
+    /// The entries of the feed, loaded once per build.
+    feed: Mutex<Option<Arc<Vec<Feed>>>>,
+    /// The templates of the pages by collection.
+    pub templates: HashMap<String, String>,
+    /// The taxonomies by name.
+    pub taxonomies: Taxonomies,
+    pub markdown_config: MarkdownConfig,
+    pub highlighter: Arc<Highlighter>,
 }
 
 impl Deref for ProcState {
@@ -66,7 +108,78 @@ impl ProcState {
         maybe_sender: Option<tokio::sync::mpsc::UnboundedSender<Vec<PathBuf>>>,
     ) -> Result<Self, Error> {
         let dir = cli_options.resolve_berlin_dir()?;
-        let parsed_source_cache = ParsedSourceCache::new(None);
+        let images_config = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_images_config()?,
+            None => ImagesConfig::default(),
+        };
+        let image_processor = Arc::new(ImageProcessor::new(
+            (dir.static_file_path(), "/static"),
+            (
+                dir.target_file_path()
+                    .join("static")
+                    .join("processed_images"),
+                "/static/processed_images",
+            ),
+            dir.cache_file_path().join("images"),
+            images_config.to_image_options()?,
+        ));
+        let shortcodes_path = dir.shortcodes_file_path();
+        let maybe_shortcode_templates = if shortcodes_path.is_dir() {
+            Some(Arc::new(ShortcodeTemplates::new(&shortcodes_path)?))
+        } else {
+            None
+        };
+        let markdown_config = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_markdown_config()?,
+            None => MarkdownConfig::default(),
+        };
+        let schemas = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_schemas_config()?,
+            None => HashMap::new(),
+        };
+        let permalink_options = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_permalinks_config()?,
+            None => PermalinkOptions::default(),
+        };
+        let templates = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_templates_config()?,
+            None => HashMap::new(),
+        };
+        let taxonomies = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_taxonomies_config()?,
+            None => berlin_core::default_taxonomies(),
+        };
+        let highlight_config = &markdown_config.highlight;
+        let theme_path = dir.root_file_path().join(&highlight_config.theme);
+        let highlighter = Arc::new(Highlighter::new(&HighlightOptions {
+            theme: match highlight_config.theme.ends_with(".tmTheme") {
+                true => theme_path.to_string_lossy().to_string(),
+                false => highlight_config.theme.clone(),
+            },
+            maybe_syntaxes: highlight_config
+                .syntaxes
+                .as_ref()
+                .map(|syntaxes| dir.root_file_path().join(syntaxes)),
+            classes: highlight_config.classes,
+            line_numbers: highlight_config.line_numbers,
+        })?);
+        let diagnostics = Diagnostics::default();
+        let content_index = Arc::new(RwLock::new(ContentIndex::default()));
+        let parsed_source_cache = ParsedSourceCache::new(
+            None,
+            DefaultMarkdownParser::new(ShortcodeContext {
+                maybe_image_processor: Some(image_processor.clone()),
+                maybe_templates: maybe_shortcode_templates,
+                diagnostics: diagnostics.clone(),
+                content_index: content_index.clone(),
+                maybe_highlighter: Some(highlighter.clone()),
+                markdown_options: markdown_config.options.clone(),
+                schemas,
+                taxonomies: taxonomies.clone(),
+                raw_html: RawHtml::default(),
+                body_links: BodyLinks::default(),
+            }),
+        );
 
         let maybe_file_watcher_reporter = maybe_sender.map(|sender| FileWatcherReporter {
             sender,
@@ -85,7 +198,16 @@ impl ProcState {
             Some(css_resolutions)
         };
 
-        let hera = Hera::new(&dir.templates_file_path())?;
+        let assets_config = match cli_options.maybe_config_file() {
+            Some(config_file) => config_file.to_assets_config()?,
+            None => AssetsConfig::default(),
+        };
+        let asset_manifest = Arc::new(RwLock::new(AssetManifest::default()));
+
+        let mut hera = Hera::new(dir.templates_file_path())?;
+        hera.register_asset_manifest(asset_manifest.clone());
+        hera.register_image_processor(image_processor.clone());
+        hera.register_content_index(content_index.clone());
 
         Ok(ProcState(Arc::new(Inner {
             dir,
@@ -94,9 +216,124 @@ impl ProcState {
             maybe_file_watcher_reporter,
             maybe_css_resolutions,
             hera: Arc::new(Mutex::new(hera)),
+            assets_config,
+            asset_manifest,
+            image_processor,
+            diagnostics,
+            content_index,
+            permalink_options,
+            photos: Mutex::new(None),
+            pages: Mutex::new(None),
+            feed: Mutex::new(None),
+            templates,
+            taxonomies,
+            markdown_config,
+            highlighter,
         })))
     }
 
+    /// Prepares a build: rebuilds the content index and drops what was loaded
+    /// during the previous build.
+    pub fn start_build(&self) -> Result<(), Error> {
+        self.index_content()?;
+        *self.photos.lock() = None;
+        *self.pages.lock() = None;
+        *self.feed.lock() = None;
+        Ok(())
+    }
+
+    /// Returns the photos of the photostream, loaded on first use in a build.
+    pub fn photos(&self) -> Result<Arc<Vec<Photo>>, Error> {
+        let mut maybe_photos = self.photos.lock();
+        if let Some(photos) = maybe_photos.as_ref() {
+            return Ok(photos.clone());
+        }
+        let photos = Arc::new(load_photos(self)?);
+        *maybe_photos = Some(photos.clone());
+        Ok(photos)
+    }
+
+    /// Returns the markdown and org pages below the content directory, newest
+    /// first, loaded on first use in a build.
+    pub fn pages(&self) -> Result<Arc<Vec<ParsedSource>>, Error> {
+        let mut maybe_pages = self.pages.lock();
+        if let Some(pages) = maybe_pages.as_ref() {
+            return Ok(pages.clone());
+        }
+        let pages = Arc::new(load_pages(self)?);
+        *maybe_pages = Some(pages.clone());
+        Ok(pages)
+    }
+
+    /// Returns the entries of the feed, loaded on first use in a build.
+    pub fn feed(&self) -> Result<Arc<Vec<Feed>>, Error> {
+        let mut maybe_feed = self.feed.lock();
+        if let Some(feed) = maybe_feed.as_ref() {
+            return Ok(feed.clone());
+        }
+        let feed = Arc::new(load_feed(self)?);
+        *maybe_feed = Some(feed.clone());
+        Ok(feed)
+    }
+
+    /// Rebuilds the content index from the markdown and org files below the
+    /// content directory, failing if two pages have the same permalink.
+    ///
+    /// Parsed sources hold the permalinks of their pages and of the pages
+    /// they link to, so they are dropped when the index changes.
+    fn index_content(&self) -> Result<(), Error> {
+        let content_path = self.dir.content_file_path();
+        let mut paths = load_files(&content_path, "**/*.md");
+        paths.extend(load_files(&content_path, "**/*.org"));
+        let content_index = ContentIndex::build(&content_path, &paths, &self.permalink_options)?;
+        let mut current = self.content_index.write();
+        if *current != content_index {
+            *current = content_index;
+            self.parsed_source_cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes an asset to the target directory and records it in the asset
+    /// manifest under `logical_path`, e.g. `css/styles.css`.
+    pub fn write_asset(&self, logical_path: &str, content: &[u8]) -> Result<PathBuf, Error> {
+        let path = self.asset_manifest.write().insert(
+            logical_path,
+            content,
+            self.assets_config.fingerprint,
+        );
+        let output = self.dir.target_file_path().join(path);
+        std::fs::create_dir_all(output.parent().unwrap())?;
+        std::fs::write(&output, content)?;
+
+        Ok(output)
+    }
+
+    /// Returns the template `source` is rendered with: the `template` of its
+    /// front matter, else the template of its collection, else `default`.
+    pub fn page_template(&self, source: &ParsedSource, default: &str) -> Result<String, Error> {
+        let maybe_template = source
+            .front_matter()
+            .and_then(|fm| fm.template.clone())
+            .or_else(|| {
+                let path = ModuleSpecifier::parse(source.specifier())
+                    .ok()?
+                    .to_file_path()
+                    .ok()?;
+                let collection = self.content_index.read().collection(&path)?;
+                self.templates.get(&collection).cloned()
+            });
+        let template = maybe_template.unwrap_or_else(|| default.to_string());
+        if !self.hera.lock().has_template(&template) {
+            return Err(generic_error(format!(
+                "{}: template `{template}` not found in {}",
+                source.specifier(),
+                self.dir.templates_file_path().display()
+            )));
+        }
+        Ok(template)
+    }
+
     pub fn render_parsed_source_with_context(
         &self,
         file_path: &str,
@@ -179,3 +416,65 @@ impl Reporter for FileWatcherReporter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::ConfigFlag;
+    use berlin_core::{FrontMatter, MediaType, ParsedSourceBuilder};
+
+    #[test]
+    fn test_page_template() {
+        let root = std::env::temp_dir().join(format!("bln-templates-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("pages")).unwrap();
+        for template in ["page.tera", "talk.tera", "special.tera"] {
+            std::fs::write(root.join("pages").join(template), "").unwrap();
+        }
+        let config_path = root.join("berlin.toml");
+        std::fs::write(&config_path, "[templates]\ntalks = \"talk.tera\"\n").unwrap();
+        let ps = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(ProcState::build(Flags {
+                cache_path: Some(root.clone()),
+                config_flag: ConfigFlag::Path(config_path.to_string_lossy().to_string()),
+                ..Flags::default()
+            }))
+            .unwrap();
+        let talks = root.join("content/talks");
+        std::fs::create_dir_all(&talks).unwrap();
+        std::fs::write(talks.join("a.md"), "---\ntitle: A\n---\n").unwrap();
+        ps.start_build().unwrap();
+        let page = |path: &str, front_matter: &str| {
+            let front_matter: FrontMatter = libs::serde_yaml::from_str(front_matter).unwrap();
+            let specifier = ModuleSpecifier::from_file_path(root.join(path)).unwrap();
+            ParsedSourceBuilder::new(specifier.to_string(), MediaType::Markdown)
+                .front_matter(front_matter)
+                .build()
+        };
+
+        let template = |source: &ParsedSource| ps.page_template(source, "page.tera").ok();
+        assert_eq!(
+            template(&page("content/talks/a.md", "template: special.tera")).as_deref(),
+            Some("special.tera")
+        );
+        assert_eq!(
+            template(&page("content/talks/a.md", "title: A")).as_deref(),
+            Some("talk.tera")
+        );
+        assert_eq!(
+            template(&page("content/notes/b.md", "title: B")).as_deref(),
+            Some("page.tera")
+        );
+        let error = ps
+            .page_template(
+                &page("content/notes/b.md", "template: missing.tera"),
+                "page.tera",
+            )
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("template `missing.tera` not found"));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
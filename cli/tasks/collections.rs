@@ -0,0 +1,423 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use berlin_core::{
+    output_path, parse_front_matter, FrontMatter, LinkGraph, ModuleSpecifier, ParsedSource, Term,
+};
+use errors::error::generic_error;
+use libs::anyhow::{Context, Error};
+use libs::serde_yaml;
+use libs::tera;
+use serde::{Deserialize, Serialize};
+
+use crate::proc_state::ProcState;
+use crate::tasks::functions::extract_front_matter;
+use crate::tasks::model::Article;
+use crate::tasks::render::post_process;
+use crate::tasks::render::task::initialize_context;
+use crate::util::fs::load_files;
+
+use super::{Input, InputLoader, Task, Watch, WatchableTask};
+
+/// The metadata of a collection, read from the front matter of the
+/// `_index.md` of its directory:
+///
+/// ```yaml
+/// title: Talks
+/// template: talks/list.tera
+/// page_template: talks/talk.tera
+/// sort_by: weight
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct Section {
+    title: Option<String>,
+    description: Option<String>,
+    /// The template of the list page, `<collection>/list.tera` by default.
+    template: Option<String>,
+    /// The template of the pages, `<collection>/page.tera` by default.
+    page_template: Option<String>,
+    sort_by: SortBy,
+    /// Reverses the order given by `sort_by`, pages without its value still
+    /// come last.
+    reverse: bool,
+}
+
+impl Section {
+    /// Orders the pages with the front matter `a` and `b` by `sort_by`.
+    fn compare(&self, a: Option<&FrontMatter>, b: Option<&FrontMatter>) -> Ordering {
+        match self.sort_by {
+            SortBy::Date => {
+                let date = |fm: Option<&FrontMatter>| fm.and_then(|fm| fm.published.clone());
+                compare_values(date(a), date(b), !self.reverse)
+            }
+            SortBy::Title => {
+                let title = |fm: Option<&FrontMatter>| {
+                    fm.and_then(|fm| fm.title.as_deref().map(str::to_lowercase))
+                };
+                compare_values(title(a), title(b), self.reverse)
+            }
+            SortBy::Weight => {
+                let weight = |fm: Option<&FrontMatter>| fm.and_then(|fm| fm.weight);
+                compare_values(weight(a), weight(b), self.reverse)
+            }
+        }
+    }
+}
+
+/// Orders `a` and `b`, in reverse if `reverse` is set, with the missing
+/// values last.
+fn compare_values<T: Ord>(a: Option<T>, b: Option<T>, reverse: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if reverse => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SortBy {
+    /// The newest pages first, pages without a date last.
+    #[default]
+    Date,
+    /// In alphabetical order, pages without a title last.
+    Title,
+    /// The lightest pages first, pages without a weight last.
+    Weight,
+}
+
+/// A term of the pages of a collection and the number of its pages.
+#[derive(Serialize)]
+struct TermCount {
+    #[serde(flatten)]
+    term: Term,
+    count: usize,
+}
+
+/// Counts the pages of `sources` by the terms of every taxonomy, by the name
+/// of the taxonomy.
+fn count_terms(
+    taxonomies: &berlin_core::Taxonomies,
+    sources: &[ParsedSource],
+) -> BTreeMap<String, Vec<TermCount>> {
+    taxonomies
+        .keys()
+        .map(|name| {
+            let mut counts = BTreeMap::<Term, usize>::new();
+            for term in sources.iter().flat_map(|source| source.terms(name)) {
+                *counts.entry(term.clone()).or_default() += 1;
+            }
+            let counts = counts
+                .into_iter()
+                .map(|(term, count)| TermCount { term, count })
+                .collect();
+            (name.clone(), counts)
+        })
+        .collect()
+}
+
+/// Renders every directory below `content/` with an `_index.md`, or with
+/// templates or permalinks configured for it, as a collection: a list page
+/// and a page for every markdown and org file in it, in all its
+/// subdirectories.
+///
+/// The list page gets the `section` from the `_index.md` of the directory,
+/// its `pages` in order, their `tags` and their `terms` by taxonomy with the
+/// number of pages of each, the pages get the `section` too.
+pub struct Collections {
+    /// The collections rendered by other tasks.
+    pub skip: Vec<String>,
+}
+
+impl Collections {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let content_path = ps.dir.content_file_path();
+        let mut directories = std::fs::read_dir(&content_path)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_dir())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        directories.sort();
+
+        // Backlinks come from all pages, not only those of the same collection.
+        let link_graph = LinkGraph::new(&ps.pages()?);
+        let mut files = Vec::new();
+        for directory in directories {
+            let name = directory
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if name.starts_with(['_', '.']) || self.skip.contains(&name) {
+                continue;
+            }
+            // other directories, e.g. of images, are not collections
+            let configured = ps.templates.contains_key(&name)
+                || ps.permalink_options.collections.contains_key(&name);
+            if !configured && !directory.join("_index.md").is_file() {
+                continue;
+            }
+            files.append(&mut self.render_collection(ps, &name, &directory, &link_graph)?);
+        }
+
+        let files = post_process::inline_critical_css(ps, files)?;
+        for f in files {
+            std::fs::create_dir_all(f.0.parent().unwrap())?;
+            std::fs::write(&f.0, &f.1)?;
+        }
+
+        Ok(0)
+    }
+
+    fn render_collection(
+        &self,
+        ps: &ProcState,
+        name: &str,
+        directory: &Path,
+        link_graph: &LinkGraph,
+    ) -> Result<Vec<(PathBuf, String)>, Error> {
+        let index_path = directory.join("_index.md");
+        let section = match std::fs::read_to_string(&index_path) {
+            Ok(content) => match parse_front_matter(&content)? {
+                Some(front_matter) => serde_yaml::from_value::<Section>(front_matter)
+                    .with_context(|| format!("{}: invalid section", index_path.display()))?,
+                None => Section::default(),
+            },
+            Err(_) => Section::default(),
+        };
+
+        let mut paths = load_files(directory, "**/*.md");
+        paths.extend(load_files(directory, "**/*.org"));
+        paths.retain(|path| path.file_stem().is_some_and(|stem| stem != "_index"));
+        let index_paths = match index_path.is_file() {
+            true => vec![index_path.clone()],
+            false => vec![],
+        };
+        let parser = &ps.parsed_source_cache.as_capturing_parser();
+        let load = |name: &str, paths: &Vec<PathBuf>| -> Result<Vec<ParsedSource>, Error> {
+            let files_provider = InputLoader {
+                name,
+                base_path: directory,
+                inputs: &Input::Files(paths).into(),
+                parser,
+            };
+            Ok(files_provider
+                .load_input()?
+                .remove(name)
+                .unwrap_or_default())
+        };
+        let mut sources = load(name, &paths)?;
+        let maybe_index = load("_index", &index_paths)?.pop();
+
+        sources.sort_by(|a, b| section.compare(a.front_matter(), b.front_matter()));
+
+        let parent_context = initialize_context(ps.options.maybe_config_file_specifier())?;
+        let permalink = match maybe_index.as_ref().and_then(|index| index.permalink()) {
+            Some(permalink) => permalink.to_string(),
+            None => {
+                ps.permalink_options
+                    .permalink(&ps.dir.content_file_path(), &index_path, None)?
+            }
+        };
+        let section_context = tera::to_value(BTreeMap::from([
+            ("name", Some(name.to_string())),
+            ("title", section.title.clone()),
+            ("description", section.description.clone()),
+            ("permalink", Some(permalink.clone())),
+            (
+                "content",
+                maybe_index.as_ref().map(|index| index.data().to_string()),
+            ),
+        ]))?;
+
+        let target = ps.dir.target_file_path();
+        let mut files = Vec::new();
+        let default_template = section
+            .page_template
+            .clone()
+            .unwrap_or_else(|| format!("{name}/page.tera"));
+        for source in &sources {
+            let template = ps.page_template(source, &default_template)?;
+            let (path, source, context) = extract_front_matter(source, link_graph);
+            let mut ctx = parent_context.clone();
+            ctx.extend(context);
+            ctx.insert("section", &section_context);
+            files.push((
+                target.join(path),
+                ps.render_parsed_source_with_context(&template, &source, &ctx),
+            ));
+        }
+
+        let template = section
+            .template
+            .clone()
+            .unwrap_or_else(|| format!("{name}/list.tera"));
+        if !ps.hera.lock().has_template(&template) {
+            return Err(generic_error(format!(
+                "collection `{name}`: template `{template}` not found in {}",
+                ps.dir.templates_file_path().display()
+            )));
+        }
+        let pages = sources.iter().map(Article::new).collect::<Vec<_>>();
+        let terms = count_terms(&ps.taxonomies, &sources);
+        let mut context = parent_context;
+        context.insert("section", &section_context);
+        context.insert("pages", &pages);
+        context.insert("tags", terms.get("tags").map_or(&[][..], Vec::as_slice));
+        context.insert("terms", &terms);
+        files.push((
+            target.join(output_path(&permalink)),
+            ps.render_with_context(&template, &context),
+        ));
+
+        Ok(files)
+    }
+}
+
+impl WatchableTask for Collections {}
+
+impl Task for Collections {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for Collections {
+    fn on_change(&self, ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl fmt::Debug for Collections {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Collections")
+            .field("skip", &self.skip)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(section: &Section, front_matters: &[&str]) -> Vec<String> {
+        let mut front_matters = front_matters
+            .iter()
+            .map(|yaml| serde_yaml::from_str::<FrontMatter>(yaml).unwrap())
+            .collect::<Vec<_>>();
+        front_matters.sort_by(|a, b| section.compare(Some(a), Some(b)));
+        front_matters
+            .into_iter()
+            .map(|fm| fm.title.unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn test_count_terms() {
+        let mut taxonomies = berlin_core::default_taxonomies();
+        taxonomies.insert("series".to_string(), Default::default());
+        let page = |name: &str, tags: &[&str], series: &[&str]| {
+            let terms = |name: &str, values: &[&str]| {
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                (name.to_string(), taxonomies[name].terms(name, &values))
+            };
+            berlin_core::ParsedSourceBuilder::new(
+                format!("file:///site/content/talks/{name}.md"),
+                berlin_core::MediaType::Markdown,
+            )
+            .terms(BTreeMap::from([
+                terms("tags", tags),
+                terms("series", series),
+            ]))
+            .build()
+        };
+        let sources = [
+            page("a", &["rust"], &["Rust in 2023"]),
+            page("b", &["rust", "org"], &[]),
+        ];
+
+        let counts = count_terms(&taxonomies, &sources)
+            .into_iter()
+            .map(|(name, counts)| {
+                let counts = counts
+                    .into_iter()
+                    .map(|count| (count.term.name, count.count))
+                    .collect::<Vec<_>>();
+                (name, counts)
+            })
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            counts,
+            BTreeMap::from([
+                ("series".to_string(), vec![("Rust in 2023".to_string(), 1)]),
+                (
+                    "tags".to_string(),
+                    vec![("org".to_string(), 1), ("rust".to_string(), 2)]
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_section() {
+        let section: Section =
+            serde_yaml::from_str("title: Talks\nsort_by: weight\nreverse: true\n").unwrap();
+        assert_eq!(section.title.as_deref(), Some("Talks"));
+        assert!(matches!(section.sort_by, SortBy::Weight));
+        assert!(section.reverse);
+
+        let section: Section = serde_yaml::from_str("title: Notes\n").unwrap();
+        assert!(matches!(section.sort_by, SortBy::Date));
+        assert!(!section.reverse);
+        assert!(serde_yaml::from_str::<Section>("sort_by: size\n").is_err());
+    }
+
+    #[test]
+    fn test_sort_pages() {
+        let dated = [
+            "title: Old\ndate: 2022-01-01\n",
+            "title: Undated\n",
+            "title: New\ndate: 2023-04-03\n",
+        ];
+        let weighted = [
+            "title: B\nweight: 2\n",
+            "title: Unweighted\n",
+            "title: A\nweight: 1\n",
+        ];
+        let section = |sort_by, reverse| Section {
+            sort_by,
+            reverse,
+            ..Section::default()
+        };
+
+        assert_eq!(
+            sorted(&section(SortBy::Date, false), &dated),
+            ["New", "Old", "Undated"]
+        );
+        assert_eq!(
+            sorted(&section(SortBy::Date, true), &dated),
+            ["Old", "New", "Undated"]
+        );
+        assert_eq!(
+            sorted(&section(SortBy::Weight, false), &weighted),
+            ["A", "B", "Unweighted"]
+        );
+        assert_eq!(
+            sorted(&section(SortBy::Weight, true), &weighted),
+            ["B", "A", "Unweighted"]
+        );
+        assert_eq!(
+            sorted(
+                &section(SortBy::Title, true),
+                &["title: a\n", "title: B\n", "{}\n"]
+            ),
+            ["B", "a", ""]
+        );
+    }
+}
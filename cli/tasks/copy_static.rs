@@ -4,8 +4,12 @@ use std::fmt;
 
 use crate::{proc_state::ProcState, util::fs::consume_files};
 
+use super::photostream::private_photos;
 use super::{Task, Watch, WatchableTask};
 
+/// Copies the files of the static directory to the target directory, except
+/// the originals of the photostream unless `include_gps` is set, which are
+/// only published re-encoded, without their EXIF data.
 pub struct CopyStatic {
     pub output: String,
 }
@@ -14,21 +18,30 @@ impl WatchableTask for CopyStatic {}
 
 impl Task for CopyStatic {
     fn run(&self, ps: &ProcState) -> Result<i32, Error> {
-        consume_files(ps.dir.static_file_path(), "**/*.*", |specifiers| {
-            let static_file_path = ps.dir.static_file_path();
-            let target_file_path = ps.dir.target_file_path();
-            let prefix = static_file_path.to_string_lossy();
-            for specifier in specifiers {
-                let relative_path = specifier
-                    .path()
-                    .strip_prefix(&format!("{}/", prefix))
-                    .unwrap();
-
-                let output = target_file_path.join(self.output.replace("{file}", relative_path));
-                std::fs::create_dir_all(&output.parent().unwrap()).unwrap();
-                std::fs::copy(&specifier.path(), &output).unwrap();
-            }
-        });
+        let private_photos = private_photos(ps)?;
+        consume_files(
+            ps.dir.static_file_path(),
+            "**/*.*",
+            |specifiers| -> Result<(), Error> {
+                let static_file_path = ps.dir.static_file_path();
+                let prefix = static_file_path.to_string_lossy();
+                for specifier in specifiers {
+                    let relative_path = specifier
+                        .path()
+                        .strip_prefix(&format!("{}/", prefix))
+                        .unwrap();
+                    if private_photos.contains(relative_path) {
+                        continue;
+                    }
+
+                    let output = self.output.replace("{file}", relative_path);
+                    let content = std::fs::read(specifier.path())?;
+                    ps.write_asset(&output, &content)?;
+                }
+
+                Ok(())
+            },
+        )?;
 
         Ok(0)
     }
@@ -37,17 +50,19 @@ impl Task for CopyStatic {
 impl Watch for CopyStatic {
     fn on_change(&self, ps: &ProcState, specifier: &ModuleSpecifier) -> Result<i32, Error> {
         let static_file_path = ps.dir.static_file_path();
-        let target_file_path = ps.dir.target_file_path();
         let prefix = static_file_path.to_string_lossy();
         if specifier.path().starts_with(prefix.as_ref()) {
             let relative_path = specifier
                 .path()
                 .strip_prefix(&format!("{}/", prefix))
                 .unwrap();
+            if private_photos(ps)?.contains(relative_path) {
+                return Ok(0);
+            }
 
-            let output = target_file_path.join(self.output.replace("{file}", relative_path));
-            std::fs::create_dir_all(&output.parent().unwrap()).unwrap();
-            std::fs::copy(&specifier.path(), &output).unwrap();
+            let output = self.output.replace("{file}", relative_path);
+            let content = std::fs::read(specifier.path())?;
+            ps.write_asset(&output, &content)?;
         }
 
         Ok(0)
@@ -17,12 +17,12 @@ impl Css {
             for parsed_source in input.iter() {
                 let specifier = resolve_url_or_path(parsed_source.specifier())?;
                 let path_buf = specifier_to_file_path(&specifier)?;
-                let output = ps.dir.target_file_path().join("css").join(
+                let output = format!(
+                    "css/{}",
                     self.output
-                        .replace("{file}", path_buf.file_name().unwrap().to_str().unwrap()),
+                        .replace("{file}", path_buf.file_name().unwrap().to_str().unwrap())
                 );
-                std::fs::create_dir_all(&output.parent().unwrap())?;
-                std::fs::write(output, parsed_source.data())?;
+                ps.write_asset(&output, parsed_source.data().as_bytes())?;
             }
         }
 
@@ -88,3 +88,40 @@ impl<'a> fmt::Debug for Css {
             .finish()
     }
 }
+
+/// Writes the colors of the highlighting theme to the stylesheet configured
+/// in `[markdown.highlight]`, if code blocks are highlighted with classes.
+pub struct SyntaxTheme;
+
+impl SyntaxTheme {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let highlight_config = &ps.markdown_config.highlight;
+        if highlight_config.classes {
+            let stylesheet = ps.highlighter.stylesheet()?;
+            ps.write_asset(&highlight_config.stylesheet, stylesheet.as_bytes())?;
+        }
+
+        Ok(0)
+    }
+}
+
+impl WatchableTask for SyntaxTheme {}
+
+impl Task for SyntaxTheme {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for SyntaxTheme {
+    fn on_change(&self, _ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        // The theme is loaded once per build, so there is nothing to update.
+        Ok(0)
+    }
+}
+
+impl fmt::Debug for SyntaxTheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SyntaxTheme").finish()
+    }
+}
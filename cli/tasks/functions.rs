@@ -1,18 +1,19 @@
-use std::{
-    collections::{BTreeSet, HashMap},
-    ops::DerefMut,
-};
+use std::{collections::HashMap, ops::DerefMut};
 
-use berlin_core::{resolve_path, FrontMatter, MediaType, ParsedSource, ParsedSourceBuilder};
+use berlin_core::{
+    output_path, resolve_path, Diagnostic, LinkGraph, MediaType, ModuleSpecifier, ParsedSource,
+    Severity, Taxonomies,
+};
 use errors::error::generic_error;
 use libs::anyhow::Error;
 use libs::serde_json;
-use libs::slugify::slugify;
 use libs::tera;
 
+use crate::proc_state::ProcState;
+
 use super::{
-    model::{Article, Feed, Picture, Record, Tag},
-    AggregatedSources, SortFn,
+    model::{Article, Feed, Record},
+    AggregatedSources, Input, InputLoader, SortFn,
 };
 
 pub fn bln_input_sort_by_date_published(
@@ -20,15 +21,10 @@ pub fn bln_input_sort_by_date_published(
     sources: &[ParsedSource],
     _sort_fn: Option<SortFn>,
 ) -> AggregatedSources {
+    // Sources without a date come last.
     let sort_fn: SortFn = Box::new(|a, b| {
-        let published_a = a
-            .front_matter()
-            .map(|f| f.published.as_ref().unwrap())
-            .unwrap();
-        let published_b = b
-            .front_matter()
-            .map(|f| f.published.as_ref().unwrap())
-            .unwrap();
+        let published_a = a.front_matter().and_then(|f| f.published.as_ref());
+        let published_b = b.front_matter().and_then(|f| f.published.as_ref());
 
         published_b.cmp(&published_a)
     });
@@ -50,153 +46,22 @@ pub fn bln_input_aggregate_all(
     map
 }
 
-pub fn bln_input_feed_aggregate_all(
-    _name: &str,
-    sources: &[ParsedSource],
-    sort_fn: Option<SortFn>,
-) -> AggregatedSources {
-    let mut v = sources.to_vec();
-    sort_fn.map(|f| v.deref_mut().sort_by(f));
-
-    let mut map = HashMap::new();
-    map.insert("feed".into(), v);
-
-    map
-}
-
-pub fn extract_tags_from_feed(sources: &Vec<ParsedSource>) -> Vec<tera::Value> {
-    let uncategorized = "uncategorized";
-
-    let mut hs: BTreeSet<Tag> = BTreeSet::new();
-
-    for src in sources {
-        for feed in parse_csv(Some(src)) {
-            if feed.tags.is_empty() {
-                hs.insert(Tag::new(uncategorized));
-            } else {
-                hs.extend(feed.tags);
-            }
-        }
-    }
-
-    to_values(hs)
-}
-
-pub fn extract_tags(sources: &Vec<ParsedSource>) -> Vec<tera::Value> {
-    let mut hs: BTreeSet<Tag> = BTreeSet::new();
-
-    for s in sources {
-        if let Some(fm) = s.front_matter() {
-            match fm.tags.as_ref() {
-                Some(tags) => {
-                    hs.extend(tags.iter().map(|t| Tag::new(t)));
-                }
-                None => {
-                    hs.insert(Tag::new("uncategorized"));
-                }
-            }
-        }
-    }
-
-    to_values(hs)
-}
-
-fn to_values<T: serde::Serialize>(hs: BTreeSet<T>) -> Vec<tera::Value> {
-    Vec::from_iter(hs)
-        .iter()
-        .flat_map(|v| serde_json::to_value(v))
-        .collect()
-}
-
-pub fn bln_input_aggregate_by_category(
-    _name: &str,
-    sources: &[ParsedSource],
-    maybe_sort_fn: Option<SortFn>,
-) -> AggregatedSources {
-    let mut map = HashMap::new();
-
-    for s in sources {
-        if let Some(fm) = s.front_matter() {
-            match fm.tags.as_ref() {
-                Some(tags) => {
-                    for t in tags {
-                        map.entry(t.to_string())
-                            .or_insert(Vec::new())
-                            .push(s.clone());
-                    }
-                }
-                None => map
-                    .entry("uncategorized".to_string())
-                    .or_insert(Vec::new())
-                    .push(s.clone()),
-            }
-        }
-    }
-
-    if let Some(f) = maybe_sort_fn.as_ref() {
-        let _ = map.values_mut().map(|v| {
-            v.sort_by(f.clone());
-        });
-    }
-
-    map
-}
-
-pub fn bln_parse_csv_aggregate_by_category(
-    _name: &str,
-    srcs: &[ParsedSource],
-    _maybe_sort_fn: Option<SortFn>,
-) -> AggregatedSources {
-    let mut map = HashMap::new();
-
-    for src in srcs {
-        let specifier = src.specifier();
-        let media_type = MediaType::JsonFeedEntry;
-
-        for feed in parse_csv(Some(src)) {
-            if feed.tags.is_empty() {
-                map.entry("uncategorized".to_string())
-                    .or_insert(Vec::new())
-                    .push(feed_item_to_parsed_source(&feed, specifier, &media_type));
-            } else {
-                for t in feed.tags.iter() {
-                    map.entry(t.name.clone())
-                        .or_insert(Vec::new())
-                        .push(feed_item_to_parsed_source(&feed, specifier, &media_type));
-                }
-            }
-        }
-    }
-
-    map
-}
-
-fn feed_item_to_parsed_source(
-    feed: &Feed,
-    specifier: &str,
-    media_type: &MediaType,
-) -> ParsedSource {
-    ParsedSourceBuilder::new(specifier.to_string(), media_type.to_owned())
-        .maybe_content(feed.to_json_string().ok())
-        .build()
-}
-
-pub fn extract_front_matter(source: &ParsedSource) -> (String, ParsedSource, tera::Context) {
+/// Adds the front matter, the terms of every taxonomy as `page_<taxonomy>`,
+/// the permalink and the pages linking to the source, as `backlinks`, to the
+/// context. The table of contents, the summary, the
+/// word count and the reading time of the page are in `page`.
+///
+/// Also returns the path the page is written to, given by its permalink.
+pub fn extract_front_matter(
+    source: &ParsedSource,
+    link_graph: &LinkGraph,
+) -> (String, ParsedSource, tera::Context) {
     let mut context = tera::Context::new();
     if let Some(front_matter) = source.front_matter() {
         for x in front_matter.get_fields().into_iter() {
             if let (k, Some(val)) = x {
-                let key = format!("page_{k}");
-                match k {
-                    "tags" => {
-                        if let Some(tags) = val.downcast_ref::<Vec<String>>() {
-                            context.insert(
-                                key,
-                                &tags.iter().map(|t| Tag::new(t)).collect::<Vec<Tag>>(),
-                            );
-                        }
-                    }
-                    _ => context.insert(key, &val.downcast_ref::<String>()),
+                if k != "tags" {
+                    context.insert(format!("page_{k}"), &val.downcast_ref::<String>());
                 }
             }
         }
@@ -216,30 +81,65 @@ pub fn extract_front_matter(source: &ParsedSource) -> (String, ParsedSource, ter
         }
     }
 
-    let path = resolve_path(source.specifier()).expect("Path is invalid!");
-    (
-        path.to_file_path()
-            .expect("Path is invalid!")
-            .file_stem()
-            .expect("Not a file!")
-            .to_string_lossy()
-            .to_string(),
-        source.to_owned(),
-        context,
-    )
+    for (taxonomy, terms) in source.terms_by_taxonomy() {
+        context.insert(format!("page_{taxonomy}"), terms);
+    }
+
+    context.insert(
+        "page",
+        &serde_json::json!({
+            "toc": source.toc(),
+            "summary": source.summary_html(),
+            "word_count": source.word_count(),
+            "reading_time": source.reading_time(),
+        }),
+    );
+
+    if let Some(permalink) = source.permalink() {
+        context.insert("permalink", permalink);
+        context.insert("backlinks", &link_graph.backlinks(permalink));
+    }
+
+    let output = match source.permalink() {
+        Some(permalink) => output_path(permalink),
+        None => {
+            let path = resolve_path(source.specifier()).expect("Path is invalid!");
+            let path = path.to_file_path().expect("Path is invalid!");
+            format!(
+                "{}.html",
+                path.file_stem().expect("Not a file!").to_string_lossy()
+            )
+        }
+    };
+    (output, source.to_owned(), context)
 }
 
-pub fn parse_feed(sources: &Vec<ParsedSource>) -> tera::Context {
+/// Adds the entries of the feed at `data/feed.csv` to the context as `feed`.
+pub fn inject_feed(ps: &ProcState) -> Result<tera::Context, Error> {
     let mut context = tera::Context::new();
-    let mut feed = Vec::new();
-    for source in sources {
-        feed.append(&mut parse_csv(Some(&source)));
-    }
-    context.insert("feed", &feed);
-    context
+    context.insert("feed", ps.feed()?.as_ref());
+
+    Ok(context)
+}
+
+/// Loads the entries of the feed at `data/feed.csv`, if there is one.
+pub fn load_feed(ps: &ProcState) -> Result<Vec<Feed>, Error> {
+    let files_provider = InputLoader {
+        name: "feed",
+        base_path: &ps.dir.root_file_path(),
+        inputs: &Input::Pattern("data/feed.csv").into(),
+        parser: &ps.parsed_source_cache.as_capturing_parser(),
+    };
+    Ok(files_provider
+        .load_input()?
+        .remove("feed")
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|source| parse_csv(Some(source), &ps.taxonomies))
+        .collect())
 }
 
-pub fn parse_csv(maybe_source: Option<&ParsedSource>) -> Vec<Feed> {
+pub fn parse_csv(maybe_source: Option<&ParsedSource>, taxonomies: &Taxonomies) -> Vec<Feed> {
     let mut feed: Vec<Feed> = Vec::new();
     if let Some(source) = maybe_source {
         if source.media_type() == MediaType::Csv {
@@ -251,7 +151,7 @@ pub fn parse_csv(maybe_source: Option<&ParsedSource>) -> Vec<Feed> {
 
             for result in rdr.deserialize::<Record>() {
                 if let Ok(record) = result {
-                    feed.push(record.into());
+                    feed.push(Feed::from_record(record, taxonomies));
                 }
             }
         }
@@ -260,51 +160,35 @@ pub fn parse_csv(maybe_source: Option<&ParsedSource>) -> Vec<Feed> {
     feed
 }
 
-pub fn inject_photo_data() -> tera::Context {
-    let mut context = tera::Context::new();
-    context.insert("photos", &vec![Picture {
-                title: "Gazelli Art House at Art Dubai 2023: Persian Dreams",
-                src: "https://d7hftxdivxxvm.cloudfront.net?height=490&amp;quality=80&amp;resize_to=fill&amp;src=https%3A%2F%2Fd32dm0rphc51dk.cloudfront.net%2FM12Gc-3Et8RdEa1E8MFIXQ%2Fnormalized.jpg&amp;width=490",
-                srcset: "https://d7hftxdivxxvm.cloudfront.net?height=490&amp;quality=80&amp;resize_to=fill&amp;src=https%3A%2F%2Fd32dm0rphc51dk.cloudfront.net%2FM12Gc-3Et8RAdEa1E8MFIXQ%2Fnormalized.jpg&amp;width=490 1x, https://d7hftxdivxxvm.cloudfront.net?height=980&amp;quality=80&amp;resize_to=fill&amp;src=https%3A%2F%2Fd32dm0rphc51dk.cloudfront.net%2FM12Gc-3Et8RdEa1E8MFIXQ%2Fnormalized.jpg&amp;width=980 2x",
-                target: "https://news.artnet.com/art-world/fake-instagram-photography-ai-generated-joe-avery-2260674",
-            }]);
-
-    context
-}
-
-pub fn collect_by_tag(srcs: &AggregatedSources) -> Vec<(String, tera::Context)> {
-    srcs.iter()
-        .map(|srcs| {
-            let mut articles: Vec<ParsedSource> = Vec::new();
-            let mut feed: Vec<ParsedSource> = Vec::new();
-            for s in srcs.1 {
-                match s.media_type() {
-                    MediaType::Html => {
-                        articles.push(s.to_owned());
-                    }
-                    MediaType::JsonFeedEntry => {
-                        feed.push(s.to_owned());
-                    }
-                    _ => {}
-                };
-            }
-
-            let mut context = tera::Context::new();
-            context.extend(collect_articles(&articles));
-            context.insert("tag_name", srcs.0);
-            context.insert("feed", &feed.to_feed_vec().expect("Cannot parse data!"));
-            (srcs.0.to_string(), context)
-        })
-        .collect()
-}
-
-pub fn collect_articles(srcs: &Vec<ParsedSource>) -> tera::Context {
+/// Collects the six first articles of `srcs`; sources that are not articles
+/// are skipped and reported as errors at the start of their front matter.
+pub fn collect_articles(ps: &ProcState, srcs: &[ParsedSource]) -> tera::Context {
     let mut context = tera::Context::new();
 
     let articles = srcs
         .iter()
-        .map(|src| {
-            Article::from_parsed_source(src.to_owned()).expect("Could not parse parsed_source!")
+        .filter_map(|src| match Article::from_parsed_source(src.to_owned()) {
+            Ok(article) => Some(article),
+            Err(e) => {
+                if let Ok(specifier) = ModuleSpecifier::parse(src.specifier()) {
+                    let snippet = specifier
+                        .to_file_path()
+                        .ok()
+                        .and_then(|path| std::fs::read_to_string(path).ok())
+                        .and_then(|source| source.lines().next().map(str::to_string))
+                        .unwrap_or_default();
+                    ps.diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        specifier,
+                        message: format!("skipping article: {e}"),
+                        line: 1,
+                        column: 1,
+                        length: snippet.chars().count(),
+                        snippet,
+                    });
+                }
+                None
+            }
         })
         .take(6)
         .collect::<Vec<Article>>();
@@ -314,61 +198,26 @@ pub fn collect_articles(srcs: &Vec<ParsedSource>) -> tera::Context {
     context
 }
 
-pub trait ToFeed: Sized {
-    fn to_feed_vec(&self) -> Result<Vec<Feed>, Error>;
-}
-
 pub trait FromParsedSource<T>: Sized {
     fn from_parsed_source(parsed_source: ParsedSource) -> Result<T, Error>;
 }
 
-impl ToFeed for Vec<ParsedSource> {
-    fn to_feed_vec(&self) -> Result<Vec<Feed>, Error> {
-        Ok(self.iter().map(Feed::from).collect::<Vec<Feed>>())
-    }
-}
-
 impl FromParsedSource<Article> for Article {
     fn from_parsed_source(parsed_source: ParsedSource) -> Result<Article, Error> {
-        if let Some(front_matter) = parsed_source.front_matter() {
-            let err_msg = |f: &str| format!("Field {} is not set!", f);
-
-            let FrontMatter {
-                author,
-                tags,
-                title,
-                description,
-                published,
-                ..
-            } = front_matter;
-
-            let mut parsed_tags: Vec<Tag> = Vec::new();
-            if let Some(tags) = tags.as_ref() {
-                for tag in tags {
-                    parsed_tags.push(Tag::new(tag.clone()));
-                }
-            }
-
-            let author = author
-                .as_ref()
-                .map(|v| v.join(", "))
-                .expect(&err_msg("author"));
-            let title = title.as_ref().expect(&err_msg("title")).clone();
-            let description = description.as_ref().expect(&err_msg("description")).clone();
-            let description =
-                markdown::string_to_html(&description, &markdown::MarkdownOptions::default());
-            let date = published.as_ref().expect(&err_msg("date")).clone();
-            let target = format!("/notes/{}.html", slugify!(&title));
-            return Ok(Article {
-                title,
-                description,
-                author,
-                date,
-                tags: parsed_tags,
-                target,
-            });
+        let front_matter = parsed_source
+            .front_matter()
+            .ok_or_else(|| generic_error("front matter is not set"))?;
+        let err_msg = |f: &str| generic_error(format!("front matter field `{f}` is not set"));
+        if front_matter.title.is_none() {
+            return Err(err_msg("title"));
+        }
+        if front_matter.published.is_none() {
+            return Err(err_msg("date"));
+        }
+        if parsed_source.permalink().is_none() {
+            return Err(generic_error("the page is not part of the content index"));
         }
 
-        return Err(generic_error("front matter is not set!"));
+        Ok(Article::new(&parsed_source))
     }
 }
@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use berlin_core::{LinkGraph, ModuleSpecifier, ParsedSource};
+use libs::anyhow::Error;
+use libs::chrono::{DateTime, Utc};
+use libs::serde_json;
+use serde::Serialize;
+
+use crate::proc_state::ProcState;
+
+use super::{Input, InputLoader, Task, Watch, WatchableTask};
+
+#[derive(Serialize)]
+struct Graph<'a> {
+    nodes: Vec<Node<'a>>,
+    edges: Vec<Edge<'a>>,
+}
+
+#[derive(Serialize)]
+struct Node<'a> {
+    id: &'a str,
+    title: &'a str,
+    url: &'a str,
+    tags: &'a [String],
+    /// The publishing date from the front matter.
+    date: Option<&'a str>,
+    /// The modification time of the file.
+    updated: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Edge<'a> {
+    /// `source` links to `target`.
+    Link { source: &'a str, target: &'a str },
+    /// `source` and `target` share `tags`.
+    Tag {
+        source: &'a str,
+        target: &'a str,
+        tags: Vec<&'a str>,
+        weight: usize,
+    },
+}
+
+fn to_node(source: &ParsedSource) -> Option<Node<'_>> {
+    let permalink = source.permalink()?;
+    let front_matter = source.front_matter();
+    Some(Node {
+        id: permalink,
+        title: front_matter
+            .and_then(|fm| fm.title.as_deref())
+            .unwrap_or(permalink),
+        url: permalink,
+        tags: front_matter
+            .and_then(|fm| fm.tags.as_deref())
+            .unwrap_or_default(),
+        date: front_matter.and_then(|fm| fm.published.as_deref()),
+        updated: source
+            .metadata()
+            .and_then(|m| m.modified().ok())
+            .map(|t| DateTime::<Utc>::from(t).to_rfc3339()),
+    })
+}
+
+/// Returns an edge for every pair of nodes sharing at least one tag.
+fn tag_edges<'a>(nodes: &[Node<'a>]) -> Vec<Edge<'a>> {
+    let mut edges = Vec::new();
+    for (i, a) in nodes.iter().enumerate() {
+        let tags_a = a.tags.iter().map(|t| t.as_str()).collect::<BTreeSet<_>>();
+        for b in nodes.iter().skip(i + 1) {
+            let tags = b
+                .tags
+                .iter()
+                .map(|t| t.as_str())
+                .filter(|t| tags_a.contains(t))
+                .collect::<BTreeSet<_>>();
+            if !tags.is_empty() {
+                edges.push(Edge::Tag {
+                    source: a.id,
+                    target: b.id,
+                    weight: tags.len(),
+                    tags: tags.into_iter().collect(),
+                });
+            }
+        }
+    }
+    edges
+}
+
+/// Writes the notes as nodes and their links and shared tags as edges, to be
+/// rendered as an interactive graph.
+pub struct NoteGraph {
+    pub input_pattern: String,
+    pub output: String,
+}
+
+impl NoteGraph {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let files_provider = InputLoader {
+            name: "graph",
+            base_path: &ps.dir.root_file_path(),
+            inputs: &Input::Pattern(&self.input_pattern).into(),
+            parser: &ps.parsed_source_cache.as_capturing_parser(),
+        };
+        let input = files_provider.load_input()?;
+        let sources = input.get("graph").map(|v| v.as_slice()).unwrap_or_default();
+
+        let link_graph = LinkGraph::new(sources);
+        let nodes = sources.iter().filter_map(to_node).collect::<Vec<_>>();
+        let mut edges = link_graph
+            .edges()
+            .into_iter()
+            .map(|(source, target)| Edge::Link {
+                source: &source.target,
+                target: &target.target,
+            })
+            .collect::<Vec<_>>();
+        edges.extend(tag_edges(&nodes));
+
+        let graph = serde_json::to_string(&Graph { nodes, edges })?;
+        let output = ps.dir.target_file_path().join(&self.output);
+        std::fs::create_dir_all(output.parent().unwrap())?;
+        std::fs::write(output, graph)?;
+
+        Ok(0)
+    }
+}
+
+impl WatchableTask for NoteGraph {}
+
+impl Task for NoteGraph {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for NoteGraph {
+    fn on_change(&self, ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl fmt::Debug for NoteGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NoteGraph")
+            .field("input_pattern", &self.input_pattern)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use berlin_core::{FrontMatter, MediaType, ParsedSourceBuilder};
+    use libs::serde_yaml;
+
+    fn source(name: &str, front_matter: &str) -> ParsedSource {
+        ParsedSourceBuilder::new(format!("file:///notes/{name}.md"), MediaType::Html)
+            .maybe_front_matter(serde_yaml::from_str::<FrontMatter>(front_matter).ok())
+            .maybe_permalink(Some(format!("/notes/{name}.html")))
+            .build()
+    }
+
+    #[test]
+    fn test_to_node() {
+        let a = source("a", "title: A\ndate: 2023-04-03\ntags: [rust]");
+        let node = to_node(&a).unwrap();
+        assert_eq!(
+            serde_json::to_value(&node).unwrap(),
+            serde_json::json!({
+                "id": "/notes/a.html",
+                "title": "A",
+                "url": "/notes/a.html",
+                "tags": ["rust"],
+                "date": "2023-04-03",
+                "updated": null,
+            })
+        );
+
+        let untitled = source("b", "{}");
+        let node = to_node(&untitled).unwrap();
+        assert_eq!((node.title, node.tags.len()), ("/notes/b.html", 0));
+
+        let unindexed =
+            ParsedSourceBuilder::new("file:///c.md".to_string(), MediaType::Html).build();
+        assert!(to_node(&unindexed).is_none());
+    }
+
+    #[test]
+    fn test_tag_edges() {
+        let sources = [
+            source("a", "tags: [rust, web, rust]"),
+            source("b", "tags: [rust]"),
+            source("c", "title: C"),
+            source("d", "tags: [web, rust]"),
+        ];
+        let nodes = sources.iter().filter_map(to_node).collect::<Vec<_>>();
+
+        assert_eq!(
+            serde_json::to_value(tag_edges(&nodes)).unwrap(),
+            serde_json::json!([
+                {"kind": "tag", "source": "/notes/a.html", "target": "/notes/b.html", "tags": ["rust"], "weight": 1},
+                {"kind": "tag", "source": "/notes/a.html", "target": "/notes/d.html", "tags": ["rust", "web"], "weight": 2},
+                {"kind": "tag", "source": "/notes/b.html", "target": "/notes/d.html", "tags": ["rust"], "weight": 1},
+            ])
+        );
+    }
+}
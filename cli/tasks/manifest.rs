@@ -0,0 +1,46 @@
+use berlin_core::ModuleSpecifier;
+use libs::anyhow::Error;
+use libs::serde_json;
+use std::fmt;
+
+use crate::proc_state::ProcState;
+
+use super::{Task, Watch, WatchableTask};
+
+/// Writes the asset manifest collected by the tasks writing assets.
+pub struct Manifest {
+    pub output: String,
+}
+
+impl Manifest {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let manifest = serde_json::to_string_pretty(&*ps.asset_manifest.read())?;
+        let output = ps.dir.target_file_path().join(&self.output);
+        std::fs::create_dir_all(output.parent().unwrap())?;
+        std::fs::write(output, manifest)?;
+
+        Ok(0)
+    }
+}
+
+impl WatchableTask for Manifest {}
+
+impl Task for Manifest {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for Manifest {
+    fn on_change(&self, ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl fmt::Debug for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Manifest")
+            .field("output", &self.output)
+            .finish()
+    }
+}
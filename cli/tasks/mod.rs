@@ -5,20 +5,16 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use crate::tasks::functions::bln_input_aggregate_by_category;
-use crate::tasks::functions::bln_input_feed_aggregate_all;
-use crate::tasks::functions::bln_parse_csv_aggregate_by_category;
 use crate::tasks::functions::collect_articles;
 use crate::tasks::functions::extract_front_matter;
-use crate::tasks::functions::extract_tags;
-use crate::tasks::functions::extract_tags_from_feed;
-use crate::tasks::functions::inject_photo_data;
-use crate::tasks::functions::parse_feed;
+use crate::tasks::functions::inject_feed;
 use crate::tasks::render::render_builder::RenderBuilder;
 use crate::util::fs::load_files;
+use berlin_core::LinkGraph;
 use berlin_core::MediaType;
 use berlin_core::ModuleSpecifier;
 use berlin_core::ParsedSource;
+use errors::error::generic_error;
 use libs::anyhow::Context;
 use libs::anyhow::Error;
 use parser::CapturingParser;
@@ -28,17 +24,31 @@ use libs::tera;
 
 use crate::proc_state::ProcState;
 
+use self::collections::Collections;
 use self::copy_static::CopyStatic;
 use self::css::Css;
+use self::css::SyntaxTheme;
 use self::functions::bln_input_aggregate_all;
 use self::functions::bln_input_sort_by_date_published;
-use self::functions::collect_by_tag;
-
+use self::graph::NoteGraph;
+use self::manifest::Manifest;
+use self::photostream::inject_photo_data;
+use self::photostream::PhotoPages;
+use self::redirects::Redirects;
+use self::taxonomies::inject_terms;
+use self::taxonomies::Taxonomies;
+
+pub mod collections;
 pub mod copy_static;
 pub mod css;
 pub mod functions;
+pub mod graph;
+pub mod manifest;
 pub mod model;
+pub mod photostream;
+pub mod redirects;
 pub mod render;
+pub mod taxonomies;
 
 pub type AggregatedSources = HashMap<String, Vec<ParsedSource>>;
 
@@ -49,11 +59,18 @@ pub type InputAggregate<'a> =
 
 pub type Map<T, U> = dyn Fn(&T) -> U;
 
+pub type ContextFn = dyn Fn(&ProcState) -> Result<tera::Context, Error>;
+
 pub type ParsedSourcesMapperFn<T> = Map<Vec<ParsedSource>, T>;
 pub type ScopedParsedSourcesMapperFn<'a> = (&'a str, &'a ParsedSourcesMapperFn<Vec<tera::Value>>);
-pub type TemplateVarsAggregate<'a> = &'a ParsedSourcesMapperFn<tera::Context>;
-
-pub type ParsedSourceMapperFn = Map<ParsedSource, (String, ParsedSource, tera::Context)>;
+/// Maps the sources of a category to the context of its page; problems with
+/// a source are reported to the diagnostics of the `ProcState`.
+pub type TemplateVarsAggregate<'a> = &'a dyn Fn(&ProcState, &[ParsedSource]) -> tera::Context;
+
+/// Maps a source to the path of its page and its context, given the links
+/// between all sources of its scope.
+pub type ParsedSourceMapperFn =
+    dyn Fn(&ParsedSource, &LinkGraph) -> (String, ParsedSource, tera::Context);
 pub type ScopedParsedSourceMapperFn<'a> = (&'a str, &'a ParsedSourceMapperFn);
 
 pub enum Aggregate<'a> {
@@ -68,7 +85,7 @@ impl<'a> Debug for Aggregate<'a> {
             Aggregate::Category(key, _) => f
                 .debug_tuple("Category")
                 .field(key)
-                .field(&"Fn(&Vec<ParsedSource>) -> tera::Context")
+                .field(&"Fn(&ProcState, &[ParsedSource]) -> tera::Context")
                 .finish(),
             Aggregate::Categories(key, _) => f
                 .debug_tuple("Categories")
@@ -83,7 +100,6 @@ pub enum Aggregator<'a> {
     // Create a context per input source
     None(&'a [ScopedParsedSourceMapperFn<'a>]),
     Merge(&'a [Aggregate<'a>]),
-    Reduce(&'a Map<AggregatedSources, Vec<(String, tera::Context)>>),
 }
 
 impl<'a> Debug for Aggregator<'a> {
@@ -94,10 +110,6 @@ impl<'a> Debug for Aggregator<'a> {
                 .field(&"Fn(&Vec<ParsedSource>) -> Vec<tera::Value>")
                 .finish(),
             Aggregator::Merge(vec) => f.debug_tuple("Merge").field(vec).finish(),
-            Aggregator::Reduce(_) => f
-                .debug_tuple("Reduce")
-                .field(&"Map<AggregatedSources, Vec<(String, tera::Context)>>")
-                .finish(),
         }
     }
 }
@@ -115,7 +127,7 @@ impl<'a, 'b> Input<'a> {
     pub fn load(
         &self,
         name: &str,
-        base_path: &PathBuf,
+        base_path: &Path,
         parser: &'a CapturingParser<'a>,
     ) -> Result<AggregatedSources, Error> {
         match self {
@@ -170,7 +182,7 @@ impl<'a> From<Input<'a>> for Inputs<'a> {
 
 pub struct InputLoader<'a> {
     pub name: &'a str,
-    pub base_path: &'a PathBuf,
+    pub base_path: &'a Path,
     pub inputs: &'a Inputs<'a>,
     pub parser: &'a CapturingParser<'a>,
 }
@@ -219,47 +231,41 @@ impl DefaultTask {
         &self,
         consumer: &dyn Fn(&dyn WatchableTask) -> Result<i32, Error>,
     ) -> Result<i32, Error> {
+        // Assets are written first, so that pages can resolve them through
+        // the asset manifest.
         let tasks: &[&dyn WatchableTask] = &[
+            &Css {
+                input_pattern: "styles.css".into(),
+                output: "styles.css".into(),
+            },
+            &SyntaxTheme,
+            &CopyStatic {
+                output: "static/{file}".into(),
+            },
             &RenderBuilder::new("index", "index.tera", "index.html")
-                .input(&[
-                    Input::PatternWithAggregate(
-                        "content/notes/*.md",
-                        &bln_input_sort_by_date_published,
-                    ),
-                    Input::PatternWithAggregate("data/feed.csv", &bln_input_feed_aggregate_all),
-                ])
-                .template_vars(Aggregator::Merge(&[
-                    Aggregate::Category("index", &collect_articles),
-                    Aggregate::Category("feed", &parse_feed),
-                    Aggregate::Categories(
-                        "tags",
-                        &[("index", &extract_tags), ("feed", &extract_tags_from_feed)],
-                    ),
-                ]))
+                .input(&[Input::PatternWithAggregate(
+                    "content/notes/*.md",
+                    &bln_input_sort_by_date_published,
+                )])
+                .template_vars(Aggregator::Merge(&[Aggregate::Category(
+                    "index",
+                    &collect_articles,
+                )]))
+                .add_to_context(&inject_feed)
+                .add_to_context(&inject_terms)
                 .add_to_context(&inject_photo_data)
-                .add_to_context(&|| -> tera::Context {
+                .add_to_context(&|_| -> Result<tera::Context, Error> {
                     let mut context = tera::Context::new();
                     let vec: Vec<String> = vec![];
                     context.insert("slides", &vec);
-                    context
+                    Ok(context)
                 })
                 .build(),
-            &RenderBuilder::new("tags", "tags/base.tera", "tags/[slug].html")
-                .input(&[
-                    Input::PatternWithAggregate(
-                        "content/notes/*.md",
-                        &bln_input_aggregate_by_category,
-                    ),
-                    Input::PatternWithAggregate(
-                        "data/feed.csv",
-                        &bln_parse_csv_aggregate_by_category,
-                    ),
-                ])
-                .template_vars(Aggregator::Reduce(&collect_by_tag))
-                .build(),
-            &RenderBuilder::new("notes", "notes/[slug].tera", "notes/[slug].html")
+            &Taxonomies,
+            &RenderBuilder::new("notes", "notes/[slug].tera", "[permalink]")
                 .input(&[Input::Pattern("content/notes/*.md")])
                 .template_vars(Aggregator::None(&[("notes", &extract_front_matter)]))
+                .og_image("[slug].png")
                 .build(),
             &RenderBuilder::new("notes_index", "notes.tera", "notes.html")
                 .input(&[Input::PatternWithAggregate(
@@ -274,21 +280,27 @@ impl DefaultTask {
             &RenderBuilder::new("about", "about.tera", "about.html").build(),
             &RenderBuilder::new("garage", "garage.tera", "garage.html").build(),
             &RenderBuilder::new("feed", "feed.tera", "feed.html")
-                .input(&[Input::Pattern("data/feed.csv")])
-                .template_vars(Aggregator::Merge(&[Aggregate::Category(
-                    "feed",
-                    &parse_feed,
-                )]))
+                .add_to_context(&inject_feed)
                 .build(),
             &RenderBuilder::new("photostream", "photostream.tera", "photostream.html")
                 .add_to_context(&inject_photo_data)
                 .build(),
-            &Css {
-                input_pattern: "styles.css".into(),
-                output: "styles.css".into(),
+            &PhotoPages {
+                template: "photo.tera".into(),
+                output: "photos/[slug].html".into(),
             },
-            &CopyStatic {
-                output: "static/{file}".into(),
+            &Collections {
+                skip: vec!["notes".into()],
+            },
+            &NoteGraph {
+                input_pattern: "content/notes/*.md".into(),
+                output: "graph.json".into(),
+            },
+            &Redirects {
+                history: "permalinks.json".into(),
+            },
+            &Manifest {
+                output: "manifest.json".into(),
             },
         ];
 
@@ -311,12 +323,38 @@ impl fmt::Debug for DefaultTask {
 
 impl Watch for DefaultTask {
     fn on_change(&self, ps: &ProcState, specifier: &ModuleSpecifier) -> Result<i32, Error> {
-        self.execute(&|task| task.on_change(ps, specifier))
+        ps.start_build()?;
+        let res = self.execute(&|task| task.on_change(ps, specifier));
+        report_diagnostics(ps)?;
+        res
     }
 }
 
 impl Task for DefaultTask {
     fn run(&self, ps: &ProcState) -> Result<i32, Error> {
-        self.execute(&|task| task.run(ps))
+        ps.start_build()?;
+        let res = self.execute(&|task| task.run(ps));
+        report_diagnostics(ps)?;
+        res
+    }
+}
+
+/// Prints the diagnostics collected while running the tasks, file by file.
+///
+/// In strict mode, errors fail the build.
+fn report_diagnostics(ps: &ProcState) -> Result<(), Error> {
+    let mut diagnostics = ps.diagnostics.take();
+    diagnostics.sort_by(|a, b| (a.specifier.as_str(), a.line).cmp(&(b.specifier.as_str(), b.line)));
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{diagnostic}\n");
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    if errors > 0 && ps.options.strict() {
+        return Err(generic_error(format!(
+            "Build failed with {errors} error(s) in the content"
+        )));
     }
+
+    Ok(())
 }
@@ -1,69 +1,92 @@
-use errors::error::generic_error;
-use libs::anyhow::Error;
-
-use berlin_core::ParsedSource;
-use libs::serde_json;
+use berlin_core::{ParsedSource, Taxonomies, TaxonomyOptions, Term};
+use images::ResponsiveImage;
 use libs::url::Url;
 use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, PartialOrd, Ord, Clone)]
-pub struct Tag {
-    pub name: String,
-    pub target: String,
-}
-
-impl Tag {
-    pub fn new<S: Into<String>>(name: S) -> Self {
-        let n = name.into();
-        Self {
-            target: format!("/tags/{}.html", &n),
-            name: n,
-        }
-    }
-}
-
 #[derive(Hash, Eq, PartialEq, Debug, Serialize, Deserialize, PartialOrd, Ord, Clone)]
 pub struct Feed {
     pub title: String,
     pub date_added: String,
     pub url: String,
     pub host: String,
-    pub tags: Vec<Tag>,
+    /// The terms of the taxonomy of the `tags` front matter key, see
+    /// [`feed_taxonomy`].
+    pub tags: Vec<Term>,
 }
 
-impl Feed {
-    pub fn to_json_string(&self) -> Result<String, Error> {
-        serde_json::to_string(self).map_err(|e| {
-            generic_error(format!(
-                "Serializing feed item into JSON string failed: {}",
-                e.to_string()
-            ))
-        })
-    }
+#[derive(Serialize)]
+pub struct Article {
+    pub title: String,
+    /// The description of the front matter, or else the summary.
+    pub description: String,
+    pub summary: String,
+    pub word_count: usize,
+    /// In minutes.
+    pub reading_time: usize,
+    pub author: String,
+    /// Always set for the articles of the index and the taxonomies.
+    pub date: Option<String>,
+    pub target: String,
+    pub tags: Vec<Term>,
 }
 
-impl From<&ParsedSource> for Feed {
-    fn from(value: &ParsedSource) -> Self {
-        serde_json::from_str(value.data()).unwrap()
+impl Article {
+    /// The article of `source`, with the fields its front matter doesn't set
+    /// left empty.
+    pub fn new(source: &ParsedSource) -> Self {
+        let front_matter = source.front_matter();
+        // Converted by the parser with the markdown options of the page.
+        let summary = source.summary_html().unwrap_or_default().to_string();
+        Self {
+            title: front_matter
+                .and_then(|fm| fm.title.clone())
+                .unwrap_or_default(),
+            description: source
+                .description_html()
+                .map_or_else(|| summary.clone(), |d| d.to_string()),
+            summary,
+            word_count: source.word_count(),
+            reading_time: source.reading_time(),
+            author: front_matter
+                .and_then(|fm| fm.author.as_ref())
+                .map(|authors| authors.join(", "))
+                .unwrap_or_default(),
+            date: front_matter.and_then(|fm| fm.published.clone()),
+            target: source.permalink().unwrap_or_default().to_string(),
+            tags: source.terms("tags").to_vec(),
+        }
     }
 }
 
-#[derive(Serialize)]
-pub struct Article {
+#[derive(Serialize, Clone, Debug)]
+pub struct Photo {
+    pub slug: String,
     pub title: String,
-    pub description: String,
-    pub author: String,
-    pub date: String,
+    pub caption: Option<String>,
+    /// The thumbnail shown in the photostream.
+    pub src: String,
+    pub srcset: String,
+    /// The page the thumbnail links to.
     pub target: String,
-    pub tags: Vec<Tag>,
+    /// The responsive variants shown on the page of the photo.
+    pub image: ResponsiveImage,
+    pub exif: PhotoExif,
 }
 
-#[derive(Serialize)]
-pub struct Picture<'a> {
-    pub title: &'a str,
-    pub src: &'a str,
-    pub srcset: &'a str,
-    pub target: &'a str,
+/// The metadata of a photo read from its EXIF data.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PhotoExif {
+    /// The date the photo was taken, e.g. `2023-03-04T18:22:05`.
+    pub date: Option<String>,
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    pub location: Option<Location>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
 }
 
 #[derive(Deserialize)]
@@ -83,24 +106,31 @@ pub struct Record {
     pub date_added: String,
     #[serde(rename = "Manual Tags")]
     #[serde(deserialize_with = "deserialize_tags")]
-    tags: Vec<Tag>,
+    tags: Vec<String>,
 }
 
-fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<Tag>, D::Error>
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let mut tags = Vec::new();
     let buf = String::deserialize(deserializer)?;
-    for tag in buf.split("; ") {
-        tags.push(Tag::new(tag.to_string()));
-    }
+    Ok(buf
+        .split("; ")
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect())
+}
 
-    Ok(tags)
+/// Returns the taxonomy the tags of the feed entries belong to, the one
+/// reading the `tags` front matter key, if any.
+pub fn feed_taxonomy(taxonomies: &Taxonomies) -> Option<(&String, &TaxonomyOptions)> {
+    taxonomies
+        .iter()
+        .find(|(name, taxonomy)| taxonomy.key(name) == "tags")
 }
 
-impl From<Record> for Feed {
-    fn from(r: Record) -> Self {
+impl Feed {
+    pub fn from_record(r: Record, taxonomies: &Taxonomies) -> Self {
         let Record {
             title,
             date_added,
@@ -114,6 +144,11 @@ impl From<Record> for Feed {
             .expect("Host missing!")
             .to_string();
 
+        let tags = match feed_taxonomy(taxonomies) {
+            Some((name, taxonomy)) => taxonomy.terms(name, &tags),
+            None => Vec::new(),
+        };
+
         Self {
             title,
             date_added,
@@ -0,0 +1,468 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use berlin_core::ModuleSpecifier;
+use errors::error::generic_error;
+use libs::anyhow::Context;
+use libs::anyhow::Error;
+use libs::exif::{self, In, Tag};
+use libs::slugify::slugify;
+use libs::tera;
+use libs::toml;
+use serde::Deserialize;
+
+use crate::args::PhotostreamConfig;
+use crate::proc_state::ProcState;
+use crate::tasks::model::{Location, Photo, PhotoExif};
+use crate::tasks::render::{post_process, task::initialize_context};
+use crate::util::fs::load_files;
+
+use super::{Task, Watch, WatchableTask};
+
+/// The file listing the photos of the photostream, relative to the data directory.
+const PHOTOS_FILE: &str = "photos.toml";
+
+const PHOTO_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "tif", "tiff"];
+
+#[derive(Deserialize)]
+struct PhotosToml {
+    photos: Vec<PhotoEntry>,
+}
+
+/// A photo listed in `data/photos.toml`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhotoEntry {
+    /// The path of the photo, relative to the static directory.
+    file: String,
+    title: Option<String>,
+    caption: Option<String>,
+    /// Links the thumbnail to another page instead of the page of the photo.
+    target: Option<String>,
+}
+
+fn read_photo_entries(
+    ps: &ProcState,
+    config: &PhotostreamConfig,
+) -> Result<Vec<PhotoEntry>, Error> {
+    let photos_file = ps.dir.data_file_path().join(PHOTOS_FILE);
+    if photos_file.is_file() {
+        let text = std::fs::read_to_string(&photos_file)?;
+        let photos_toml: PhotosToml =
+            toml::from_str(&text).context(format!("Unable to parse {}", photos_file.display()))?;
+        return Ok(photos_toml.photos);
+    }
+
+    let static_path = ps.dir.static_file_path();
+    let directory = static_path.join(&config.directory);
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries = load_files(&directory, "*")
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| PHOTO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .filter_map(|path| {
+            let file = path.strip_prefix(&static_path).ok()?;
+            Some(PhotoEntry {
+                file: file.to_string_lossy().to_string(),
+                title: None,
+                caption: None,
+                target: None,
+            })
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Titles photos without a title after their file name, so `night-market.jpg`
+/// becomes `night market`.
+fn title_from_file(file: &str) -> String {
+    Path::new(file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace(['-', '_'], " "))
+        .unwrap_or_default()
+}
+
+fn ascii_value(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+fn read_date(exif: &exif::Exif) -> Option<String> {
+    let field = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY))?;
+    match &field.value {
+        exif::Value::Ascii(values) => {
+            let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+            Some(format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                date.year, date.month, date.day, date.hour, date.minute, date.second
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn read_camera(exif: &exif::Exif) -> Option<String> {
+    let maybe_make = ascii_value(exif, Tag::Make);
+    let model = ascii_value(exif, Tag::Model)?;
+    match maybe_make {
+        Some(make) if !model.starts_with(&make) => Some(format!("{make} {model}")),
+        _ => Some(model),
+    }
+}
+
+/// Converts a coordinate given as degrees, minutes and seconds into decimal degrees.
+fn read_coordinate(exif: &exif::Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Rational(values) if values.len() == 3 => {
+            values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0
+        }
+        _ => return None,
+    };
+    match ascii_value(exif, ref_tag) {
+        Some(r) if r == negative_ref => Some(-degrees),
+        _ => Some(degrees),
+    }
+}
+
+fn read_exif(path: &Path, include_gps: bool) -> PhotoExif {
+    let exif = match File::open(path).map_err(Error::from).and_then(|file| {
+        exif::Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .map_err(Error::from)
+    }) {
+        Ok(exif) => exif,
+        Err(_) => return PhotoExif::default(),
+    };
+
+    let location = if include_gps {
+        read_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S").and_then(|latitude| {
+            read_coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W").map(|longitude| {
+                Location {
+                    latitude,
+                    longitude,
+                }
+            })
+        })
+    } else {
+        None
+    };
+
+    PhotoExif {
+        date: read_date(&exif),
+        camera: read_camera(&exif),
+        lens: ascii_value(&exif, Tag::LensModel),
+        location,
+    }
+}
+
+/// Returns the files of the photos that must not be published as they are,
+/// relative to the static directory, because their EXIF data may contain
+/// the position they were taken at.
+pub fn private_photos(ps: &ProcState) -> Result<HashSet<String>, Error> {
+    let config = match ps.options.maybe_config_file() {
+        Some(config_file) => config_file.to_photostream_config()?,
+        None => PhotostreamConfig::default(),
+    };
+    if config.include_gps {
+        return Ok(HashSet::new());
+    }
+
+    Ok(read_photo_entries(ps, &config)?
+        .into_iter()
+        .map(|entry| entry.file.trim_start_matches('/').to_string())
+        .collect())
+}
+
+/// Loads the photos of the photostream, newest first.
+///
+/// Photos are read from `data/photos.toml` if it exists, otherwise every image
+/// in the configured directory is part of the photostream. Thumbnails and the
+/// variants shown on the page of a photo are generated by the image pipeline,
+/// which does not copy EXIF data into the processed images.
+///
+/// Use [`ProcState::photos`], which loads them once per build.
+pub fn load_photos(ps: &ProcState) -> Result<Vec<Photo>, Error> {
+    let config = match ps.options.maybe_config_file() {
+        Some(config_file) => config_file.to_photostream_config()?,
+        None => PhotostreamConfig::default(),
+    };
+    let image_processor = &ps.image_processor;
+
+    let mut photos = Vec::new();
+    for entry in read_photo_entries(ps, &config)? {
+        let file_path = ps.dir.static_file_path().join(&entry.file);
+        if !file_path.is_file() {
+            return Err(generic_error(format!(
+                "Photo not found: {}",
+                file_path.display()
+            )));
+        }
+
+        let title = entry.title.unwrap_or_else(|| title_from_file(&entry.file));
+        let slug = slugify!(&title);
+        let thumbnail =
+            image_processor.resize(&entry.file, Some(config.thumbnail_width), None, None)?;
+        let thumbnail_2x =
+            image_processor.resize(&entry.file, Some(config.thumbnail_width * 2), None, None)?;
+
+        let mut image = image_processor.responsive(&entry.file)?;
+        if !config.include_gps {
+            // Serve a re-encoded copy, so the position is not leaked through
+            // the EXIF data of the original.
+            image.url = image_processor.resize(&entry.file, None, None, None)?.url;
+        }
+
+        let photo = Photo {
+            target: String::new(),
+            slug,
+            title,
+            caption: entry.caption,
+            srcset: format!("{} 1x, {} 2x", thumbnail.url, thumbnail_2x.url),
+            src: thumbnail.url,
+            image,
+            exif: read_exif(&file_path, config.include_gps),
+        };
+        photos.push((photo, entry.target));
+    }
+
+    photos.sort_by(|(a, _), (b, _)| compare_photos(a, b));
+
+    Ok(assign_slugs(photos))
+}
+
+/// Orders photos newest first, the photos without a date last, then by title.
+fn compare_photos(a: &Photo, b: &Photo) -> Ordering {
+    b.exif
+        .date
+        .cmp(&a.exif.date)
+        .then_with(|| a.title.cmp(&b.title))
+}
+
+/// Numbers the slugs of photos with the same title in order, so that their
+/// pages do not overwrite each other, and links the photos without a target
+/// to their page.
+fn assign_slugs(photos: Vec<(Photo, Option<String>)>) -> Vec<Photo> {
+    let mut taken = HashSet::new();
+    photos
+        .into_iter()
+        .map(|(mut photo, maybe_target)| {
+            let mut slug = photo.slug.clone();
+            let mut n = 1;
+            while !taken.insert(slug.clone()) {
+                n += 1;
+                slug = format!("{}-{n}", photo.slug);
+            }
+            photo.target = maybe_target.unwrap_or_else(|| format!("/photos/{slug}.html"));
+            photo.slug = slug;
+            photo
+        })
+        .collect()
+}
+
+/// Renders a page for every photo of the photostream.
+pub struct PhotoPages {
+    pub template: String,
+    /// The output path, `[slug]` is replaced by the slug of the photo.
+    pub output: String,
+}
+
+impl PhotoPages {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let photos = ps.photos()?;
+        let context = initialize_context(ps.options.maybe_config_file_specifier())?;
+
+        let mut files = Vec::with_capacity(photos.len());
+        for (i, photo) in photos.iter().enumerate() {
+            let mut ctx = context.clone();
+            ctx.insert("photo", photo);
+            ctx.insert("previous", &i.checked_sub(1).and_then(|i| photos.get(i)));
+            ctx.insert("next", &photos.get(i + 1));
+            files.push((
+                ps.dir
+                    .target_file_path()
+                    .join(self.output.replace("[slug]", &photo.slug)),
+                ps.render_with_context(&self.template, &ctx),
+            ));
+        }
+
+        let files = post_process::inline_critical_css(ps, files)?;
+        for f in files {
+            std::fs::create_dir_all(f.0.parent().unwrap())?;
+            std::fs::write(&f.0, &f.1)?;
+        }
+
+        Ok(0)
+    }
+}
+
+impl WatchableTask for PhotoPages {}
+
+impl Task for PhotoPages {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for PhotoPages {
+    fn on_change(&self, ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl fmt::Debug for PhotoPages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PhotoPages")
+            .field("template", &self.template)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+/// Adds the photos of the photostream to the context as `photos`.
+pub fn inject_photo_data(ps: &ProcState) -> Result<tera::Context, Error> {
+    let mut context = tera::Context::new();
+    context.insert("photos", ps.photos()?.as_ref());
+
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use images::ResponsiveImage;
+    use libs::exif::experimental::Writer;
+    use libs::exif::{Field, Rational, Value};
+
+    fn photo(title: &str, date: Option<&str>) -> Photo {
+        Photo {
+            slug: slugify!(title),
+            title: title.to_string(),
+            caption: None,
+            src: String::new(),
+            srcset: String::new(),
+            target: String::new(),
+            image: ResponsiveImage {
+                url: String::new(),
+                width: 0,
+                height: 0,
+                sources: vec![],
+            },
+            exif: PhotoExif {
+                date: date.map(str::to_string),
+                ..PhotoExif::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_title_from_file() {
+        assert_eq!(
+            title_from_file("pics/photostream/night-market.jpg"),
+            "night market"
+        );
+        assert_eq!(title_from_file("old_town_2.JPG"), "old town 2");
+    }
+
+    #[test]
+    fn test_sort_and_assign_slugs() {
+        let mut photos = vec![
+            (photo("Harbour", None), None),
+            (photo("Harbour", Some("2023-01-02T10:00:00")), None),
+            (photo("Alps", Some("2023-05-01T08:00:00")), None),
+            (
+                photo("Harbour", Some("2022-12-24T18:00:00")),
+                Some("/x".into()),
+            ),
+        ];
+        photos.sort_by(|(a, _), (b, _)| compare_photos(a, b));
+        let photos = assign_slugs(photos);
+
+        assert_eq!(
+            photos
+                .iter()
+                .map(|p| (p.slug.as_str(), p.target.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("alps", "/photos/alps.html"),
+                ("harbour", "/photos/harbour.html"),
+                ("harbour-2", "/x"),
+                ("harbour-3", "/photos/harbour-3.html"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_exif() {
+        let ascii = |tag, value: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        };
+        let dms = |degrees, minutes, seconds| {
+            Value::Rational(vec![
+                Rational::from((degrees, 1)),
+                Rational::from((minutes, 1)),
+                Rational::from((seconds, 1)),
+            ])
+        };
+        let fields = [
+            ascii(Tag::Make, "FUJIFILM"),
+            ascii(Tag::Model, "X100V"),
+            ascii(Tag::DateTimeOriginal, "2023:03:04 18:22:05"),
+            ascii(Tag::GPSLatitudeRef, "N"),
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: dms(52, 30, 36),
+            },
+            ascii(Tag::GPSLongitudeRef, "W"),
+            Field {
+                tag: Tag::GPSLongitude,
+                ifd_num: In::PRIMARY,
+                value: dms(13, 15, 0),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let path = std::env::temp_dir().join(format!("berlin-exif-{}.tif", std::process::id()));
+        std::fs::write(&path, tiff.into_inner()).unwrap();
+
+        let exif = read_exif(&path, true);
+        let private_exif = read_exif(&path, false);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(exif.date.as_deref(), Some("2023-03-04T18:22:05"));
+        assert_eq!(exif.camera.as_deref(), Some("FUJIFILM X100V"));
+        assert_eq!(
+            exif.location,
+            Some(Location {
+                latitude: 52.51,
+                longitude: -13.25,
+            })
+        );
+        assert_eq!(private_exif.location, None);
+        assert_eq!(private_exif.camera, exif.camera);
+    }
+}
@@ -0,0 +1,288 @@
+use berlin_core::{output_path, redirect_path, ContentIndex, ModuleSpecifier};
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::serde_json;
+use libs::tera;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::args::RedirectsConfig;
+use crate::proc_state::ProcState;
+
+use super::{Task, Watch, WatchableTask};
+
+/// The permalinks of the previous build, kept in the cache directory to find
+/// the pages that moved.
+#[derive(Default, Serialize, Deserialize)]
+struct PermalinkHistory {
+    /// The permalinks by the path of their file below `content/`.
+    permalinks: BTreeMap<String, String>,
+    /// The old URLs of the pages that moved, with the path of their file.
+    moved: BTreeMap<String, String>,
+}
+
+impl PermalinkHistory {
+    /// Replaces the permalinks of the previous build by `permalinks` and
+    /// returns the redirects from the old URLs of the pages that moved to
+    /// their permalinks.
+    fn update(&mut self, permalinks: BTreeMap<String, String>) -> BTreeMap<String, String> {
+        for (path, permalink) in &permalinks {
+            if let Some(old) = self.permalinks.get(path).filter(|old| *old != permalink) {
+                self.moved.insert(old.clone(), path.clone());
+            }
+        }
+        // Deleted pages are not redirected, nor URLs taken by a page again.
+        let urls = permalinks.values().collect::<HashSet<_>>();
+        self.moved
+            .retain(|url, path| permalinks.contains_key(path) && !urls.contains(url));
+        let redirects = self
+            .moved
+            .iter()
+            .map(|(url, path)| (url.clone(), permalinks[path].clone()))
+            .collect();
+
+        self.permalinks = permalinks;
+        redirects
+    }
+}
+
+/// Returns the permalinks of the content index by the path of their file
+/// below `content/`.
+fn relative_permalinks(content_index: &ContentIndex) -> BTreeMap<String, String> {
+    content_index
+        .permalinks()
+        .filter_map(|(path, permalink)| {
+            Some((content_index.relative_path(path)?, permalink.to_string()))
+        })
+        .collect()
+}
+
+/// Writes a page redirecting to the new URL for every alias in the front
+/// matter, every redirect of the `[redirects]` section and every page whose
+/// permalink changed since the last build.
+pub struct Redirects {
+    /// The file the permalinks of the previous build are kept in, relative to
+    /// the cache directory.
+    pub history: String,
+}
+
+impl Redirects {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let config = match ps.options.maybe_config_file() {
+            Some(config_file) => config_file.to_redirects_config()?,
+            None => RedirectsConfig::default(),
+        };
+        let content_index = ps.content_index.read();
+
+        let mut redirects = BTreeMap::new();
+        if config.track_moves {
+            let history_path = ps.dir.cache_file_path().join(&self.history);
+            let mut history = std::fs::read_to_string(&history_path)
+                .ok()
+                .and_then(|json| serde_json::from_str::<PermalinkHistory>(&json).ok())
+                .unwrap_or_default();
+            redirects.extend(history.update(relative_permalinks(&content_index)));
+            std::fs::create_dir_all(history_path.parent().unwrap())?;
+            std::fs::write(history_path, serde_json::to_string_pretty(&history)?)?;
+        }
+        for (alias, permalink) in content_index.aliases() {
+            redirects.insert(alias.to_string(), permalink.to_string());
+        }
+        check_redirects(&content_index, &config.paths)?;
+        redirects.extend(config.paths.clone());
+
+        let target = ps.dir.target_file_path();
+        for (from, to) in &redirects {
+            let output = target.join(redirect_path(from));
+            std::fs::create_dir_all(output.parent().unwrap())?;
+            std::fs::write(output, redirect_page(to))?;
+        }
+        if config.netlify {
+            let lines = redirects
+                .iter()
+                .map(|(from, to)| format!("{from} {to} 301\n"))
+                .collect::<String>();
+            std::fs::write(target.join("_redirects"), lines)?;
+        }
+        if config.nginx {
+            let lines = redirects
+                .iter()
+                .map(|(from, to)| format!("{from} {to};\n"))
+                .collect::<String>();
+            std::fs::write(target.join("redirects.map"), lines)?;
+        }
+
+        Ok(0)
+    }
+}
+
+/// Fails if a redirect of the `[redirects]` section would replace a page or
+/// the redirect of an alias. URLs clash when they are written to the same
+/// file, e.g. `/notes/x` and `/notes/x/`.
+fn check_redirects(
+    content_index: &ContentIndex,
+    paths: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let page_outputs = content_index
+        .permalinks()
+        .map(|(_, permalink)| output_path(permalink))
+        .collect::<HashSet<_>>();
+    let alias_outputs = content_index
+        .aliases()
+        .map(|(alias, permalink)| (redirect_path(alias), (alias, permalink)))
+        .collect::<HashMap<_, _>>();
+    for from in paths.keys() {
+        let output = redirect_path(from);
+        if page_outputs.contains(&output) || content_index.is_permalink(from) {
+            return Err(generic_error(format!(
+                "the redirect from {from} would replace the page at that URL"
+            )));
+        }
+        if let Some((alias, permalink)) = alias_outputs.get(&output) {
+            return Err(generic_error(format!(
+                "the redirect from {from} clashes with the alias {alias} of {permalink}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn redirect_page(to: &str) -> String {
+    let to = tera::escape_html(to);
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Redirecting…</title>
+<meta name="robots" content="noindex">
+<link rel="canonical" href="{to}">
+<meta http-equiv="refresh" content="0; url={to}">
+</head>
+<body>
+<p>This page has moved to <a href="{to}">{to}</a>.</p>
+</body>
+</html>
+"#
+    )
+}
+
+impl WatchableTask for Redirects {}
+
+impl Task for Redirects {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for Redirects {
+    fn on_change(&self, ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl fmt::Debug for Redirects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Redirects")
+            .field("history", &self.history)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use berlin_core::PermalinkOptions;
+
+    #[test]
+    fn test_track_moves() {
+        let root = std::env::temp_dir().join(format!("bln-moves-{}", std::process::id()));
+        let path = root.join("notes/a.md");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let build = |title: &str| {
+            std::fs::write(&path, format!("---\ntitle: {title}\n---\n")).unwrap();
+            let options = PermalinkOptions::default();
+            relative_permalinks(
+                &ContentIndex::build(&root, std::slice::from_ref(&path), &options).unwrap(),
+            )
+        };
+        let mut history = PermalinkHistory::default();
+
+        assert!(history.update(build("First")).is_empty());
+        assert_eq!(
+            history.update(build("Second")),
+            BTreeMap::from([(
+                "/notes/first.html".to_string(),
+                "/notes/second.html".to_string()
+            )])
+        );
+        // the page taking its old URL again replaces the redirect
+        assert_eq!(
+            history.update(build("First")),
+            BTreeMap::from([(
+                "/notes/second.html".to_string(),
+                "/notes/first.html".to_string()
+            )])
+        );
+        assert!(history.update(BTreeMap::new()).is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Indexes the pages `(path, front matter)` of a temporary site `name`.
+    fn index(
+        name: &str,
+        pages: &[(&str, &str)],
+        options: &PermalinkOptions,
+    ) -> Result<ContentIndex, Error> {
+        let root = std::env::temp_dir().join(format!("bln-{name}-{}", std::process::id()));
+        let mut paths = Vec::new();
+        for (path, front_matter) in pages {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, format!("---\n{front_matter}---\n")).unwrap();
+            paths.push(path);
+        }
+        let content_index = ContentIndex::build(&root, &paths, options);
+        std::fs::remove_dir_all(root).unwrap();
+        content_index
+    }
+
+    #[test]
+    fn test_reject_aliases_leaving_the_site() {
+        let options = PermalinkOptions::default();
+        let pages = [("notes/a.md", "title: A\naliases: [../../x]\n")];
+
+        assert!(index("escaping-alias", &pages, &options).is_err());
+    }
+
+    #[test]
+    fn test_reject_clashing_redirects() {
+        let pretty = PermalinkOptions {
+            pretty_urls: true,
+            ..PermalinkOptions::default()
+        };
+        // the alias and the page are both written to `notes/x/index.html`
+        let pages = [
+            ("notes/x.md", "title: X\n"),
+            ("notes/y.md", "title: Y\naliases: [/notes/x]\n"),
+        ];
+        assert!(index("alias-page", &pages, &pretty).is_err());
+        let pages = [
+            ("notes/a.md", "title: A\naliases: [/old/a]\n"),
+            ("notes/b.md", "title: B\naliases: [/old/a/]\n"),
+        ];
+        assert!(index("alias-alias", &pages, &pretty).is_err());
+
+        let pages = [
+            ("notes/x.md", "title: X\n"),
+            ("notes/y.md", "title: Y\naliases: [/old/y.html]\n"),
+        ];
+        let content_index = index("config", &pages, &pretty).unwrap();
+        let redirect = |from: &str| BTreeMap::from([(from.to_string(), "/".to_string())]);
+        assert!(check_redirects(&content_index, &redirect("/elsewhere")).is_ok());
+        assert!(check_redirects(&content_index, &redirect("/notes/x")).is_err());
+        assert!(check_redirects(&content_index, &redirect("/old/y.html")).is_err());
+    }
+}
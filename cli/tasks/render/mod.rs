@@ -1,2 +1,4 @@
+mod og_image;
+pub(crate) mod post_process;
 pub mod render_builder;
 pub mod task;
@@ -0,0 +1,162 @@
+use berlin_core::ParsedSource;
+use images::SvgRasterizer;
+use libs::anyhow::Error;
+use libs::sha2::{Digest, Sha256};
+use libs::tera;
+
+use crate::args::{OgImageConfig, SiteConfig};
+use crate::proc_state::ProcState;
+
+/// Used when the site does not provide its own template.
+const DEFAULT_TEMPLATE: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="1200" height="630" viewBox="0 0 1200 630">
+  <rect width="1200" height="630" fill="#0f172a"/>
+  <rect x="0" y="0" width="16" height="630" fill="#38bdf8"/>
+  <text x="80" y="110" font-family="sans-serif" font-size="32" fill="#94a3b8">{{ site_name | escape_xml }}</text>
+  {% for line in title_lines %}<text x="80" y="{{ 230 + loop.index0 * 76 }}" font-family="sans-serif" font-size="64" font-weight="bold" fill="#f8fafc">{{ line | escape_xml }}</text>
+  {% endfor %}{% if date %}<text x="80" y="540" font-family="sans-serif" font-size="30" fill="#cbd5e1">{{ date | escape_xml }}</text>{% endif %}
+  <text x="1120" y="540" text-anchor="end" font-family="sans-serif" font-size="30" fill="#38bdf8">{% for tag in tags %}#{{ tag | escape_xml }} {% endfor %}</text>
+</svg>
+"##;
+
+/// The number of characters after which titles are wrapped.
+const TITLE_LINE_LENGTH: usize = 28;
+
+/// Wraps `text` into lines of at most `width` characters, breaking at spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + word.chars().count() < width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+/// Checks whether the page already links a hand-made image that exists.
+fn has_custom_image(ps: &ProcState, context: &tera::Context) -> bool {
+    match context.get("og_image_path").and_then(|v| v.as_str()) {
+        Some(path) if !path.is_empty() => ps
+            .dir
+            .root_file_path()
+            .join(path.trim_start_matches('/'))
+            .is_file(),
+        _ => false,
+    }
+}
+
+/// Generates a social preview image for every page without a hand-made one
+/// and points `og_image_path` of the page at it. The images are kept in
+/// `.cache/og_images` and reused while their content does not change.
+///
+/// `output` is the path of the images relative to the target directory, with
+/// `[slug]` replaced by the path of the page without `.html`, e.g.
+/// `notes/rust` for `notes/rust.html` and `notes/rust/index.html`.
+pub(crate) fn add_og_images(
+    ps: &ProcState,
+    output: &str,
+    pages: &mut [(String, ParsedSource, tera::Context)],
+) -> Result<(), Error> {
+    let (og_image_config, site_config) = match ps.options.maybe_config_file() {
+        Some(config_file) => (
+            config_file.to_og_image_config()?,
+            config_file.to_site_config()?,
+        ),
+        None => (OgImageConfig::default(), SiteConfig::empty()),
+    };
+
+    if !og_image_config.generate {
+        return Ok(());
+    }
+
+    let font_dirs = og_image_config
+        .fonts
+        .iter()
+        .map(|dir| ps.dir.root_file_path().join(dir))
+        .collect::<Vec<_>>();
+    let mut maybe_rasterizer = None;
+    let has_template = ps.hera.lock().has_template(&og_image_config.template);
+    let template_source = match has_template {
+        true => {
+            std::fs::read_to_string(ps.dir.templates_file_path().join(&og_image_config.template))?
+        }
+        false => DEFAULT_TEMPLATE.to_string(),
+    };
+    let cache_path = ps.dir.cache_file_path().join("og_images");
+
+    for (path, parsed_source, context) in pages.iter_mut() {
+        if has_custom_image(ps, context) {
+            continue;
+        }
+
+        let front_matter = parsed_source.front_matter();
+        let title = front_matter
+            .and_then(|fm| fm.title.clone())
+            .unwrap_or_default();
+        let mut svg_context = tera::Context::new();
+        svg_context.insert("site_name", &site_config.title.clone().unwrap_or_default());
+        svg_context.insert("title_lines", &wrap(&title, TITLE_LINE_LENGTH));
+        svg_context.insert("title", &title);
+        svg_context.insert("date", &front_matter.and_then(|fm| fm.published.clone()));
+        svg_context.insert(
+            "tags",
+            &front_matter
+                .and_then(|fm| fm.tags.clone())
+                .unwrap_or_default(),
+        );
+
+        // Images are only rendered again when their template or what it shows
+        // changes.
+        let mut hasher = Sha256::new();
+        hasher.update(&template_source);
+        hasher.update(svg_context.clone().into_json().to_string());
+        hasher.update(format!("{font_dirs:?}"));
+        let hash = hasher
+            .finalize()
+            .iter()
+            .take(8)
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let cached = cache_path.join(format!("{hash}.png"));
+        if !cached.exists() {
+            let svg = if has_template {
+                ps.render_with_context(&og_image_config.template, &svg_context)
+            } else {
+                tera::Tera::one_off(DEFAULT_TEMPLATE, &svg_context, false)?
+            };
+            let rasterizer = maybe_rasterizer.get_or_insert_with(|| SvgRasterizer::new(&font_dirs));
+            std::fs::create_dir_all(&cache_path)?;
+            std::fs::write(&cached, rasterizer.rasterize(&svg)?)?;
+        }
+
+        let slug = path
+            .strip_suffix("/index.html")
+            .or_else(|| path.strip_suffix(".html"))
+            .unwrap_or(path);
+        let image_path = output.replace("[slug]", slug);
+        let target = ps.dir.target_file_path().join(&image_path);
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        std::fs::copy(&cached, target)?;
+
+        context.insert("og_image_path", &format!("/{image_path}"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("Building a static site generator in Rust", 20),
+            vec!["Building a static", "site generator in", "Rust"]
+        );
+        assert!(wrap("", 20).is_empty());
+    }
+}
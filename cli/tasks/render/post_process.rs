@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use libs::anyhow::Error;
+
+use crate::proc_state::ProcState;
+use crate::tasks::{Input, InputLoader};
+
+/// Inlines the critical rules of the compiled stylesheet into every page.
+///
+/// Does nothing unless `critical` is enabled in the `[css]` section of the
+/// config file.
+pub(crate) fn inline_critical_css(
+    ps: &ProcState,
+    files: Vec<(PathBuf, String)>,
+) -> Result<Vec<(PathBuf, String)>, Error> {
+    let css_config = match ps.options.maybe_config_file() {
+        Some(config_file) => config_file.to_css_config()?,
+        None => return Ok(files),
+    };
+
+    if !css_config.critical {
+        return Ok(files);
+    }
+
+    let files_provider = InputLoader {
+        name: "css",
+        base_path: &ps.dir.css_file_path(),
+        inputs: &Input::Pattern(&css_config.stylesheet).into(),
+        parser: &ps.parsed_source_cache.as_capturing_parser(),
+    };
+    let input = files_provider.load_input()?;
+    let stylesheet = match input.get("css").and_then(|sources| sources.first()) {
+        Some(parsed_source) => parsed_source.data(),
+        None => return Ok(files),
+    };
+
+    let logical_path = format!("css/{}", css_config.stylesheet);
+    let stylesheet_href = match ps.asset_manifest.read().get(&logical_path) {
+        Some(entry) => entry.path.clone(),
+        None => logical_path,
+    };
+    let critical_css = css::CriticalCss::parse(stylesheet)?;
+    let mut processed = Vec::with_capacity(files.len());
+    for (path, html) in files {
+        let markup = css::above_the_fold(&html, &css_config.fold_marker);
+        let critical = critical_css.extract(markup)?;
+        let html = css::inline_critical_css(&html, &critical, &stylesheet_href);
+        processed.push((path, html));
+    }
+
+    Ok(processed)
+}
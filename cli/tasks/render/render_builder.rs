@@ -1,8 +1,11 @@
 use crate::tasks::render::task::Render;
 
 use crate::tasks::Aggregator;
+use crate::tasks::ContextFn;
 
+use crate::proc_state::ProcState;
 use crate::tasks::Input;
+use libs::anyhow::Error;
 use libs::tera;
 
 #[derive(Default)]
@@ -11,8 +14,9 @@ pub struct RenderBuilder {
     inputs: &'static [Input<'static>],
     template: &'static str,
     maybe_aggregator: Option<Aggregator<'static>>,
-    add_to_context: Vec<&'static dyn Fn() -> tera::Context>,
+    add_to_context: Vec<&'static ContextFn>,
     output: &'static str,
+    maybe_og_image: Option<&'static str>,
 }
 
 #[allow(dead_code)]
@@ -25,6 +29,7 @@ impl RenderBuilder {
             maybe_aggregator: None,
             add_to_context: Vec::new(),
             output,
+            maybe_og_image: None,
         }
     }
 
@@ -38,11 +43,21 @@ impl RenderBuilder {
         self
     }
 
-    pub fn add_to_context<I: Fn() -> tera::Context>(mut self, input: &'static I) -> Self {
+    pub fn add_to_context<I: Fn(&ProcState) -> Result<tera::Context, Error>>(
+        mut self,
+        input: &'static I,
+    ) -> Self {
         self.add_to_context.push(input);
         self
     }
 
+    /// Generates a social preview image for every page rendered per input
+    /// source, written to `output` with `[slug]` replaced by the page slug.
+    pub fn og_image(mut self, output: &'static str) -> Self {
+        self.maybe_og_image = Some(output);
+        self
+    }
+
     pub fn build(self) -> Render<'static> {
         Render {
             name: self.name,
@@ -51,6 +66,7 @@ impl RenderBuilder {
             maybe_aggregator: self.maybe_aggregator,
             add_to_context: self.add_to_context,
             output: self.output,
+            maybe_og_image: self.maybe_og_image,
         }
     }
 }
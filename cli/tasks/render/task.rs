@@ -1,7 +1,10 @@
+use crate::tasks::render::og_image;
+use crate::tasks::render::post_process;
 use crate::tasks::render::render_builder::RenderBuilder;
 use crate::tasks::Aggregate;
 use crate::tasks::AggregatedSources;
 use crate::tasks::Aggregator;
+use crate::tasks::ContextFn;
 use crate::tasks::Input;
 use crate::tasks::InputLoader;
 use crate::tasks::Inputs;
@@ -11,7 +14,7 @@ use crate::tasks::WatchableTask;
 use std::fmt;
 use std::{path::PathBuf, process::exit};
 
-use berlin_core::{resolve_path, ModuleSpecifier, ParsedSource};
+use berlin_core::{resolve_path, LinkGraph, ModuleSpecifier, ParsedSource};
 use libs::anyhow::Error;
 
 use crate::{args::ConfigFile, proc_state::ProcState};
@@ -25,22 +28,18 @@ mod render {
 
     pub(crate) type All<'a> = RenderStruct<'a, Vec<(String, ParsedSource, tera::Context)>>;
     pub(crate) type Single<'a> = RenderStruct<'a, tera::Context>;
-    pub(crate) type Category<'a> = RenderStruct<'a, Vec<(String, tera::Context)>>;
 }
 
 mod reducer {
-    use crate::tasks::{AggregatedSources, Aggregators, ScopedParsedSourceMapperFn};
-    use libs::tera;
+    use crate::tasks::{Aggregators, ScopedParsedSourceMapperFn};
 
     use super::RenderData;
 
     pub(crate) type SingleContext<'a> = RenderData<&'a Aggregators<'a>>;
     pub(crate) type PerScope<'a> = RenderData<&'a [ScopedParsedSourceMapperFn<'a>]>;
-    pub(crate) type ReducerFn<'a> =
-        RenderData<&'a dyn Fn(&AggregatedSources) -> Vec<(String, tera::Context)>>;
 }
 
-fn initialize_context(
+pub(crate) fn initialize_context(
     maybe_module_specifier: Option<ModuleSpecifier>,
 ) -> Result<tera::Context, Error> {
     let mut context = tera::Context::new();
@@ -89,25 +88,6 @@ pub(crate) struct RenderData<T> {
     pub(crate) parent_context: tera::Context,
 }
 
-fn do_slugify(source: &ParsedSource) -> String {
-    use libs::slugify::slugify;
-
-    let maybe_title = source
-        .front_matter()
-        .map(|fm| fm.title.as_ref().expect("Title not set in front_matter"));
-
-    let value = match maybe_title {
-        Some(title) => title.to_owned(),
-        None => PathBuf::from(source.specifier().to_owned())
-            .file_stem()
-            .map(|n| n.to_string_lossy())
-            .map(|n| n.to_string())
-            .unwrap(),
-    };
-
-    slugify!(&value)
-}
-
 impl<'a> From<reducer::PerScope<'a>> for Vec<(String, ParsedSource, tera::Context)> {
     fn from(value: reducer::PerScope) -> Self {
         let reducer::PerScope {
@@ -119,11 +99,12 @@ impl<'a> From<reducer::PerScope<'a>> for Vec<(String, ParsedSource, tera::Contex
         for (category, processor_fn) in data.into_iter() {
             let key: &str = category.as_ref();
             if let Some(sources) = aggregated_sources.get(key) {
+                let link_graph = LinkGraph::new(sources);
                 for src in sources {
-                    let (_, parsed_source, context) = processor_fn(src);
+                    let (path, parsed_source, context) = processor_fn(src, &link_graph);
                     let mut ctx = parent_context.clone();
                     ctx.extend(context);
-                    vec.push((do_slugify(&parsed_source), parsed_source, ctx));
+                    vec.push((path, parsed_source, ctx));
                 }
             }
         }
@@ -131,40 +112,20 @@ impl<'a> From<reducer::PerScope<'a>> for Vec<(String, ParsedSource, tera::Contex
     }
 }
 
-impl<'a> From<reducer::ReducerFn<'a>> for Vec<(String, tera::Context)> {
-    fn from(value: reducer::ReducerFn<'a>) -> Self {
-        let reducer::ReducerFn {
-            aggregated_sources,
-            parent_context,
-            data: reducer_fn,
-        } = value;
-
-        let to_pair = |v: &(String, tera::Context)| {
-            let mut ctx = parent_context.clone();
-            ctx.extend(v.1.to_owned());
-            (v.0.to_owned(), ctx)
-        };
-        reducer_fn(&aggregated_sources)
-            .iter()
-            .map(to_pair)
-            .collect::<Vec<(String, tera::Context)>>()
-    }
-}
-
-impl<'a> From<reducer::SingleContext<'a>> for tera::Context {
-    fn from(value: reducer::SingleContext<'a>) -> Self {
+impl<'a> reducer::SingleContext<'a> {
+    fn into_context(self, ps: &ProcState) -> tera::Context {
         let reducer::SingleContext {
             aggregated_sources,
             parent_context,
             data,
-        } = value;
+        } = self;
 
         let mut context = parent_context.clone();
         for processor in data.into_iter() {
             match processor {
                 Aggregate::Category(key, process) => {
                     if let Some(input) = aggregated_sources.get(&key.to_string()) {
-                        context.extend(process(input));
+                        context.extend(process(ps, input));
                     }
                 }
                 // Aggregate::Merge(new_key, processors) => {
@@ -214,30 +175,18 @@ impl<'a> RenderFn for render::Single<'a> {
     }
 }
 
-impl<'a> RenderFn for render::Category<'a> {
-    fn render(&self) -> Vec<(PathBuf, String)> {
-        let to_rendered_pair = |f: &(String, tera::Context)| {
-            let (slug, context) = f;
-            (
-                self.output.target_path(Some(("[slug]", slug))),
-                self.ps.render_with_context(&self.template_name, context),
-            )
-        };
-        self.data.iter().map(to_rendered_pair).collect()
-    }
-}
-
 impl<'a> RenderFn for render::All<'a> {
     fn render(&self) -> Vec<(PathBuf, String)> {
         let to_rendered_pair = |f: &(String, ParsedSource, tera::Context)| {
-            let (slug, parsed_source, context) = f;
+            let (path, parsed_source, context) = f;
+            let template_name = self
+                .ps
+                .page_template(parsed_source, self.template_name)
+                .expect("Templates are checked before rendering!");
             (
-                self.output.target_path(Some(("[slug]", slug))),
-                self.ps.render_parsed_source_with_context(
-                    &self.template_name,
-                    parsed_source,
-                    context,
-                ),
+                self.output.target_path(Some(("[permalink]", path))),
+                self.ps
+                    .render_parsed_source_with_context(&template_name, parsed_source, context),
             )
         };
         self.data.iter().map(to_rendered_pair).collect()
@@ -249,8 +198,9 @@ pub struct Render<'a> {
     pub inputs: Inputs<'a>,
     pub template: &'a str,
     pub maybe_aggregator: Option<Aggregator<'a>>,
-    pub add_to_context: Vec<&'a dyn Fn() -> tera::Context>,
+    pub add_to_context: Vec<&'a ContextFn>,
     pub output: &'a str,
+    pub maybe_og_image: Option<&'a str>,
 }
 
 impl<'a> Render<'a> {
@@ -264,15 +214,21 @@ impl<'a> Render<'a> {
         reducer: reducer::PerScope,
         output: OutputStruct,
         ps: &'a ProcState,
-    ) -> render::All {
+    ) -> Result<render::All<'a>, Error> {
         let template_name = &self.template;
-        let data = Vec::<(String, ParsedSource, tera::Context)>::from(reducer);
-        render::All {
+        let mut data = Vec::<(String, ParsedSource, tera::Context)>::from(reducer);
+        for (_, parsed_source, _) in data.iter() {
+            ps.page_template(parsed_source, template_name)?;
+        }
+        if let Some(og_image_output) = self.maybe_og_image {
+            og_image::add_og_images(ps, og_image_output, &mut data)?;
+        }
+        Ok(render::All {
             ps,
             template_name,
             output,
             data,
-        }
+        })
     }
 
     fn to_render_single(
@@ -280,9 +236,9 @@ impl<'a> Render<'a> {
         reducer: reducer::SingleContext,
         output: OutputStruct,
         ps: &'a ProcState,
-    ) -> render::Single {
+    ) -> render::Single<'a> {
         let template_name = &self.template;
-        let data = tera::Context::from(reducer);
+        let data = reducer.into_context(ps);
         render::Single {
             ps,
             template_name,
@@ -291,20 +247,27 @@ impl<'a> Render<'a> {
         }
     }
 
-    fn to_render_category(
-        &'a self,
-        reducer: reducer::ReducerFn,
-        output: OutputStruct,
-        ps: &'a ProcState,
-    ) -> render::Category {
-        let template_name = &self.template;
-        let data = Vec::<(String, tera::Context)>::from(reducer);
-        render::Category {
-            ps,
-            template_name,
-            output,
-            data,
+    /// Whether a page of this task is rendered with `template` instead of the
+    /// template of the task, see [`ProcState::page_template`].
+    fn uses_page_template(&self, ps: &ProcState, template: &str) -> Result<bool, Error> {
+        if self.inputs.is_empty() || !matches!(self.maybe_aggregator, Some(Aggregator::None(_))) {
+            return Ok(false);
         }
+        let files_provider = InputLoader {
+            name: self.name,
+            inputs: &self.inputs,
+            base_path: &ps.dir.root_file_path(),
+            parser: &ps.parsed_source_cache.as_capturing_parser(),
+        };
+        let uses_template = files_provider
+            .load_input()?
+            .values()
+            .flatten()
+            .any(|source| {
+                ps.page_template(source, self.template)
+                    .is_ok_and(|page_template| page_template == template)
+            });
+        Ok(uses_template)
     }
 
     fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
@@ -315,6 +278,7 @@ impl<'a> Render<'a> {
             maybe_aggregator,
             template: template_name,
             output,
+            ..
         } = self;
 
         let base_path = &ps.dir.root_file_path();
@@ -323,7 +287,7 @@ impl<'a> Render<'a> {
         let mut context = initialize_context(ps.options.maybe_config_file_specifier())?;
 
         for enricher in inject_to_context {
-            context.extend(enricher());
+            context.extend(enricher(ps)?);
         }
 
         let output = OutputStruct {
@@ -361,7 +325,7 @@ impl<'a> Render<'a> {
                                 parent_context: context,
                                 data: processors,
                             };
-                            self.to_render_all(reducer, output, ps).render()
+                            self.to_render_all(reducer, output, ps)?.render()
                         }
                         Aggregator::Merge(processors) => {
                             let reducer = reducer::SingleContext {
@@ -372,14 +336,6 @@ impl<'a> Render<'a> {
 
                             self.to_render_single(reducer, output, ps).render()
                         }
-                        Aggregator::Reduce(processor) => {
-                            let reducer = reducer::ReducerFn {
-                                aggregated_sources: input_aggregate,
-                                parent_context: context,
-                                data: processor,
-                            };
-                            self.to_render_category(reducer, output, ps).render()
-                        }
                     }
                 } else {
                     eprintln!("Input found, but it is not clear what to do with it!");
@@ -389,6 +345,8 @@ impl<'a> Render<'a> {
             }
         };
 
+        let files = post_process::inline_critical_css(ps, files)?;
+
         for f in files {
             std::fs::create_dir_all(&f.0.parent().unwrap())?;
             std::fs::write(&f.0, &f.1)?;
@@ -405,7 +363,10 @@ impl<'a> fmt::Debug for Render<'a> {
             .field("template", &self.template)
             .field("output", &self.output)
             .field("maybe_aggregator", &self.maybe_aggregator)
-            .field("inject_to_context", &"Vec<&Fn() -> tera::Context>")
+            .field(
+                "inject_to_context",
+                &"Vec<&Fn(&ProcState) -> tera::Context>",
+            )
             .finish()
     }
 }
@@ -451,7 +412,7 @@ impl<'a> Watch for Render<'a> {
             let re = libs::fnmatch_regex::glob_to_regex(&expr)?;
 
             ps.hera.lock().full_reload()?;
-            if re.is_match(&changed_file) {
+            if re.is_match(&changed_file) || self.uses_page_template(ps, changed_file)? {
                 let path = specifier.to_file_path().ok().expect("Invalid path");
                 ps.parsed_source_cache
                     .free(&resolve_path(&path.to_string_lossy())?);
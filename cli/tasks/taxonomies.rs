@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use berlin_core::{output_path, ModuleSpecifier, ParsedSource, Term};
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use libs::tera;
+use serde::Serialize;
+
+use crate::args::SiteConfig;
+use crate::proc_state::ProcState;
+use crate::tasks::functions::collect_articles;
+use crate::tasks::model::{feed_taxonomy, Feed};
+use crate::tasks::render::post_process;
+use crate::tasks::render::task::initialize_context;
+use crate::util::fs::load_files;
+
+use super::{Input, InputLoader, Task, Watch, WatchableTask};
+
+/// The pages and feed entries listed under a term.
+#[derive(Default)]
+struct TermPages {
+    sources: Vec<ParsedSource>,
+    feed: Vec<Feed>,
+}
+
+/// A term as listed on the page of its taxonomy.
+#[derive(Serialize)]
+struct TermCount<'a> {
+    #[serde(flatten)]
+    term: &'a Term,
+    count: usize,
+}
+
+/// Renders a page for every term of every taxonomy of `ps.taxonomies`, with
+/// the pages below `content/` and the entries of the feed listed under it,
+/// and the page listing the terms and the Atom feeds of the terms of the
+/// taxonomies that have them.
+///
+/// The term pages get the `taxonomy`, the `term`, its `articles` and its
+/// `feed`, the list page gets the `taxonomy` and its `terms`.
+pub struct Taxonomies;
+
+impl Taxonomies {
+    fn run_internal(&self, ps: &ProcState) -> Result<i32, Error> {
+        let sources = ps.pages()?;
+        let feed = ps.feed()?;
+        let parent_context = initialize_context(ps.options.maybe_config_file_specifier())?;
+        let site_url = match ps.options.maybe_config_file() {
+            Some(config_file) => config_file.to_site_config()?,
+            None => SiteConfig::empty(),
+        }
+        .url
+        .unwrap_or_default();
+
+        let target = ps.dir.target_file_path();
+        let mut files = Vec::new();
+        let mut feeds = Vec::new();
+        for (name, taxonomy) in &ps.taxonomies {
+            let terms = collect_terms(&ps.taxonomies, name, &sources, &feed);
+
+            let template = taxonomy
+                .template
+                .clone()
+                .unwrap_or_else(|| format!("{name}/base.tera"));
+            check_template(ps, name, &template)?;
+            for (term, pages) in &terms {
+                let mut context = parent_context.clone();
+                context.insert("taxonomy", name);
+                context.insert("term", term);
+                context.insert("tag_name", &term.name);
+                context.insert("feed_url", &taxonomy.feed_url(name, &term.name));
+                context.extend(collect_articles(ps, &pages.sources));
+                context.insert("feed", &pages.feed);
+                files.push((
+                    target.join(output_path(&term.target)),
+                    ps.render_with_context(&template, &context),
+                ));
+
+                if let Some(feed_url) = taxonomy.feed_url(name, &term.name) {
+                    let atom = atom_feed(&site_url, &feed_url, term, &pages.sources);
+                    feeds.push((target.join(output_path(&feed_url)), atom));
+                }
+            }
+
+            if let Some(list_template) = taxonomy.list_template.as_ref() {
+                check_template(ps, name, list_template)?;
+                let mut context = parent_context.clone();
+                context.insert("taxonomy", name);
+                context.insert(
+                    "terms",
+                    &terms
+                        .iter()
+                        .map(|(term, pages)| TermCount {
+                            term,
+                            count: pages.sources.len() + pages.feed.len(),
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                files.push((
+                    target.join(output_path(&format!("/{name}/"))),
+                    ps.render_with_context(list_template, &context),
+                ));
+            }
+        }
+
+        let mut files = post_process::inline_critical_css(ps, files)?;
+        files.append(&mut feeds);
+        for f in files {
+            std::fs::create_dir_all(f.0.parent().unwrap())?;
+            std::fs::write(&f.0, &f.1)?;
+        }
+
+        Ok(0)
+    }
+}
+
+/// Adds the terms of every taxonomy to the context, by the name of the
+/// taxonomy, e.g. `tags`.
+pub fn inject_terms(ps: &ProcState) -> Result<tera::Context, Error> {
+    let sources = ps.pages()?;
+    let feed = ps.feed()?;
+    let mut context = tera::Context::new();
+    for name in ps.taxonomies.keys() {
+        let terms = collect_terms(&ps.taxonomies, name, &sources, &feed);
+        context.insert(name, &terms.keys().collect::<Vec<_>>());
+    }
+
+    Ok(context)
+}
+
+/// Loads the markdown and org pages below `content/`, newest first.
+pub(crate) fn load_pages(ps: &ProcState) -> Result<Vec<ParsedSource>, Error> {
+    let content_path = ps.dir.content_file_path();
+    let mut paths = load_files(&content_path, "**/*.md");
+    paths.extend(load_files(&content_path, "**/*.org"));
+    paths.retain(|path| path.file_stem().is_some_and(|stem| stem != "_index"));
+    let files_provider = InputLoader {
+        name: "pages",
+        base_path: &content_path,
+        inputs: &Input::Files(&paths).into(),
+        parser: &ps.parsed_source_cache.as_capturing_parser(),
+    };
+    let mut sources = files_provider
+        .load_input()?
+        .remove("pages")
+        .unwrap_or_default();
+    sources.sort_by(|a, b| {
+        let date =
+            |source: &ParsedSource| source.front_matter().and_then(|fm| fm.published.clone());
+        date(b).cmp(&date(a))
+    });
+
+    Ok(sources)
+}
+
+/// Groups the pages and, for the taxonomy of the tags, the feed entries by
+/// the terms of the taxonomy `name`.
+fn collect_terms(
+    taxonomies: &berlin_core::Taxonomies,
+    name: &str,
+    sources: &[ParsedSource],
+    feed: &[Feed],
+) -> BTreeMap<Term, TermPages> {
+    let mut terms = BTreeMap::<Term, TermPages>::new();
+    for source in sources {
+        for term in source.terms(name) {
+            terms
+                .entry(term.clone())
+                .or_default()
+                .sources
+                .push(source.clone());
+        }
+    }
+    if feed_taxonomy(taxonomies).is_some_and(|(feed_name, _)| feed_name == name) {
+        for item in feed {
+            for term in &item.tags {
+                terms
+                    .entry(term.clone())
+                    .or_default()
+                    .feed
+                    .push(item.clone());
+            }
+        }
+    }
+
+    terms
+}
+
+fn check_template(ps: &ProcState, name: &str, template: &str) -> Result<(), Error> {
+    if !ps.hera.lock().has_template(template) {
+        return Err(generic_error(format!(
+            "taxonomy `{name}`: template `{template}` not found in {}",
+            ps.dir.templates_file_path().display()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the Atom feed of the pages listed under `term`, with the URLs
+/// made absolute with `site_url`.
+///
+/// The pages are updated at their front matter date, or else when their file
+/// was modified; pages with neither are left out.
+fn atom_feed(site_url: &str, feed_url: &str, term: &Term, sources: &[ParsedSource]) -> String {
+    let site_url = site_url.trim_end_matches('/');
+    let escape = |s: &str| tera::escape_html(s);
+
+    let mut entries = String::new();
+    let mut last_updated = None;
+    for source in sources {
+        let updated = match updated(source) {
+            Some(updated) => updated,
+            None => continue,
+        };
+        let front_matter = source.front_matter();
+        let url = format!("{site_url}{}", source.permalink().unwrap_or_default());
+        entries.push_str(&format!(
+            "<entry>\n<title>{}</title>\n<link href=\"{}\"/>\n<id>{}</id>\n<updated>{}</updated>\n<summary type=\"html\">{}</summary>\n</entry>\n",
+            escape(front_matter.and_then(|fm| fm.title.as_deref()).unwrap_or_default()),
+            escape(&url),
+            escape(&url),
+            updated,
+            escape(source.summary_html().unwrap_or_default()),
+        ));
+        last_updated = last_updated.max(Some(updated));
+    }
+    let updated = last_updated.unwrap_or_else(|| rfc3339(Utc::now()));
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n<title>{}</title>\n<link href=\"{}\"/>\n<link rel=\"self\" href=\"{}\"/>\n<id>{}</id>\n<updated>{updated}</updated>\n{entries}</feed>\n",
+        escape(&term.name),
+        escape(&format!("{site_url}{}", term.target)),
+        escape(&format!("{site_url}{feed_url}")),
+        escape(&format!("{site_url}{}", term.target)),
+    )
+}
+
+/// Returns when the page of `source` was last updated, in RFC 3339.
+fn updated(source: &ParsedSource) -> Option<String> {
+    source
+        .front_matter()
+        .and_then(|fm| fm.published.as_deref())
+        .and_then(atom_date)
+        .or_else(|| {
+            let path = ModuleSpecifier::parse(source.specifier())
+                .ok()?
+                .to_file_path()
+                .ok()?;
+            let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+            Some(rfc3339(DateTime::<Utc>::from(modified)))
+        })
+}
+
+/// Turns a front matter date like `2023-04-03` or `2023-04-03 10:00` into an
+/// RFC 3339 date, in UTC unless it has an offset.
+fn atom_date(date: &str) -> Option<String> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(rfc3339(date.with_timezone(&Utc)));
+    }
+    [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)
+    })
+    .map(|date| rfc3339(DateTime::<Utc>::from_utc(date, Utc)))
+}
+
+/// Formats `date` like `2023-04-03T10:00:00Z`, so dates compare as strings.
+fn rfc3339(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl WatchableTask for Taxonomies {}
+
+impl Task for Taxonomies {
+    fn run(&self, ps: &ProcState) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl Watch for Taxonomies {
+    fn on_change(&self, ps: &ProcState, _specifier: &ModuleSpecifier) -> Result<i32, Error> {
+        self.run_internal(ps)
+    }
+}
+
+impl fmt::Debug for Taxonomies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Taxonomies").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use berlin_core::{default_taxonomies, FrontMatter, MediaType, ParsedSourceBuilder};
+
+    fn page(name: &str, front_matter: &str) -> ParsedSource {
+        let taxonomies = default_taxonomies();
+        let front_matter: FrontMatter = libs::serde_yaml::from_str(front_matter).unwrap();
+        let tags = taxonomies["tags"].terms("tags", &front_matter.tags.clone().unwrap_or_default());
+        ParsedSourceBuilder::new(
+            format!("file:///site/content/notes/{name}.md"),
+            MediaType::Markdown,
+        )
+        .front_matter(front_matter)
+        .maybe_permalink(Some(format!("/notes/{name}.html")))
+        .terms(BTreeMap::from([("tags".to_string(), tags)]))
+        .build()
+    }
+
+    #[test]
+    fn test_collect_terms() {
+        let taxonomies = default_taxonomies();
+        let sources = [
+            page("a", "title: A\ntags: [rust, web]\n"),
+            page("b", "title: B\ntags: [rust]\n"),
+        ];
+        let feed = [Feed {
+            title: "Link".to_string(),
+            date_added: "2023-04-03".to_string(),
+            url: "https://example.com".to_string(),
+            host: "example.com".to_string(),
+            tags: vec![taxonomies["tags"].term("tags", "web")],
+        }];
+
+        let terms = collect_terms(&taxonomies, "tags", &sources, &feed);
+        let counts = terms
+            .iter()
+            .map(|(term, pages)| (term.target.as_str(), pages.sources.len(), pages.feed.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [("/tags/rust.html", 2, 0), ("/tags/web.html", 1, 1)]
+        );
+        assert!(collect_terms(&taxonomies, "series", &sources, &feed).is_empty());
+    }
+
+    #[test]
+    fn test_atom_feed() {
+        let term = default_taxonomies()["tags"].term("tags", "rust");
+        let sources = [
+            page("a", "title: A & B\ndate: 2023-04-03 10:00\n"),
+            page("b", "title: B\n"),
+            page("c", "title: C\ndate: 2023-05-01\n"),
+        ];
+
+        let atom = atom_feed("https://example.com/", "/tags/rust.xml", &term, &sources);
+
+        assert!(atom.contains("<title>A &amp; B</title>"));
+        assert!(atom.contains("<updated>2023-04-03T10:00:00Z</updated>"));
+        assert!(!atom.contains("<title>B</title>"));
+        assert!(!atom.contains("<updated></updated>"));
+        assert!(atom.contains("</id>\n<updated>2023-05-01T00:00:00Z</updated>\n<entry>"));
+        assert_eq!(
+            atom_date("2023-04-03T10:00:00+02:00").as_deref(),
+            Some("2023-04-03T08:00:00Z")
+        );
+        assert_eq!(atom_date("03.04.2023"), None);
+    }
+}
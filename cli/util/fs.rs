@@ -15,21 +15,21 @@ pub fn canonicalize_path(path: &Path) -> Result<PathBuf, Error> {
     return Ok(path);
 }
 
-pub fn load_files(cwd: &PathBuf, pattern: &str) -> Vec<PathBuf> {
+pub fn load_files(cwd: &Path, pattern: &str) -> Vec<PathBuf> {
     let pattern_path = cwd.join(pattern);
     let pattern_path_str = pattern_path.to_str().unwrap();
 
     glob(pattern_path_str).unwrap().flatten().collect()
 }
 
-pub fn consume_files<F>(path: PathBuf, pattern: &str, consumer: F)
+pub fn consume_files<F, R>(path: PathBuf, pattern: &str, consumer: F) -> R
 where
-    F: FnOnce(Vec<ModuleSpecifier>),
+    F: FnOnce(Vec<ModuleSpecifier>) -> R,
 {
     let mut specifiers = Vec::<ModuleSpecifier>::new();
     for f in load_files(&path, pattern) {
         let specifier = ModuleSpecifier::from_file_path(f).expect("Invalid path.");
         specifiers.push(specifier);
     }
-    consumer(specifiers);
+    consumer(specifiers)
 }
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::parking_lot::RwLock;
+use libs::serde_yaml::{Mapping, Value};
+use libs::slugify::slugify;
+
+use crate::permalink::normalize_url;
+use crate::{
+    normalize_path, output_path, parse_front_matter, redirect_path, FrontMatter, PermalinkOptions,
+};
+
+/// The content index of the current build, shared by the parser and the templates.
+pub type SharedContentIndex = Arc<RwLock<ContentIndex>>;
+
+/// Maps the content files of the site to the permalinks of their pages.
+///
+/// The permalinks are built with the [`PermalinkOptions`], by default
+/// `/{directory}/{slug}.html` where `directory` is the directory of the file
+/// relative to `content/` and `slug` is the slugified title of the file, or
+/// its file stem if it has no title.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContentIndex {
+    root: PathBuf,
+    permalinks: HashMap<PathBuf, String>,
+    urls: HashSet<String>,
+    /// The files by the old URLs in the `aliases` of their front matter.
+    aliases: HashMap<String, PathBuf>,
+    /// The files by their lowercased title and by their slugified file stem,
+    /// used to resolve wiki links.
+    titles: HashMap<String, PathBuf>,
+    stems: HashMap<String, PathBuf>,
+}
+
+impl ContentIndex {
+    /// Indexes `paths`, which are expected to be below the content directory
+    /// `root`, and fails if two of them have the same permalink or an alias
+    /// is taken.
+    pub fn build(
+        root: &Path,
+        paths: &[PathBuf],
+        options: &PermalinkOptions,
+    ) -> Result<Self, Error> {
+        let mut permalinks = HashMap::with_capacity(paths.len());
+        // The pages and the aliases by the file they are written to, as URLs
+        // like `/notes/x` and `/notes/x/` end up in the same file.
+        let mut pages = HashMap::<String, PathBuf>::with_capacity(paths.len());
+        let mut alias_outputs = HashMap::<String, String>::new();
+        let mut titles = HashMap::new();
+        let mut stems = HashMap::new();
+        let mut aliases = HashMap::<String, PathBuf>::new();
+        for path in paths {
+            let path = normalize_path(path);
+            let maybe_front_matter = read_front_matter_of_file(&path);
+            let permalink = options.permalink(root, &path, maybe_front_matter.as_ref())?;
+            if let Some(other) = pages.insert(output_path(&permalink), path.clone()) {
+                return Err(generic_error(format!(
+                    "{} and {} have the same permalink {permalink}",
+                    other.display(),
+                    path.display()
+                )));
+            }
+            for alias in maybe_front_matter
+                .as_ref()
+                .and_then(|fm| fm.aliases.as_ref())
+                .into_iter()
+                .flatten()
+            {
+                let alias = normalize_url(alias).map_err(|e| {
+                    generic_error(format!("{}: {e} in its `aliases`", path.display()))
+                })?;
+                let output = redirect_path(&alias);
+                if let Some(other) = alias_outputs.get(&output).map(|other| &aliases[other]) {
+                    return Err(generic_error(format!(
+                        "{} and {} have the same alias {alias}",
+                        other.display(),
+                        path.display()
+                    )));
+                }
+                alias_outputs.insert(output, alias.clone());
+                aliases.insert(alias, path.clone());
+            }
+            if let Some(title) = maybe_front_matter.and_then(|fm| fm.title) {
+                titles.insert(title.to_lowercase(), path.clone());
+            }
+            if let Some(stem) = path.file_stem() {
+                stems.insert(slugify!(&stem.to_string_lossy()), path.clone());
+            }
+            permalinks.insert(path, permalink);
+        }
+
+        let urls = permalinks
+            .iter()
+            .map(|(path, permalink)| (permalink.as_str(), path))
+            .collect::<HashMap<_, _>>();
+        let maybe_taken = alias_outputs.iter().find_map(|(output, alias)| {
+            let page = pages
+                .get(output)
+                .or_else(|| urls.get(alias.as_str()).copied())?;
+            Some((alias, page))
+        });
+        if let Some((alias, page)) = maybe_taken {
+            return Err(generic_error(format!(
+                "the alias {alias} of {} is the permalink of {}",
+                aliases[alias].display(),
+                page.display()
+            )));
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            urls: urls.into_keys().map(str::to_string).collect(),
+            permalinks,
+            aliases,
+            titles,
+            stems,
+        })
+    }
+
+    /// Returns the collection of the file at `path`, the first directory
+    /// below the content directory, e.g. `notes`.
+    pub fn collection(&self, path: &Path) -> Option<String> {
+        let path = normalize_path(path);
+        let relative = path.strip_prefix(&self.root).ok()?;
+        match relative.parent()?.components().next()? {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns the permalink of the page rendered from the file at `path`.
+    pub fn get(&self, path: &Path) -> Option<&str> {
+        self.permalinks
+            .get(&normalize_path(path))
+            .map(|p| p.as_str())
+    }
+
+    /// Returns the files with their permalinks.
+    pub fn permalinks(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.permalinks
+            .iter()
+            .map(|(path, permalink)| (path.as_path(), permalink.as_str()))
+    }
+
+    /// Returns the aliases of the pages with their permalinks.
+    pub fn aliases(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases
+            .iter()
+            .filter_map(|(alias, path)| Some((alias.as_str(), self.get(path)?)))
+    }
+
+    /// Returns the path of the file at `path` relative to the content
+    /// directory, e.g. `notes/rust.md`.
+    pub fn relative_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        Some(relative.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Returns the permalink of the page rendered from the file at `path`,
+    /// relative to the content directory, e.g. `notes/rust.md`.
+    pub fn get_relative(&self, path: &str) -> Option<&str> {
+        self.get(&self.root.join(path.trim_start_matches('/')))
+    }
+
+    /// Resolves a link to a content file as written in the file at `referrer`.
+    ///
+    /// Links starting with `/` are relative to the content directory, all
+    /// other links are relative to the directory of `referrer`. A fragment
+    /// is kept, so `other.md#usage` resolves to `/notes/other.html#usage`.
+    pub fn resolve(&self, referrer: &Path, link: &str) -> Option<String> {
+        let (path, maybe_fragment) = match link.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (link, None),
+        };
+        let permalink = match path.starts_with('/') {
+            true => self.get_relative(path)?,
+            false => self.get(&referrer.parent()?.join(path))?,
+        };
+        Some(match maybe_fragment {
+            Some(fragment) => format!("{permalink}#{fragment}"),
+            None => permalink.to_string(),
+        })
+    }
+
+    /// Resolves the target of a wiki link like `[[Note Title]]` or
+    /// `[[note#usage|label]]` as written in the file at `referrer`.
+    ///
+    /// The target is looked up as a title first, then as a file stem anywhere
+    /// in the content directory and finally as a path relative to `referrer`.
+    /// Titles are compared case-insensitively and file stems by their slug, so
+    /// `[[Digital Garden]]` finds `digital-garden.md`.
+    pub fn resolve_wiki_link(&self, referrer: &Path, target: &str) -> Option<String> {
+        let (name, maybe_fragment) = match target.split_once('#') {
+            Some((name, fragment)) => (name.trim(), Some(fragment.trim())),
+            None => (target.trim(), None),
+        };
+        let permalink = match self
+            .titles
+            .get(&name.to_lowercase())
+            .or_else(|| self.stems.get(&slugify!(name)))
+            .and_then(|path| self.get(path))
+        {
+            Some(permalink) => permalink.to_string(),
+            None if is_content_link(name) => return self.resolve(referrer, target),
+            None => return None,
+        };
+
+        Some(match maybe_fragment {
+            Some(fragment) if !fragment.is_empty() => format!("{permalink}#{fragment}"),
+            _ => permalink,
+        })
+    }
+
+    /// Checks whether `url` is the permalink of a page, ignoring a fragment.
+    pub fn is_permalink(&self, url: &str) -> bool {
+        let url = url.split('#').next().unwrap_or_default();
+        self.urls.contains(url)
+    }
+}
+
+/// Checks whether `link` points at a markdown or org file of the site rather
+/// than at an external resource.
+pub fn is_content_link(link: &str) -> bool {
+    if link.contains("://") || link.starts_with("mailto:") {
+        return false;
+    }
+    let path = link.split('#').next().unwrap_or_default();
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("org"))
+}
+
+/// Returns the slug of a page, derived from its title or, without a title,
+/// from the file stem of `path`.
+pub fn slug(maybe_title: Option<&str>, path: &Path) -> String {
+    match maybe_title {
+        Some(title) => slugify!(title),
+        None => slugify!(&path
+            .file_stem()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()),
+    }
+}
+
+/// Reads the title of a content file without parsing its content.
+pub fn read_title_from_content_of_file(path: &Path) -> Option<String> {
+    read_front_matter_of_file(path).and_then(|fm| fm.title)
+}
+
+/// Reads the front matter of a content file without parsing its content.
+///
+/// Of an org file, only the `title`, `date`, `slug` and `url` keywords are
+/// read, like `#+title: Notes`.
+pub fn read_front_matter_of_file(path: &Path) -> Option<FrontMatter> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let front_matter = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("org") => Value::Mapping(
+            content
+                .lines()
+                .filter_map(|line| {
+                    let (keyword, value) = line.trim().split_once(':')?;
+                    let key = keyword.strip_prefix("#+")?.to_lowercase();
+                    matches!(key.as_str(), "title" | "date" | "slug" | "url")
+                        .then(|| (Value::String(key), Value::String(value.trim().to_string())))
+                })
+                .collect::<Mapping>(),
+        ),
+        _ => parse_front_matter(&content).ok().flatten()?,
+    };
+    libs::serde_yaml::from_value(front_matter).ok()
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+use std::sync::Arc;
+
+use libs::parking_lot::Mutex;
+
+use crate::ModuleSpecifier;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a source file, pointing at the offending text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub specifier: ModuleSpecifier,
+    pub message: String,
+    /// The 1-based line of the offending text.
+    pub line: usize,
+    /// The 1-based column of the offending text.
+    pub column: usize,
+    /// The line containing the offending text.
+    pub snippet: String,
+    /// The number of characters of the offending text on `snippet`.
+    pub length: usize,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    /// Formats the diagnostic like
+    ///
+    /// ```text
+    /// error: relref target not found: missing.md
+    ///  --> /site/content/notes/a.md:3:5
+    ///   |
+    /// 3 | See {{< relref "missing.md" >}}
+    ///   |     ^^^^^^^^^^^^^^^^^^^^^^^^^^^
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        let snippet = self.snippet.trim_end_matches(['\r', '\n']);
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+        writeln!(
+            f,
+            "{gutter}--> {}:{}:{}",
+            self.specifier.path(),
+            self.line,
+            self.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {snippet}", self.line)?;
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(self.column.saturating_sub(1)),
+            "^".repeat(self.length.max(1))
+        )
+    }
+}
+
+/// Collects the diagnostics of a build.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics(Arc<Mutex<Vec<Diagnostic>>>);
+
+impl Diagnostics {
+    /// Adds `diagnostic`, unless it was already added: the diagnostics of a
+    /// source are reported every time a task loads it.
+    pub fn push(&self, diagnostic: Diagnostic) {
+        let mut diagnostics = self.0.lock();
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Removes and returns all diagnostics collected so far.
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.0.lock())
+    }
+}
@@ -0,0 +1,101 @@
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::serde_yaml::{Mapping, Value};
+use std::ops::Range;
+
+/// The format of a front matter, told apart by its delimiters:
+///
+/// - YAML between two `---` lines,
+/// - TOML between two `+++` lines, as written by Hugo,
+/// - a JSON object between a `{` and a `}` line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontMatterFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+/// Finds the front matter at the start of `content` and returns its format,
+/// its text without the YAML and TOML delimiters, and the range of the whole
+/// block, delimiters included.
+pub fn split_front_matter(content: &str) -> Option<(FrontMatterFormat, &str, Range<usize>)> {
+    let start = content.len() - content.trim_start().len();
+    let mut lines = content[start..].split_inclusive('\n');
+    let first = lines.next()?;
+    let (format, closing) = match first.trim() {
+        "---" => (FrontMatterFormat::Yaml, "---"),
+        "+++" => (FrontMatterFormat::Toml, "+++"),
+        "{" => (FrontMatterFormat::Json, "}"),
+        _ => return None,
+    };
+
+    let body_start = start + first.len();
+    let mut offset = body_start;
+    for line in lines {
+        if line.trim_end() == closing {
+            let end = offset + line.len();
+            let text = match format {
+                FrontMatterFormat::Json => &content[start..end],
+                _ => &content[body_start..offset],
+            };
+            return Some((format, text, start..end));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Parses the front matter at the start of `content`, whatever its format,
+/// into a YAML value.
+pub fn parse_front_matter(content: &str) -> Result<Option<Value>, Error> {
+    let (format, text, _) = match split_front_matter(content) {
+        Some(front_matter) => front_matter,
+        None => return Ok(None),
+    };
+    let value = match format {
+        FrontMatterFormat::Yaml => libs::serde_yaml::from_str(text)
+            .map_err(|e| generic_error(format!("invalid YAML front matter: {e}")))?,
+        FrontMatterFormat::Toml => libs::toml::from_str(text)
+            .map(toml_to_yaml)
+            .map_err(|e| generic_error(format!("invalid TOML front matter: {e}")))?,
+        FrontMatterFormat::Json => libs::serde_json::from_str::<libs::serde_json::Value>(text)
+            .map_err(|e| generic_error(format!("invalid JSON front matter: {e}")))
+            .and_then(|value| Ok(libs::serde_yaml::to_value(value)?))?,
+    };
+    Ok(Some(value))
+}
+
+/// Returns the front matter of `markdown` as YAML, converted from TOML or
+/// JSON if need be, or an empty string if there is none or it is invalid.
+pub fn extract_yaml(markdown: &str) -> String {
+    match split_front_matter(markdown) {
+        Some((FrontMatterFormat::Yaml, text, _)) => text.to_string(),
+        Some(_) => parse_front_matter(markdown)
+            .ok()
+            .flatten()
+            .and_then(|value| libs::serde_yaml::to_string(&value).ok())
+            .unwrap_or_default(),
+        None => String::new(),
+    }
+}
+
+/// Converts a TOML value to YAML, with the dates as strings like in YAML
+/// front matter.
+fn toml_to_yaml(value: libs::toml::Value) -> Value {
+    use libs::toml::Value as Toml;
+
+    match value {
+        Toml::String(s) => Value::String(s),
+        Toml::Integer(i) => Value::Number(i.into()),
+        Toml::Float(f) => Value::Number(f.into()),
+        Toml::Boolean(b) => Value::Bool(b),
+        Toml::Datetime(datetime) => Value::String(datetime.to_string()),
+        Toml::Array(values) => Value::Sequence(values.into_iter().map(toml_to_yaml).collect()),
+        Toml::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Value::String(key), toml_to_yaml(value)))
+                .collect::<Mapping>(),
+        ),
+    }
+}
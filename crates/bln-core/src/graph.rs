@@ -7,8 +7,13 @@ use std::path::{Path, PathBuf};
 use libs::petgraph::graph::NodeIndex;
 use libs::petgraph::visit::Dfs;
 use libs::petgraph::Directed;
+use libs::petgraph::Direction;
 use libs::petgraph::Graph;
 use libs::petgraph::Incoming;
+use libs::petgraph::Outgoing;
+use serde::Serialize;
+
+use crate::ParsedSource;
 
 pub struct Resolutions(Inner);
 
@@ -123,6 +128,91 @@ impl ResolutionsBuilder {
     }
 }
 
+/// A page in a [`LinkGraph`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LinkedPage {
+    pub title: String,
+    pub target: String,
+}
+
+/// The links between pages, used to list the pages linking to a page.
+pub struct LinkGraph {
+    graph: Graph<LinkedPage, (), Directed>,
+    node_ids: HashMap<String, NodeIndex<u32>>,
+}
+
+impl LinkGraph {
+    /// Builds the graph of the links between `sources`; links to pages
+    /// outside of `sources` are ignored.
+    pub fn new(sources: &[ParsedSource]) -> Self {
+        let mut graph = Graph::new();
+        let mut node_ids = HashMap::new();
+
+        for source in sources {
+            if let Some(permalink) = source.permalink() {
+                let title = source
+                    .front_matter()
+                    .and_then(|fm| fm.title.clone())
+                    .unwrap_or_else(|| permalink.to_string());
+                let idx = graph.add_node(LinkedPage {
+                    title,
+                    target: permalink.to_string(),
+                });
+                node_ids.insert(permalink.to_string(), idx);
+            }
+        }
+
+        for source in sources {
+            let from = match source.permalink().and_then(|p| node_ids.get(p)) {
+                Some(from) => *from,
+                None => continue,
+            };
+            for link in source.links() {
+                let target = link.split('#').next().unwrap_or_default();
+                if let Some(to) = node_ids.get(target) {
+                    if from != *to && !graph.contains_edge(from, *to) {
+                        graph.add_edge(from, *to, ());
+                    }
+                }
+            }
+        }
+
+        Self { graph, node_ids }
+    }
+
+    /// Returns all links as pairs of the linking and the linked page.
+    pub fn edges(&self) -> Vec<(&LinkedPage, &LinkedPage)> {
+        self.graph
+            .raw_edges()
+            .iter()
+            .map(|e| (&self.graph[e.source()], &self.graph[e.target()]))
+            .collect()
+    }
+
+    /// Returns the pages linking to the page at `permalink`, ordered by title.
+    pub fn backlinks(&self, permalink: &str) -> Vec<&LinkedPage> {
+        self.neighbors(permalink, Incoming)
+    }
+
+    /// Returns the pages the page at `permalink` links to, ordered by title.
+    pub fn links(&self, permalink: &str) -> Vec<&LinkedPage> {
+        self.neighbors(permalink, Outgoing)
+    }
+
+    fn neighbors(&self, permalink: &str, direction: Direction) -> Vec<&LinkedPage> {
+        let mut pages = match self.node_ids.get(permalink) {
+            Some(idx) => self
+                .graph
+                .neighbors_directed(*idx, direction)
+                .map(|n| &self.graph[n])
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        pages.sort_by(|a, b| a.title.cmp(&b.title));
+        pages
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
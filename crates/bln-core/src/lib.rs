@@ -1,8 +1,13 @@
+mod content_index;
+mod diagnostic;
+mod front_matter;
 mod graph;
 mod media_type;
 mod module_specifier;
 mod normalize_path;
 mod parsed_source;
+mod permalink;
+mod taxonomy;
 
 pub use module_specifier::resolve_import;
 pub use module_specifier::resolve_path;
@@ -13,11 +18,40 @@ pub use module_specifier::ModuleSpecifier;
 pub use module_specifier::DUMMY_SPECIFIER;
 pub use normalize_path::normalize_path;
 
+pub use content_index::is_content_link;
+pub use content_index::read_front_matter_of_file;
+pub use content_index::read_title_from_content_of_file;
+pub use content_index::slug;
+pub use content_index::ContentIndex;
+pub use content_index::SharedContentIndex;
+
+pub use front_matter::extract_yaml;
+pub use front_matter::parse_front_matter;
+pub use front_matter::split_front_matter;
+pub use front_matter::FrontMatterFormat;
+
+pub use diagnostic::Diagnostic;
+pub use diagnostic::Diagnostics;
+pub use diagnostic::Severity;
+
 pub use media_type::MediaType;
 
 pub use parsed_source::FrontMatter;
 pub use parsed_source::ParsedSource;
 pub use parsed_source::ParsedSourceBuilder;
+pub use parsed_source::TocEntry;
+
+pub use permalink::output_path;
+pub use permalink::redirect_path;
+pub use permalink::PermalinkOptions;
+pub use permalink::SlugSource;
+
+pub use taxonomy::default_taxonomies;
+pub use taxonomy::Taxonomies;
+pub use taxonomy::TaxonomyOptions;
+pub use taxonomy::Term;
 
+pub use graph::LinkGraph;
+pub use graph::LinkedPage;
 pub use graph::Resolutions;
 pub use graph::ResolutionsBuilder;
@@ -1,8 +1,9 @@
+use libs::serde_yaml;
 use libs::url::Url;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{MediaType, ModuleSpecifier};
-use std::{any::Any, fs::Metadata, sync::Arc};
+use crate::{Diagnostic, MediaType, ModuleSpecifier, Term};
+use std::{any::Any, collections::BTreeMap, fs::Metadata, sync::Arc};
 
 #[derive(Clone, Debug)]
 struct ParsedSourceInner {
@@ -11,6 +12,15 @@ struct ParsedSourceInner {
     data: Option<String>,
     front_matter: Option<FrontMatter>,
     metadata: Option<Metadata>,
+    permalink: Option<String>,
+    terms: BTreeMap<String, Vec<Term>>,
+    links: Vec<String>,
+    description_html: Option<String>,
+    toc: Vec<TocEntry>,
+    summary_html: Option<String>,
+    word_count: usize,
+    reading_time: usize,
+    diagnostics: Vec<Diagnostic>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -22,9 +32,48 @@ pub struct FrontMatter {
     pub description: Option<String>,
     pub tags: Option<Vec<String>>,
     pub id: Option<String>,
+    /// Replaces the slug of the permalink.
+    pub slug: Option<String>,
+    /// Replaces the whole permalink.
+    pub url: Option<String>,
+    /// The old URLs of the page, redirected to its permalink.
+    pub aliases: Option<Vec<String>>,
+    /// The template the page is rendered with, relative to `templates/`.
+    pub template: Option<String>,
+    /// The position of the page in a collection sorted by weight.
+    pub weight: Option<i64>,
+    /// The other keys, e.g. the terms of the taxonomies other than tags.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A heading of a page in its table of contents, with the headings of the
+/// lower levels below it as `children`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    /// The id of the heading element, to link to it with `#id`.
+    pub id: String,
+    pub text: String,
+    pub children: Vec<TocEntry>,
 }
 
 impl FrontMatter {
+    /// Returns the strings under `key`, a single string or a list of them.
+    pub fn values(&self, key: &str) -> Vec<String> {
+        if key == "tags" {
+            return self.tags.clone().unwrap_or_default();
+        }
+        match self.extra.get(key) {
+            Some(serde_yaml::Value::String(value)) => vec![value.clone()],
+            Some(serde_yaml::Value::Sequence(values)) => values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn get_fields(&self) -> Vec<(&str, Option<Box<dyn Any>>)> {
         vec![
             (
@@ -107,6 +156,63 @@ impl ParsedSource {
     pub fn metadata(&self) -> Option<&Metadata> {
         self.inner.metadata.as_ref()
     }
+
+    /// Gets the URL of the page rendered from the module, if it is part of
+    /// the content index.
+    pub fn permalink(&self) -> Option<&str> {
+        self.inner.permalink.as_deref()
+    }
+
+    /// Gets the terms of the taxonomy `taxonomy` the module is listed under.
+    pub fn terms(&self, taxonomy: &str) -> &[Term] {
+        self.inner
+            .terms
+            .get(taxonomy)
+            .map_or(&[], |terms| terms.as_slice())
+    }
+
+    /// Gets the terms of the module by taxonomy.
+    pub fn terms_by_taxonomy(&self) -> &BTreeMap<String, Vec<Term>> {
+        &self.inner.terms
+    }
+
+    /// Gets the permalinks of the pages the module links to.
+    pub fn links(&self) -> &[String] {
+        &self.inner.links
+    }
+
+    /// Gets the description of the front matter converted to HTML with the
+    /// markdown options of the module.
+    pub fn description_html(&self) -> Option<&str> {
+        self.inner.description_html.as_deref()
+    }
+
+    /// Gets the table of contents of the page rendered from the module.
+    pub fn toc(&self) -> &[TocEntry] {
+        &self.inner.toc
+    }
+
+    /// Gets the summary of the page rendered from the module: the content
+    /// before a `<!-- more -->` line, or else its first words.
+    pub fn summary_html(&self) -> Option<&str> {
+        self.inner.summary_html.as_deref()
+    }
+
+    /// Gets the number of words of the page rendered from the module.
+    pub fn word_count(&self) -> usize {
+        self.inner.word_count
+    }
+
+    /// Gets the minutes it takes to read the page rendered from the module.
+    pub fn reading_time(&self) -> usize {
+        self.inner.reading_time
+    }
+
+    /// Gets the problems found while parsing the module, kept so they are
+    /// reported again when the module is taken from a cache.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.inner.diagnostics
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -116,6 +222,15 @@ pub struct ParsedSourceBuilder {
     content: Option<String>,
     front_matter: Option<FrontMatter>,
     metadata: Option<Metadata>,
+    permalink: Option<String>,
+    terms: BTreeMap<String, Vec<Term>>,
+    links: Vec<String>,
+    description_html: Option<String>,
+    toc: Vec<TocEntry>,
+    summary_html: Option<String>,
+    word_count: usize,
+    reading_time: usize,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl ParsedSourceBuilder {
@@ -134,6 +249,15 @@ impl ParsedSourceBuilder {
             content: None,
             front_matter: None,
             metadata: None,
+            permalink: None,
+            terms: BTreeMap::new(),
+            links: Vec::new(),
+            description_html: None,
+            toc: Vec::new(),
+            summary_html: None,
+            word_count: 0,
+            reading_time: 0,
+            diagnostics: Vec::new(),
         }
     }
 
@@ -162,6 +286,51 @@ impl ParsedSourceBuilder {
         self
     }
 
+    pub fn maybe_permalink(mut self, maybe_permalink: Option<String>) -> Self {
+        self.permalink = maybe_permalink;
+        self
+    }
+
+    pub fn terms(mut self, terms: BTreeMap<String, Vec<Term>>) -> Self {
+        self.terms = terms;
+        self
+    }
+
+    pub fn links(mut self, links: Vec<String>) -> Self {
+        self.links = links;
+        self
+    }
+
+    pub fn maybe_description_html(mut self, maybe_description_html: Option<String>) -> Self {
+        self.description_html = maybe_description_html;
+        self
+    }
+
+    pub fn toc(mut self, toc: Vec<TocEntry>) -> Self {
+        self.toc = toc;
+        self
+    }
+
+    pub fn maybe_summary_html(mut self, maybe_summary_html: Option<String>) -> Self {
+        self.summary_html = maybe_summary_html;
+        self
+    }
+
+    pub fn word_count(mut self, word_count: usize) -> Self {
+        self.word_count = word_count;
+        self
+    }
+
+    pub fn reading_time(mut self, reading_time: usize) -> Self {
+        self.reading_time = reading_time;
+        self
+    }
+
+    pub fn diagnostics(mut self, diagnostics: Vec<Diagnostic>) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
     pub fn build(self) -> ParsedSource {
         ParsedSource {
             inner: Arc::new(ParsedSourceInner {
@@ -170,6 +339,15 @@ impl ParsedSourceBuilder {
                 data: self.content,
                 front_matter: self.front_matter,
                 metadata: self.metadata,
+                permalink: self.permalink,
+                terms: self.terms,
+                links: self.links,
+                description_html: self.description_html,
+                toc: self.toc,
+                summary_html: self.summary_html,
+                word_count: self.word_count,
+                reading_time: self.reading_time,
+                diagnostics: self.diagnostics,
             }),
         }
     }
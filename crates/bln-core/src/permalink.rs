@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::chrono::{Datelike, NaiveDate};
+use libs::lazy_static;
+use libs::regex::{Captures, Regex};
+use libs::slugify::slugify;
+use serde::{Deserialize, Serialize};
+
+use crate::{slug, FrontMatter};
+
+lazy_static::lazy_static! {
+    static ref PLACEHOLDER_RE: Regex = Regex::new(r":(?P<name>[a-z]+)").unwrap();
+}
+
+/// How the permalinks of the pages below `content/` are built, read from the
+/// `[permalinks]` section of `berlin.toml`:
+///
+/// ```toml
+/// [permalinks]
+/// pretty_urls = true
+/// slug = "file"
+/// collections = { notes = "/notes/:year/:slug/" }
+/// ```
+///
+/// A pattern may use `:year`, `:month` and `:day` of the `date` of the page,
+/// `:slug`, `:collection` and `:section`, the directory of the page below
+/// `content/`. Patterns ending with `/` are written to `index.html`. A `slug`
+/// in the front matter replaces `:slug`, a `url` replaces the whole pattern.
+///
+/// The `_index.md` of a directory is the list page of its section, at
+/// `/:section.html`, or `/:section/` with pretty URLs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PermalinkOptions {
+    /// Ends the permalinks without a pattern with `/` rather than `.html`.
+    pub pretty_urls: bool,
+    pub slug: SlugSource,
+    /// The patterns by collection, the first directory below `content/`.
+    pub collections: BTreeMap<String, String>,
+}
+
+/// What the slug of a page is derived from, if its front matter has none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugSource {
+    /// The title, or the file stem without a title.
+    Title,
+    /// The file stem, so that changing a title keeps the URL.
+    File,
+}
+
+impl Default for PermalinkOptions {
+    fn default() -> Self {
+        Self {
+            pretty_urls: false,
+            slug: SlugSource::Title,
+            collections: BTreeMap::new(),
+        }
+    }
+}
+
+impl PermalinkOptions {
+    /// Returns the permalink of the page rendered from the file at `path`,
+    /// which is below the content directory `root`.
+    pub fn permalink(
+        &self,
+        root: &Path,
+        path: &Path,
+        maybe_front_matter: Option<&FrontMatter>,
+    ) -> Result<String, Error> {
+        if let Some(url) = maybe_front_matter.and_then(|fm| fm.url.as_deref()) {
+            let url = normalize_url(url)
+                .map_err(|e| generic_error(format!("{}: {e} in its `url`", path.display())))?;
+            return Ok(self.finish(&url));
+        }
+
+        let section = path
+            .parent()
+            .and_then(|p| p.strip_prefix(root).ok())
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let collection = section.split('/').next().unwrap_or_default();
+        if path.file_stem().is_some_and(|stem| stem == "_index") {
+            return Ok(self.finish(&format!("/{section}")));
+        }
+        let slug = match maybe_front_matter.and_then(|fm| fm.slug.as_deref()) {
+            Some(slug) => slugify!(slug),
+            None => match self.slug {
+                SlugSource::Title => {
+                    slug(maybe_front_matter.and_then(|fm| fm.title.as_deref()), path)
+                }
+                SlugSource::File => slug(None, path),
+            },
+        };
+        let pattern = match self.collections.get(collection) {
+            Some(pattern) => pattern.as_str(),
+            None => "/:section/:slug",
+        };
+
+        let maybe_date = maybe_front_matter
+            .and_then(|fm| fm.published.as_deref())
+            .and_then(|date| NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok());
+        let mut maybe_error = None;
+        let permalink = PLACEHOLDER_RE.replace_all(pattern, |caps: &Captures| {
+            let name = &caps["name"];
+            let value = match (name, maybe_date) {
+                ("slug", _) => Ok(slug.clone()),
+                ("section", _) => Ok(section.clone()),
+                ("collection", _) => Ok(collection.to_string()),
+                ("year", Some(date)) => Ok(format!("{:04}", date.year())),
+                ("month", Some(date)) => Ok(format!("{:02}", date.month())),
+                ("day", Some(date)) => Ok(format!("{:02}", date.day())),
+                ("year" | "month" | "day", None) => Err(format!(
+                    "{}: the permalink pattern `{pattern}` needs a `date` like 2023-04-03",
+                    path.display()
+                )),
+                _ => Err(format!(
+                    "unknown placeholder `:{name}` in the permalink pattern `{pattern}`"
+                )),
+            };
+            value.unwrap_or_else(|e| {
+                maybe_error.get_or_insert(e);
+                String::new()
+            })
+        });
+        if let Some(e) = maybe_error {
+            return Err(generic_error(e));
+        }
+
+        let normalized = normalize_url(&permalink)
+            .map_err(|e| generic_error(format!("{e} in the permalink pattern `{pattern}`")))?;
+        Ok(self.finish(&normalized))
+    }
+
+    /// Ends `url` with `/` or `.html`, unless it ends with either already.
+    fn finish(&self, url: &str) -> String {
+        match url.ends_with('/') || url.ends_with(".html") {
+            true => url.to_string(),
+            false if self.pretty_urls => format!("{url}/"),
+            false => format!("{url}.html"),
+        }
+    }
+}
+
+/// Makes `url` absolute and drops its empty and `.` segments, keeping a
+/// trailing `/`. Fails on `..` segments, which would write pages outside of
+/// the target directory.
+pub(crate) fn normalize_url(url: &str) -> Result<String, Error> {
+    let mut normalized = String::from("/");
+    for segment in url.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        if segment == ".." {
+            return Err(generic_error(format!("`{url}` leaves the site with `..`")));
+        }
+        normalized.push_str(segment);
+        normalized.push('/');
+    }
+    if !url.ends_with('/') && normalized.len() > 1 {
+        normalized.pop();
+    }
+    Ok(normalized)
+}
+
+/// Returns the path of the file the page at `permalink` is written to,
+/// relative to the target directory, e.g. `notes/rust/index.html` for
+/// `/notes/rust/`.
+pub fn output_path(permalink: &str) -> String {
+    let path = permalink.trim_start_matches('/');
+    match path.is_empty() || path.ends_with('/') {
+        true => format!("{path}index.html"),
+        false => path.to_string(),
+    }
+}
+
+/// Returns the path of the redirect page for the URL `from`, relative to the
+/// target directory. URLs without an extension are taken as directories.
+pub fn redirect_path(from: &str) -> String {
+    let last_segment = from.rsplit('/').next().unwrap_or_default();
+    match last_segment.is_empty() || last_segment.contains('.') {
+        true => output_path(from),
+        false => output_path(&format!("{from}/")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permalink() {
+        let root = Path::new("/site/content");
+        let path = Path::new("/site/content/notes/rust-notes.md");
+        let front_matter: FrontMatter =
+            libs::serde_yaml::from_str("title: Rust in 2023\ndate: 2023-04-03\n").unwrap();
+
+        let options = PermalinkOptions::default();
+        assert_eq!(
+            options.permalink(root, path, Some(&front_matter)).unwrap(),
+            "/notes/rust-in-2023.html"
+        );
+
+        let options = PermalinkOptions {
+            pretty_urls: true,
+            slug: SlugSource::File,
+            collections: BTreeMap::from([("notes".to_string(), "/notes/:year/:slug/".to_string())]),
+        };
+        assert_eq!(
+            options.permalink(root, path, Some(&front_matter)).unwrap(),
+            "/notes/2023/rust-notes/"
+        );
+        assert_eq!(
+            options
+                .permalink(root, Path::new("/site/content/about.md"), None)
+                .unwrap(),
+            "/about/"
+        );
+        assert!(options.permalink(root, path, None).is_err());
+        assert_eq!(
+            options
+                .permalink(root, Path::new("/site/content/talks/_index.md"), None)
+                .unwrap(),
+            "/talks/"
+        );
+        let moved: FrontMatter = libs::serde_yaml::from_str("url: ./old/./rust//\n").unwrap();
+        assert_eq!(
+            options.permalink(root, path, Some(&moved)).unwrap(),
+            "/old/rust/"
+        );
+        let escaped: FrontMatter = libs::serde_yaml::from_str("url: ../../x\n").unwrap();
+        assert!(options.permalink(root, path, Some(&escaped)).is_err());
+        assert_eq!(normalize_url("/").unwrap(), "/");
+        assert_eq!(normalize_url("a/./b.html").unwrap(), "/a/b.html");
+        assert!(normalize_url("/a/../../b").is_err());
+        assert_eq!(
+            output_path("/notes/2023/rust-notes/"),
+            "notes/2023/rust-notes/index.html"
+        );
+    }
+
+    #[test]
+    fn test_redirect_path() {
+        assert_eq!(redirect_path("/notes/old.html"), "notes/old.html");
+        assert_eq!(redirect_path("/notes/old/"), "notes/old/index.html");
+        assert_eq!(redirect_path("/notes/old"), "notes/old/index.html");
+    }
+}
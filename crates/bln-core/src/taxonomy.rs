@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use libs::slugify::slugify;
+use serde::{Deserialize, Serialize};
+
+/// The taxonomies by name.
+pub type Taxonomies = BTreeMap<String, TaxonomyOptions>;
+
+/// A way to group pages by the values of a front matter key, read from the
+/// `[taxonomies.<name>]` sections of `berlin.toml`:
+///
+/// ```toml
+/// [taxonomies.series]
+/// url = "/series/:term/"
+/// template = "series/term.tera"
+/// list_template = "series/list.tera"
+/// feed = "/series/:term/atom.xml"
+/// ```
+///
+/// Without a `[taxonomies]` section, pages are grouped by their `tags`, at
+/// `/tags/:term.html` with the tags as they are written, like before
+/// taxonomies could be configured.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaxonomyOptions {
+    /// The front matter key of the terms, the name of the taxonomy by default.
+    pub key: Option<String>,
+    /// The pattern of the URLs of the term pages, with the `:taxonomy` and
+    /// the `:term`.
+    pub url: String,
+    /// Whether `:term` is slugified in the URLs.
+    pub slugify: bool,
+    /// The template of the term pages, `<taxonomy>/base.tera` by default.
+    pub template: Option<String>,
+    /// The template of the page listing all terms at `/<taxonomy>/`, which is
+    /// only rendered with a template.
+    pub list_template: Option<String>,
+    /// The pattern of the URLs of the Atom feeds of the terms, which are only
+    /// written with a pattern.
+    pub feed: Option<String>,
+    /// The term of the pages without any.
+    pub default_term: Option<String>,
+}
+
+/// A term of a taxonomy, e.g. a tag, and the URL of its page.
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, PartialOrd, Ord, Clone)]
+pub struct Term {
+    pub name: String,
+    pub target: String,
+}
+
+impl Default for TaxonomyOptions {
+    fn default() -> Self {
+        Self {
+            key: None,
+            url: "/:taxonomy/:term.html".to_string(),
+            slugify: true,
+            template: None,
+            list_template: None,
+            feed: None,
+            default_term: None,
+        }
+    }
+}
+
+impl TaxonomyOptions {
+    /// Returns the front matter key of the taxonomy `name`.
+    pub fn key<'a>(&'a self, name: &'a str) -> &'a str {
+        self.key.as_deref().unwrap_or(name)
+    }
+
+    /// Returns the term `term` of the taxonomy `name`.
+    pub fn term(&self, name: &str, term: &str) -> Term {
+        Term {
+            name: term.to_string(),
+            target: self.expand(&self.url, name, term),
+        }
+    }
+
+    /// Returns the URL of the feed of the term `term` of the taxonomy `name`.
+    pub fn feed_url(&self, name: &str, term: &str) -> Option<String> {
+        self.feed.as_ref().map(|feed| self.expand(feed, name, term))
+    }
+
+    fn expand(&self, pattern: &str, name: &str, term: &str) -> String {
+        let term = match self.slugify {
+            true => slugify!(term),
+            false => term.to_string(),
+        };
+        pattern.replace(":taxonomy", name).replace(":term", &term)
+    }
+
+    /// Returns the terms `values` of the taxonomy `name`, or the default term
+    /// if there are none.
+    pub fn terms(&self, name: &str, values: &[String]) -> Vec<Term> {
+        match (values.is_empty(), self.default_term.as_ref()) {
+            (true, Some(default_term)) => vec![self.term(name, default_term)],
+            _ => values.iter().map(|value| self.term(name, value)).collect(),
+        }
+    }
+}
+
+/// Returns the taxonomies of a site without a `[taxonomies]` section.
+pub fn default_taxonomies() -> Taxonomies {
+    BTreeMap::from([(
+        "tags".to_string(),
+        TaxonomyOptions {
+            slugify: false,
+            default_term: Some("uncategorized".to_string()),
+            ..TaxonomyOptions::default()
+        },
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terms() {
+        let tags = &default_taxonomies()["tags"];
+        assert_eq!(
+            tags.terms("tags", &["Rust Lang".to_string()]),
+            vec![Term {
+                name: "Rust Lang".to_string(),
+                target: "/tags/Rust Lang.html".to_string(),
+            }]
+        );
+        assert_eq!(
+            tags.terms("tags", &[])[0].target,
+            "/tags/uncategorized.html"
+        );
+
+        let series = TaxonomyOptions {
+            url: "/:taxonomy/:term/".to_string(),
+            feed: Some("/:taxonomy/:term/atom.xml".to_string()),
+            ..TaxonomyOptions::default()
+        };
+        assert!(series.terms("series", &[]).is_empty());
+        assert_eq!(
+            series.term("series", "Rust Lang").target,
+            "/series/rust-lang/"
+        );
+        assert_eq!(
+            series.feed_url("series", "Berlin"),
+            Some("/series/berlin/atom.xml".to_string())
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::lazy_static::lazy_static;
+use libs::lightningcss::{
+    rules::{CssRule, CssRuleList},
+    stylesheet::{ParserOptions, PrinterOptions, StyleSheet},
+    traits::ToCss,
+};
+use libs::regex::Regex;
+
+lazy_static! {
+    static ref TAG_RE: Regex = Regex::new(r#"<([a-zA-Z][a-zA-Z0-9-]*)([^>]*)>"#).unwrap();
+    static ref CLASS_RE: Regex = Regex::new(r#"\bclass\s*=\s*["']([^"']*)["']"#).unwrap();
+    static ref ID_RE: Regex = Regex::new(r#"\bid\s*=\s*["']([^"']*)["']"#).unwrap();
+    static ref STYLESHEET_RE: Regex =
+        Regex::new(r#"<link\b[^>]*\brel\s*=\s*["']stylesheet["'][^>]*>"#).unwrap();
+    static ref HREF_RE: Regex = Regex::new(r#"\bhref\s*=\s*["']([^"']*)["']"#).unwrap();
+    static ref REL_RE: Regex = Regex::new(r#"\brel\s*=\s*["']stylesheet["']"#).unwrap();
+}
+
+/// The element names, ids and classes used by a piece of markup.
+#[derive(Default, Debug)]
+struct UsedSelectors {
+    tags: HashSet<String>,
+    ids: HashSet<String>,
+    classes: HashSet<String>,
+}
+
+impl UsedSelectors {
+    fn from_markup(markup: &str) -> Self {
+        let mut used = UsedSelectors::default();
+        for caps in TAG_RE.captures_iter(markup) {
+            used.tags.insert(caps[1].to_lowercase());
+            let attributes = &caps[2];
+            if let Some(classes) = CLASS_RE.captures(attributes) {
+                used.classes
+                    .extend(classes[1].split_whitespace().map(String::from));
+            }
+            if let Some(id) = ID_RE.captures(attributes) {
+                used.ids.insert(id[1].trim().to_string());
+            }
+        }
+        used
+    }
+
+    /// Checks whether every element name, id and class of the selector is used.
+    ///
+    /// Pseudo-classes, pseudo-elements and attribute selectors are ignored, so
+    /// `a:hover` is kept as soon as the markup contains an `a` element.
+    fn matches(&self, selector: &str) -> bool {
+        let selector = strip_groups(selector);
+        selector
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '+' || c == '~')
+            .filter(|compound| !compound.is_empty())
+            .all(|compound| self.matches_compound(compound))
+    }
+
+    fn matches_compound(&self, compound: &str) -> bool {
+        let compound = compound.split(':').next().unwrap_or_default();
+        let parts = compound.split_inclusive(['.', '#']);
+        let mut prefix = None;
+        let mut matches = true;
+
+        for part in parts {
+            let (name, next_prefix) = match part.chars().last() {
+                Some(c @ ('.' | '#')) => (&part[..part.len() - 1], Some(c)),
+                _ => (part, None),
+            };
+            if !name.is_empty() {
+                matches &= match prefix {
+                    Some('.') => self.classes.contains(name),
+                    Some('#') => self.ids.contains(name),
+                    _ => name == "*" || self.tags.contains(&name.to_lowercase()),
+                };
+            }
+            prefix = next_prefix;
+        }
+
+        matches
+    }
+}
+
+/// Removes attribute selectors and the arguments of functional pseudo-classes.
+fn strip_groups(selector: &str) -> String {
+    let mut depth = 0;
+    selector
+        .chars()
+        .filter(|c| match c {
+            '[' | '(' => {
+                depth += 1;
+                false
+            }
+            ']' | ')' => {
+                depth -= 1;
+                false
+            }
+            _ => depth == 0,
+        })
+        .collect()
+}
+
+fn retain_critical_rules<'i>(rules: &CssRuleList<'i>, used: &UsedSelectors) -> CssRuleList<'i> {
+    let mut critical = Vec::new();
+    for rule in &rules.0 {
+        match rule {
+            CssRule::Style(style) => {
+                let mut selectors = style.selectors.clone();
+                selectors.0.retain(|selector| {
+                    selector
+                        .to_css_string(PrinterOptions::default())
+                        .map_or(true, |s| used.matches(&s))
+                });
+                if !selectors.0.is_empty() {
+                    let mut style = style.clone();
+                    style.selectors = selectors;
+                    critical.push(CssRule::Style(style));
+                }
+            }
+            CssRule::Media(media) => {
+                let rules = retain_critical_rules(&media.rules, used);
+                if !rules.0.is_empty() {
+                    let mut media = media.clone();
+                    media.rules = rules;
+                    critical.push(CssRule::Media(media));
+                }
+            }
+            CssRule::FontFace(_) => critical.push(rule.clone()),
+            _ => {}
+        }
+    }
+    CssRuleList(critical)
+}
+
+/// Returns the part of the page that is rendered before the fold.
+///
+/// Everything up to `fold_marker` is considered above the fold. If the page
+/// does not contain the marker, the whole page is used.
+pub fn above_the_fold<'a>(html: &'a str, fold_marker: &str) -> &'a str {
+    match html.find(fold_marker) {
+        Some(pos) => &html[..pos],
+        None => html,
+    }
+}
+
+/// A compiled stylesheet, parsed once to extract the critical rules of all
+/// the pages of a build.
+pub struct CriticalCss<'i> {
+    rules: CssRuleList<'i>,
+}
+
+impl<'i> CriticalCss<'i> {
+    pub fn parse(code: &'i str) -> Result<Self, Error> {
+        let stylesheet = StyleSheet::parse(code, ParserOptions::default())
+            .map_err(|e| generic_error(e.to_string()))?;
+        Ok(Self {
+            rules: stylesheet.rules,
+        })
+    }
+
+    /// Extracts the rules of the stylesheet that apply to `markup`.
+    pub fn extract(&self, markup: &str) -> Result<String, Error> {
+        let used = UsedSelectors::from_markup(markup);
+        let rules = retain_critical_rules(&self.rules, &used);
+
+        StyleSheet::new(vec![], rules, ParserOptions::default())
+            .to_css(PrinterOptions {
+                minify: true,
+                ..PrinterOptions::default()
+            })
+            .map(|res| res.code)
+            .map_err(|e| generic_error(e.to_string()))
+    }
+}
+
+/// Extracts the rules of a compiled stylesheet that apply to `markup`.
+pub fn extract_critical_css(code: &str, markup: &str) -> Result<String, Error> {
+    CriticalCss::parse(code)?.extract(markup)
+}
+
+/// Inlines `critical` into the `<head>` of `html` and loads the stylesheet
+/// at `stylesheet_href` in the asset manifest, linked as `/{stylesheet_href}`,
+/// asynchronously. The other attributes of its `<link>` are kept.
+pub fn inline_critical_css(html: &str, critical: &str, stylesheet_href: &str) -> String {
+    let stylesheet_url = format!("/{stylesheet_href}");
+    let html = STYLESHEET_RE.replace_all(html, |caps: &libs::regex::Captures| {
+        let link = &caps[0];
+        match HREF_RE.captures(link) {
+            Some(href) if href[1] == stylesheet_url => {
+                let preload = REL_RE.replace(
+                    link,
+                    r#"rel="preload" as="style" onload="this.onload=null;this.rel='stylesheet'""#,
+                );
+                format!("{preload}<noscript>{link}</noscript>")
+            }
+            _ => link.to_string(),
+        }
+    });
+
+    match html.find("</head>") {
+        Some(pos) if !critical.is_empty() => format!(
+            "{}<style>{}</style>{}",
+            &html[..pos],
+            critical,
+            &html[pos..]
+        ),
+        _ => html.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_critical_css() {
+        let code = "h1{color:red}.nav a:hover{color:blue}#footer{margin:0}@media (min-width:600px){.nav{display:flex}.aside{float:right}}";
+        let html = r#"<html><head></head><body><h1 class="title">Hi</h1><nav class="nav"><a href="/">Home</a></nav><!-- fold --><div id="footer"></div></body></html>"#;
+
+        let critical = extract_critical_css(code, above_the_fold(html, "<!-- fold -->")).unwrap();
+
+        assert_eq!(
+            critical,
+            "h1{color:red}.nav a:hover{color:#00f}@media (min-width:600px){.nav{display:flex}}"
+        );
+    }
+
+    #[test]
+    fn test_critical_css_reused_across_pages() {
+        let critical_css = CriticalCss::parse("h1{color:red}.nav{display:flex}").unwrap();
+
+        assert_eq!(
+            critical_css.extract("<h1>Hi</h1>").unwrap(),
+            "h1{color:red}"
+        );
+        assert_eq!(
+            critical_css.extract(r#"<nav class="nav"></nav>"#).unwrap(),
+            ".nav{display:flex}"
+        );
+    }
+
+    #[test]
+    fn test_inline_critical_css() {
+        let html = r#"<html><head><link rel="stylesheet" href="/css/styles.css"></head><body></body></html>"#;
+
+        let output = inline_critical_css(html, "h1{color:red}", "css/styles.css");
+
+        assert_eq!(
+            output,
+            r#"<html><head><link rel="preload" as="style" onload="this.onload=null;this.rel='stylesheet'" href="/css/styles.css"><noscript><link rel="stylesheet" href="/css/styles.css"></noscript><style>h1{color:red}</style></head><body></body></html>"#
+        );
+    }
+
+    #[test]
+    fn test_inline_critical_css_keeps_attributes() {
+        let html = concat!(
+            r#"<link rel="stylesheet" href="https://cdn.example.com/css/styles.css">"#,
+            r#"<link href="/css/styles.css" media="screen" crossorigin="anonymous" integrity="sha384-x" rel='stylesheet'>"#,
+        );
+
+        let output = inline_critical_css(html, "", "css/styles.css");
+
+        assert_eq!(
+            output,
+            concat!(
+                r#"<link rel="stylesheet" href="https://cdn.example.com/css/styles.css">"#,
+                r#"<link href="/css/styles.css" media="screen" crossorigin="anonymous" integrity="sha384-x" rel="preload" as="style" onload="this.onload=null;this.rel='stylesheet'">"#,
+                r#"<noscript><link href="/css/styles.css" media="screen" crossorigin="anonymous" integrity="sha384-x" rel='stylesheet'></noscript>"#,
+            )
+        );
+    }
+}
@@ -1,6 +1,8 @@
+mod critical;
 mod css;
 
 pub use libs::lightningcss::error::PrinterErrorKind;
 pub use libs::lightningcss::stylesheet::ToCssResult;
 
+pub use critical::{above_the_fold, extract_critical_css, inline_critical_css, CriticalCss};
 pub use css::to_css;
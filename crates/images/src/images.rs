@@ -0,0 +1,418 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::image::codecs::avif::AvifEncoder;
+use libs::image::codecs::jpeg::JpegEncoder;
+use libs::image::codecs::png::PngEncoder;
+use libs::image::codecs::webp::WebPEncoder;
+use libs::image::imageops::FilterType;
+use libs::image::DynamicImage;
+use libs::sha2::{Digest, Sha256};
+use serde::Serialize;
+
+/// AVIF encoding is slow, so trade some compression for build time.
+const AVIF_SPEED: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    WebP,
+    Avif,
+}
+
+impl ImageFormat {
+    pub fn as_extension(&self) -> &str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    pub fn mime_type(&self) -> &str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::WebP => "image/webp",
+            Self::Avif => "image/avif",
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Ok(Self::Jpeg),
+            "png" => Ok(Self::Png),
+            "webp" => Ok(Self::WebP),
+            "avif" => Ok(Self::Avif),
+            _ => Err(generic_error(format!("Unsupported image format: {s}"))),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_extension())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    /// The widths of the responsive variants generated for an image.
+    pub widths: Vec<u32>,
+    /// The formats of the responsive variants, in order of preference. WebP
+    /// is encoded losslessly, which suits graphics rather than photos.
+    pub formats: Vec<ImageFormat>,
+    /// The quality used by lossy encoders, from 1 to 100.
+    pub quality: u8,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            widths: vec![480, 960, 1440],
+            formats: vec![ImageFormat::Avif, ImageFormat::Jpeg],
+            quality: 80,
+        }
+    }
+}
+
+/// A processed image written to the target directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageVariant {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: ImageFormat,
+}
+
+/// All variants of an image in one format, ordered by width.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageSource {
+    pub format: ImageFormat,
+    pub variants: Vec<ImageVariant>,
+}
+
+impl ImageSource {
+    /// Returns the value of a `srcset` attribute listing all variants.
+    pub fn srcset(&self) -> String {
+        self.variants
+            .iter()
+            .map(|v| format!("{} {}w", v.url, v.width))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// The original image together with its responsive variants.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponsiveImage {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    pub sources: Vec<ImageSource>,
+}
+
+/// An original image, hashed once for all its variants.
+struct Original {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    /// The hash of the content of the file.
+    hash: Vec<u8>,
+}
+
+/// Resizes and converts images below `source_path`.
+///
+/// Processed images are kept in `cache_path`, keyed by the content of the
+/// original and the requested operation, so unchanged images are not
+/// processed again on the next build.
+#[derive(Debug, Clone)]
+pub struct ImageProcessor {
+    /// The directory images are resolved against.
+    source_path: PathBuf,
+    /// The URL under which the files of `source_path` are served.
+    source_url: String,
+    /// The directory processed images are written to.
+    output_path: PathBuf,
+    /// The URL under which the files of `output_path` are served.
+    output_url: String,
+    cache_path: PathBuf,
+    options: ImageOptions,
+}
+
+impl ImageProcessor {
+    pub fn new(
+        source: (PathBuf, &str),
+        output: (PathBuf, &str),
+        cache_path: PathBuf,
+        options: ImageOptions,
+    ) -> Self {
+        Self {
+            source_path: source.0,
+            source_url: source.1.trim_end_matches('/').to_string(),
+            output_path: output.0,
+            output_url: output.1.trim_end_matches('/').to_string(),
+            cache_path,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &ImageOptions {
+        &self.options
+    }
+
+    /// Returns the file of the image at `path`, which may start with the URL
+    /// of the source directory. Paths leaving the directory are rejected.
+    fn resolve(&self, path: &str) -> Result<PathBuf, Error> {
+        let path = path.trim_start_matches('/');
+        let path = path
+            .strip_prefix(self.source_url.trim_start_matches('/'))
+            .map(|p| p.trim_start_matches('/'))
+            .unwrap_or(path);
+        if path.split(['/', '\\']).any(|segment| segment == "..") {
+            return Err(generic_error(format!(
+                "`{path}` leaves {} with `..`",
+                self.source_path.display()
+            )));
+        }
+        let file_path = self.source_path.join(path);
+        if file_path.is_file() {
+            Ok(file_path)
+        } else {
+            Err(generic_error(format!(
+                "Image not found: {}",
+                file_path.display()
+            )))
+        }
+    }
+
+    /// Returns the width and height of an image without decoding it.
+    pub fn dimensions(&self, path: &str) -> Result<(u32, u32), Error> {
+        let file_path = self.resolve(path)?;
+        libs::image::image_dimensions(&file_path)
+            .map_err(|e| generic_error(format!("{}: {e}", file_path.display())))
+    }
+
+    fn original(&self, path: &str) -> Result<Original, Error> {
+        let file_path = self.resolve(path)?;
+        let (width, height) = self.dimensions(path)?;
+        let hash = Sha256::digest(std::fs::read(&file_path)?).to_vec();
+        Ok(Original {
+            path: file_path,
+            width,
+            height,
+            hash,
+        })
+    }
+
+    /// Resizes an image to fit into `width` and `height` and converts it into
+    /// `format`. Images are never upscaled; a missing format keeps the format
+    /// of the original.
+    pub fn resize(
+        &self,
+        path: &str,
+        width: Option<u32>,
+        height: Option<u32>,
+        maybe_format: Option<ImageFormat>,
+    ) -> Result<ImageVariant, Error> {
+        self.resize_original(&self.original(path)?, width, height, maybe_format)
+    }
+
+    fn resize_original(
+        &self,
+        original: &Original,
+        width: Option<u32>,
+        height: Option<u32>,
+        maybe_format: Option<ImageFormat>,
+    ) -> Result<ImageVariant, Error> {
+        let file_path = &original.path;
+        let format = match maybe_format.or_else(|| ImageFormat::from_path(file_path)) {
+            Some(format) => format,
+            None => {
+                return Err(generic_error(format!(
+                    "Cannot determine the format of {}",
+                    file_path.display()
+                )))
+            }
+        };
+        let (original_width, original_height) = (original.width, original.height);
+        let (width, height) = fit(
+            (original_width, original_height),
+            width.unwrap_or(original_width),
+            height.unwrap_or(original_height),
+        );
+
+        let mut hasher = Sha256::new();
+        hasher.update(&original.hash);
+        hasher.update(format!(
+            "{width}x{height}.{format}.{}",
+            self.options.quality
+        ));
+        let hash = hasher
+            .finalize()
+            .iter()
+            .take(5)
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+
+        let stem = file_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file_name = format!("{stem}.{hash}.{width}w.{format}");
+
+        let cached = self.cache_path.join(&file_name);
+        if !cached.exists() {
+            let image = libs::image::open(file_path)
+                .map_err(|e| generic_error(format!("{}: {e}", file_path.display())))?;
+            let image = if (width, height) == (original_width, original_height) {
+                image
+            } else {
+                image.resize_exact(width, height, FilterType::Lanczos3)
+            };
+            std::fs::create_dir_all(&self.cache_path)?;
+            encode(&image, &cached, format, self.options.quality)?;
+        }
+
+        let output = self.output_path.join(&file_name);
+        if !output.exists() {
+            std::fs::create_dir_all(&self.output_path)?;
+            std::fs::copy(&cached, &output)?;
+        }
+
+        Ok(ImageVariant {
+            url: format!("{}/{}", self.output_url, file_name),
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Generates the variants of an image for all configured widths and
+    /// formats.
+    pub fn responsive(&self, path: &str) -> Result<ResponsiveImage, Error> {
+        let original = self.original(path)?;
+        let (width, height) = (original.width, original.height);
+        let mut widths: Vec<u32> = self
+            .options
+            .widths
+            .iter()
+            .copied()
+            .filter(|w| *w < width)
+            .collect();
+        widths.push(width);
+
+        let mut sources = Vec::new();
+        for format in self.options.formats.iter() {
+            let variants = widths
+                .iter()
+                .map(|w| self.resize_original(&original, Some(*w), None, Some(*format)))
+                .collect::<Result<Vec<ImageVariant>, Error>>()?;
+            sources.push(ImageSource {
+                format: *format,
+                variants,
+            });
+        }
+
+        Ok(ResponsiveImage {
+            url: format!("{}/{}", self.source_url, path.trim_start_matches('/')),
+            width,
+            height,
+            sources,
+        })
+    }
+}
+
+/// Scales `original` down to fit into `width` x `height`, keeping the aspect ratio.
+fn fit(original: (u32, u32), width: u32, height: u32) -> (u32, u32) {
+    let (original_width, original_height) = original;
+    if width >= original_width && height >= original_height {
+        return original;
+    }
+
+    let ratio = f64::min(
+        width as f64 / original_width as f64,
+        height as f64 / original_height as f64,
+    );
+    (
+        ((original_width as f64 * ratio).round() as u32).max(1),
+        ((original_height as f64 * ratio).round() as u32).max(1),
+    )
+}
+
+fn encode(
+    image: &DynamicImage,
+    path: &Path,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    let res = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(writer, quality)),
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new(writer)),
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(writer)),
+        ImageFormat::Avif => DynamicImage::ImageRgba8(image.to_rgba8()).write_with_encoder(
+            AvifEncoder::new_with_speed_quality(writer, AVIF_SPEED, quality),
+        ),
+    };
+
+    res.map_err(|e| {
+        let _ = std::fs::remove_file(path);
+        generic_error(format!("Cannot encode {}: {e}", path.display()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit((1000, 500), 480, 500), (480, 240));
+        assert_eq!(fit((1000, 500), 2000, 2000), (1000, 500));
+        assert_eq!(fit((1000, 500), 1000, 100), (200, 100));
+    }
+
+    #[test]
+    fn test_resolve_below_source_path() {
+        let source_path = std::env::temp_dir().join(format!("bln-static-{}", std::process::id()));
+        std::fs::create_dir_all(source_path.join("img")).unwrap();
+        std::fs::write(source_path.join("img/cat.jpg"), "").unwrap();
+        std::fs::write(source_path.join("secret.jpg"), "").unwrap();
+        let processor = ImageProcessor::new(
+            (source_path.join("img"), "/static"),
+            (source_path.join("out"), "/static/out"),
+            source_path.join("cache"),
+            ImageOptions::default(),
+        );
+
+        assert_eq!(
+            processor.resolve("/static/cat.jpg").unwrap(),
+            source_path.join("img/cat.jpg")
+        );
+        assert!(processor.resolve("/static/../secret.jpg").is_err());
+        assert!(processor.resolve("./../secret.jpg").is_err());
+
+        std::fs::remove_dir_all(source_path).unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+mod images;
+mod svg;
+
+pub use images::{
+    ImageFormat, ImageOptions, ImageProcessor, ImageSource, ImageVariant, ResponsiveImage,
+};
+pub use svg::SvgRasterizer;
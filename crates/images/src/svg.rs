@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::resvg::tiny_skia::{Pixmap, Transform};
+use libs::resvg::usvg::{fontdb, Options, Tree};
+
+/// Renders SVG documents into PNG images.
+///
+/// Loading fonts is expensive, so the font database is built once and shared
+/// by every document rendered with this rasterizer.
+#[derive(Clone)]
+pub struct SvgRasterizer {
+    fontdb: Arc<fontdb::Database>,
+}
+
+impl SvgRasterizer {
+    /// Creates a rasterizer using the system fonts and the fonts found in
+    /// `font_dirs`.
+    pub fn new(font_dirs: &[PathBuf]) -> Self {
+        let mut fontdb = fontdb::Database::new();
+        fontdb.load_system_fonts();
+        for dir in font_dirs {
+            fontdb.load_fonts_dir(dir);
+        }
+
+        Self {
+            fontdb: Arc::new(fontdb),
+        }
+    }
+
+    /// Rasterizes `svg` at the size given by its `width` and `height` and
+    /// returns the encoded PNG.
+    pub fn rasterize(&self, svg: &str) -> Result<Vec<u8>, Error> {
+        let options = Options {
+            fontdb: self.fontdb.clone(),
+            ..Options::default()
+        };
+        let tree = Tree::from_str(svg, &options)
+            .map_err(|e| generic_error(format!("Invalid SVG: {e}")))?;
+
+        let size = tree.size().to_int_size();
+        let mut pixmap = Pixmap::new(size.width(), size.height())
+            .ok_or_else(|| generic_error("SVG has an empty size".to_string()))?;
+        libs::resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+
+        pixmap
+            .encode_png()
+            .map_err(|e| generic_error(format!("Cannot encode PNG: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rasterize() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="120" height="63"><rect width="120" height="63" fill="red"/></svg>"#;
+
+        let png = SvgRasterizer::new(&[]).rasterize(svg).unwrap();
+
+        let image = libs::image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (120, 63));
+    }
+}
@@ -1,14 +1,17 @@
 pub use anyhow;
 pub use atty;
+pub use base64;
 pub use chrono;
 pub use clap;
 pub use comrak;
 pub use cozo;
 pub use csv;
 pub use env_logger;
+pub use exif;
 pub use fnmatch_regex;
 pub use futures;
 pub use glob;
+pub use image;
 pub use lazy_static;
 pub use lightningcss;
 pub use log;
@@ -19,10 +22,13 @@ pub use parking_lot;
 pub use percent_encoding;
 pub use petgraph;
 pub use regex;
+pub use resvg;
 pub use serde_json;
 pub use serde_yaml;
+pub use sha2;
 pub use slugify;
 pub use strum;
+pub use syntect;
 pub use tera;
 pub use termcolor;
 pub use tokio;
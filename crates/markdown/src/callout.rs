@@ -0,0 +1,187 @@
+use crate::shortcode::ShortcodeTemplates;
+use libs::anyhow::Error;
+use libs::comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+use libs::comrak::{format_html, ComrakOptions};
+use libs::lazy_static;
+use libs::regex::Regex;
+use libs::serde_json::json;
+use libs::tera;
+
+lazy_static::lazy_static! {
+    static ref CALLOUT_RE: Regex = Regex::new(r"^\[!(?P<kind>[A-Za-z][\w-]*)\](?P<fold>[+-])?[ \t]*").unwrap();
+}
+
+/// The shortcode template rendering callouts instead of the built-in markup.
+const TEMPLATE: &str = "callout";
+
+/// A blockquote starting with a callout marker, as written in GitHub and
+/// Obsidian:
+///
+/// ```markdown
+/// > [!WARNING]- Optional title
+/// > The content of the callout.
+/// ```
+///
+/// A `-` after the marker makes the callout foldable and folded by default,
+/// a `+` makes it foldable and unfolded.
+pub(crate) struct Callout<'a> {
+    node: &'a AstNode<'a>,
+    /// The lowercased kind, e.g. `warning`.
+    kind: String,
+    maybe_open: Option<bool>,
+    /// The inline nodes following the marker on its line.
+    title: Vec<&'a AstNode<'a>>,
+    /// The line of the blockquote in the source.
+    pub(crate) line: usize,
+}
+
+impl<'a> Callout<'a> {
+    /// Recognizes the blockquote `node` as a callout and strips its marker.
+    ///
+    /// The content is left in place, so links and code blocks in it are
+    /// processed like the rest of the page before the callout is rendered.
+    pub(crate) fn parse(node: &'a AstNode<'a>) -> Option<Self> {
+        if !matches!(node.data.borrow().value, NodeValue::BlockQuote) {
+            return None;
+        }
+        let paragraph = node
+            .first_child()
+            .filter(|n| matches!(n.data.borrow().value, NodeValue::Paragraph))?;
+
+        // The brackets of the marker may end up in text nodes of their own.
+        let mut marker = Vec::new();
+        let mut texts = Vec::new();
+        for child in paragraph.children() {
+            match child.data.borrow().value {
+                NodeValue::Text(ref text) => marker.extend_from_slice(text),
+                _ => break,
+            }
+            texts.push(child);
+        }
+        let marker = String::from_utf8(marker).ok()?;
+        let caps = CALLOUT_RE.captures(&marker)?;
+
+        let mut consumed = caps[0].len();
+        for text_node in texts {
+            if let NodeValue::Text(ref mut text) = text_node.data.borrow_mut().value {
+                let n = consumed.min(text.len());
+                text.drain(..n);
+                consumed -= n;
+            }
+            if consumed == 0 {
+                break;
+            }
+        }
+
+        Some(Self {
+            node,
+            kind: caps["kind"].to_lowercase(),
+            maybe_open: caps.name("fold").map(|fold| fold.as_str() == "+"),
+            title: paragraph
+                .children()
+                .take_while(|n| {
+                    !matches!(
+                        n.data.borrow().value,
+                        NodeValue::SoftBreak | NodeValue::LineBreak
+                    )
+                })
+                .collect(),
+            line: node.data.borrow().start_line as usize,
+        })
+    }
+
+    /// Replaces the blockquote by the markup of the callout, rendered by the
+    /// `callout` shortcode template of the site if there is one.
+    ///
+    /// The template gets the `kind`, the `title` and the `body` as HTML,
+    /// `foldable`, `open` and the front matter of the page as `page`. If it
+    /// fails, the built-in markup is used and the error is returned.
+    pub(crate) fn render(
+        self,
+        options: &ComrakOptions,
+        maybe_templates: Option<&ShortcodeTemplates>,
+        page: &tera::Value,
+    ) -> Result<(), Error> {
+        let mut title = Vec::new();
+        for node in self.title {
+            format_html(node, options, &mut title)?;
+            node.detach();
+        }
+        if let Some(paragraph) = self.node.first_child() {
+            if let Some(line_break) = paragraph.first_child().filter(|n| {
+                matches!(
+                    n.data.borrow().value,
+                    NodeValue::SoftBreak | NodeValue::LineBreak
+                )
+            }) {
+                line_break.detach();
+            }
+            if paragraph.first_child().is_none() {
+                paragraph.detach();
+            }
+        }
+        let title = match String::from_utf8_lossy(&title).trim() {
+            "" => default_title(&self.kind),
+            title => title.to_string(),
+        };
+
+        let mut body = Vec::new();
+        for child in self.node.children().collect::<Vec<_>>() {
+            format_html(child, options, &mut body)?;
+            child.detach();
+        }
+        let body = String::from_utf8_lossy(&body);
+
+        let mut result = Ok(());
+        let html = match maybe_templates.filter(|t| t.has(TEMPLATE)) {
+            Some(templates) => {
+                let args = json!({
+                    "kind": self.kind,
+                    "title": title,
+                    "body": body,
+                    "foldable": self.maybe_open.is_some(),
+                    "open": self.maybe_open.unwrap_or(true),
+                });
+                match templates.render(TEMPLATE, &args, page) {
+                    Ok(html) => html + "\n",
+                    Err(e) => {
+                        result = Err(e);
+                        default_markup(&self.kind, &title, &body, self.maybe_open)
+                    }
+                }
+            }
+            None => default_markup(&self.kind, &title, &body, self.maybe_open),
+        };
+
+        self.node.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+            block_type: 0,
+            literal: html.into_bytes(),
+        });
+        result
+    }
+}
+
+/// Titles callouts without a title after their kind, so `note` becomes `Note`.
+fn default_title(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars)
+            .collect::<String>()
+            .replace('-', " "),
+        None => String::new(),
+    }
+}
+
+fn default_markup(kind: &str, title: &str, body: &str, maybe_open: Option<bool>) -> String {
+    match maybe_open {
+        Some(open) => format!(
+            "<aside class=\"callout callout-{kind}\">\n<details{}>\n<summary class=\"callout-title\">{title}</summary>\n<div class=\"callout-body\">\n{body}</div>\n</details>\n</aside>\n",
+            if open { " open" } else { "" }
+        ),
+        None => format!(
+            "<aside class=\"callout callout-{kind}\">\n<p class=\"callout-title\">{title}</p>\n<div class=\"callout-body\">\n{body}</div>\n</aside>\n"
+        ),
+    }
+}
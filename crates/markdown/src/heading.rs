@@ -0,0 +1,160 @@
+use crate::highlight::escape_html;
+use libs::anyhow::Error;
+use libs::comrak::nodes::{AstNode, NodeCode, NodeHtmlBlock, NodeValue};
+use libs::comrak::{format_html, ComrakOptions};
+use libs::lazy_static;
+use libs::regex::Regex;
+
+lazy_static::lazy_static! {
+    static ref ATTRIBUTES_RE: Regex = Regex::new(r"[ \t]*\{(?P<attributes>[^{}]*)\}[ \t]*$").unwrap();
+}
+
+/// A heading with the attributes written after its text, as generated by
+/// ox-hugo:
+///
+/// ```markdown
+/// ## Getting started {#setup .wide}
+/// ```
+pub(crate) struct Heading<'a> {
+    node: &'a AstNode<'a>,
+    level: u8,
+    pub(crate) maybe_id: Option<String>,
+    classes: Vec<String>,
+    /// The other attributes, written as `key=value`.
+    attributes: Vec<(String, String)>,
+}
+
+impl<'a> Heading<'a> {
+    /// Recognizes `node` as a heading and strips its attributes from the text.
+    ///
+    /// The braces are only taken as attributes if everything in them is an
+    /// `#id`, a `.class` or a `key=value`, so a heading like `Sets {a, b}`
+    /// keeps its text.
+    pub(crate) fn parse(node: &'a AstNode<'a>) -> Option<Self> {
+        let level = match node.data.borrow().value {
+            NodeValue::Heading(ref heading) => heading.level,
+            _ => return None,
+        };
+        let mut heading = Self {
+            node,
+            level,
+            maybe_id: None,
+            classes: Vec::new(),
+            attributes: Vec::new(),
+        };
+
+        let last = match node.last_child() {
+            Some(last) => last,
+            None => return Some(heading),
+        };
+        if let NodeValue::Text(ref mut text) = last.data.borrow_mut().value {
+            let literal = String::from_utf8_lossy(text).to_string();
+            if let Some(caps) = ATTRIBUTES_RE.captures(&literal) {
+                if heading.parse_attributes(&caps["attributes"]) {
+                    text.truncate(caps.get(0).unwrap().start());
+                }
+            }
+        }
+        Some(heading)
+    }
+
+    fn parse_attributes(&mut self, attributes: &str) -> bool {
+        let mut maybe_id = None;
+        let mut classes = Vec::new();
+        let mut pairs = Vec::new();
+        for attribute in attributes.split_whitespace() {
+            if let Some(id) = attribute.strip_prefix('#').filter(|id| !id.is_empty()) {
+                maybe_id = Some(id.to_string());
+            } else if let Some(class) = attribute.strip_prefix('.').filter(|c| !c.is_empty()) {
+                classes.push(class.to_string());
+            } else if let Some((key, value)) = attribute.split_once('=') {
+                if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '-') {
+                    return false;
+                }
+                pairs.push((key.to_string(), value.trim_matches('"').to_string()));
+            } else {
+                return false;
+            }
+        }
+        self.maybe_id = maybe_id;
+        self.classes = classes;
+        self.attributes = pairs;
+        true
+    }
+
+    /// Returns the text of the heading, like comrak collects it for its ids.
+    pub(crate) fn text(&self) -> String {
+        let mut text = Vec::new();
+        collect_text(self.node, &mut text);
+        String::from_utf8_lossy(&text).trim().to_string()
+    }
+
+    pub(crate) fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Replaces the heading by its markup with the id `maybe_id`, its classes
+    /// and attributes, and with `anchor` a `¶` link to itself. Headings
+    /// without any of those are left to comrak.
+    pub(crate) fn render(
+        self,
+        maybe_id: Option<&str>,
+        anchor: bool,
+        options: &ComrakOptions,
+    ) -> Result<(), Error> {
+        if maybe_id.is_none() && self.classes.is_empty() && self.attributes.is_empty() {
+            return Ok(());
+        }
+
+        let mut content = Vec::new();
+        for child in self.node.children() {
+            format_html(child, options, &mut content)?;
+        }
+        let content = String::from_utf8_lossy(&content);
+
+        let mut attributes = String::new();
+        if let Some(id) = maybe_id {
+            attributes.push_str(&format!(r#" id="{}""#, escape_html(id)));
+        }
+        if !self.classes.is_empty() {
+            attributes.push_str(&format!(
+                r#" class="{}""#,
+                escape_html(&self.classes.join(" "))
+            ));
+        }
+        for (key, value) in &self.attributes {
+            attributes.push_str(&format!(r#" {key}="{}""#, escape_html(value)));
+        }
+        let anchor = match maybe_id.filter(|_| anchor) {
+            Some(id) => format!(
+                r##"<a class="anchor" href="#{}" aria-hidden="true">¶</a>"##,
+                escape_html(id)
+            ),
+            None => String::new(),
+        };
+
+        let level = self.level;
+        for child in self.node.children().collect::<Vec<_>>() {
+            child.detach();
+        }
+        self.node.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+            block_type: 0,
+            literal: format!("<h{level}{attributes}>{content}{anchor}</h{level}>\n").into_bytes(),
+        });
+        Ok(())
+    }
+}
+
+fn collect_text<'a>(node: &'a AstNode<'a>, output: &mut Vec<u8>) {
+    match node.data.borrow().value {
+        NodeValue::Text(ref literal) | NodeValue::Code(NodeCode { ref literal, .. }) => {
+            output.extend_from_slice(literal)
+        }
+        NodeValue::LineBreak | NodeValue::SoftBreak => output.push(b' '),
+        _ => {
+            for n in node.children() {
+                collect_text(n, output);
+            }
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::once_cell::sync::Lazy;
+use libs::syntect::easy::HighlightLines;
+use libs::syntect::highlighting::{Theme, ThemeSet};
+use libs::syntect::html::{
+    css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html,
+    ClassStyle, IncludeBackground,
+};
+use libs::syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use libs::syntect::util::LinesWithEndings;
+
+/// The prefix of the classes used for class-based output.
+const CLASS_PREFIX: &str = "hl-";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+/// Used by pages converted without a configured highlighter.
+pub(crate) static DEFAULT_HIGHLIGHTER: Lazy<Highlighter> = Lazy::new(Highlighter::default);
+
+#[derive(Clone, Debug)]
+pub struct HighlightOptions {
+    /// The name of a built-in theme, e.g. `base16-ocean.dark`, or the path
+    /// of a `.tmTheme` file.
+    pub theme: String,
+    /// A directory with additional `.sublime-syntax` files.
+    pub maybe_syntaxes: Option<PathBuf>,
+    /// Emit classes instead of inline styles; the colors are then defined by
+    /// the stylesheet returned by [`Highlighter::stylesheet`].
+    pub classes: bool,
+    /// Number the lines of every code block. Can be changed per code block
+    /// with `{linenos=false}`.
+    pub line_numbers: bool,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        Self {
+            theme: "base16-ocean.dark".to_string(),
+            maybe_syntaxes: None,
+            classes: false,
+            line_numbers: false,
+        }
+    }
+}
+
+/// Highlights code blocks.
+///
+/// Loading the syntaxes and themes is expensive, so a highlighter is created
+/// once per build and shared by all pages.
+pub struct Highlighter {
+    syntax_set: SyntaxSet,
+    theme: Theme,
+    classes: bool,
+    line_numbers: bool,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new(&HighlightOptions::default()).expect("The default theme should exist")
+    }
+}
+
+impl Highlighter {
+    pub fn new(options: &HighlightOptions) -> Result<Self, Error> {
+        let syntax_set = match options.maybe_syntaxes.as_ref() {
+            Some(path) => {
+                let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+                builder.add_from_folder(path, true).map_err(|e| {
+                    generic_error(format!("Cannot load syntaxes from {}: {e}", path.display()))
+                })?;
+                builder.build()
+            }
+            None => SyntaxSet::load_defaults_newlines(),
+        };
+
+        let theme = if options.theme.ends_with(".tmTheme") {
+            ThemeSet::get_theme(&options.theme)
+                .map_err(|e| generic_error(format!("Cannot load theme {}: {e}", options.theme)))?
+        } else {
+            let mut theme_set = ThemeSet::load_defaults();
+            match theme_set.themes.remove(&options.theme) {
+                Some(theme) => theme,
+                None => {
+                    let mut names = theme_set.themes.into_keys().collect::<Vec<_>>();
+                    names.sort();
+                    return Err(generic_error(format!(
+                        "Unknown theme `{}`, available themes are: {}",
+                        options.theme,
+                        names.join(", ")
+                    )));
+                }
+            }
+        };
+
+        Ok(Self {
+            syntax_set,
+            theme,
+            classes: options.classes,
+            line_numbers: options.line_numbers,
+        })
+    }
+
+    /// Returns the stylesheet defining the colors of the class-based output.
+    pub fn stylesheet(&self) -> Result<String, Error> {
+        css_for_theme_with_class_style(&self.theme, CLASS_STYLE).map_err(Error::from)
+    }
+
+    /// Returns the HTML of a code block whose fence has the info string
+    /// `info`, e.g. `rust {hl_lines=[2,4]}`.
+    pub fn highlight(&self, info: &str, code: &str) -> String {
+        let fence = Fence::parse(info);
+        let syntax = fence
+            .lang
+            .and_then(|lang| self.syntax_set.find_syntax_by_token(lang))
+            .or_else(|| self.syntax_set.find_syntax_by_first_line(code))
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+
+        let lines = match self.highlight_lines(syntax, code) {
+            Ok(lines) => lines,
+            Err(_) => LinesWithEndings::from(code).map(escape_html).collect(),
+        };
+
+        let mut html = String::with_capacity(code.len() * 2);
+        match self.classes {
+            true => html.push_str(&format!(r#"<pre class="{CLASS_PREFIX}code">"#)),
+            false => {
+                let style = self
+                    .theme
+                    .settings
+                    .background
+                    .map(|c| {
+                        format!(
+                            r#" style="background-color:#{:02x}{:02x}{:02x};""#,
+                            c.r, c.g, c.b
+                        )
+                    })
+                    .unwrap_or_default();
+                html.push_str(&format!("<pre{style}>"));
+            }
+        }
+        match fence.lang {
+            Some(lang) => {
+                let lang = escape_html(lang);
+                let _ = write!(html, r#"<code class="language-{lang}" data-lang="{lang}">"#);
+            }
+            None => html.push_str("<code>"),
+        }
+
+        let line_numbers = fence.maybe_line_numbers.unwrap_or(self.line_numbers);
+        for (i, line) in lines.iter().enumerate() {
+            let number = fence.line_number_start + i;
+            let (line, newline) = match line.strip_suffix('\n') {
+                Some(line) => (line, "\n"),
+                None => (line.as_str(), ""),
+            };
+            let highlighted = fence.hl_lines.iter().any(|r| r.contains(&(i + 1)));
+            html.push_str(match highlighted {
+                true => r#"<mark class="hl-line">"#,
+                false => "",
+            });
+            if line_numbers {
+                let _ = write!(html, r#"<span class="line-number">{number}</span>"#);
+            }
+            html.push_str(line);
+            html.push_str(if highlighted { "</mark>" } else { "" });
+            html.push_str(newline);
+        }
+
+        html.push_str("</code></pre>\n");
+        html
+    }
+
+    /// Returns the highlighted lines of `code`. Every line is complete, so
+    /// spans open at its end are closed and reopened on the next line.
+    fn highlight_lines(&self, syntax: &SyntaxReference, code: &str) -> Result<Vec<String>, Error> {
+        let mut lines = Vec::new();
+        if self.classes {
+            let mut parse_state = ParseState::new(syntax);
+            let mut stack = ScopeStack::new();
+            for line in LinesWithEndings::from(code) {
+                let mut html = String::new();
+                for scope in stack.as_slice() {
+                    let classes = scope
+                        .build_string()
+                        .split('.')
+                        .map(|atom| format!("{CLASS_PREFIX}{atom}"))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let _ = write!(html, r#"<span class="{classes}">"#);
+                }
+                let ops = parse_state.parse_line(line, &self.syntax_set)?;
+                let (spans, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)?;
+                html.push_str(&spans);
+                let newline = html.ends_with('\n');
+                if newline {
+                    html.pop();
+                }
+                html.push_str(&"</span>".repeat(stack.len()));
+                if newline {
+                    html.push('\n');
+                }
+                lines.push(html);
+            }
+        } else {
+            let mut highlight_lines = HighlightLines::new(syntax, &self.theme);
+            for line in LinesWithEndings::from(code) {
+                let regions = highlight_lines.highlight_line(line, &self.syntax_set)?;
+                lines.push(styled_line_to_highlighted_html(
+                    &regions,
+                    IncludeBackground::No,
+                )?);
+            }
+        }
+        Ok(lines)
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The language and the annotations of a code fence, e.g.
+/// `rust {hl_lines=[2, "4-6"], linenos=true, linenostart=10}`.
+#[derive(Debug, PartialEq)]
+struct Fence<'a> {
+    lang: Option<&'a str>,
+    /// The 1-based lines to highlight.
+    hl_lines: Vec<RangeInclusive<usize>>,
+    maybe_line_numbers: Option<bool>,
+    line_number_start: usize,
+}
+
+impl<'a> Fence<'a> {
+    fn parse(info: &'a str) -> Self {
+        let info = info.trim();
+        let (lang, annotations) = match info.find('{') {
+            Some(start) => (
+                info[..start].trim(),
+                info[start + 1..].trim_end().trim_end_matches('}'),
+            ),
+            None => (info.split_whitespace().next().unwrap_or_default(), ""),
+        };
+
+        let mut fence = Fence {
+            lang: Some(lang).filter(|l| !l.is_empty()),
+            hl_lines: Vec::new(),
+            maybe_line_numbers: None,
+            line_number_start: 1,
+        };
+        for (key, value) in split_annotations(annotations) {
+            match key {
+                "hl_lines" => fence.hl_lines = parse_line_ranges(value),
+                "linenos" => fence.maybe_line_numbers = value.parse().ok(),
+                "linenostart" => fence.line_number_start = value.parse().unwrap_or(1),
+                _ => {}
+            }
+        }
+        fence
+    }
+}
+
+/// Splits `key=value` pairs separated by commas or spaces outside of brackets.
+fn split_annotations(annotations: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in annotations.char_indices().chain([(annotations.len(), ',')]) {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' | ' ' if depth == 0 => {
+                if let Some((key, value)) = annotations[start..i].split_once('=') {
+                    pairs.push((key.trim(), value.trim()));
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    pairs
+}
+
+/// Parses `[2, 4-6]` or `[2, "4-6"]` into line ranges.
+fn parse_line_ranges(value: &str) -> Vec<RangeInclusive<usize>> {
+    value
+        .trim_matches(['[', ']'])
+        .split([',', ' '])
+        .map(|item| item.trim().trim_matches('"'))
+        .filter_map(|item| match item.split_once('-') {
+            Some((from, to)) => Some(from.trim().parse().ok()?..=to.trim().parse().ok()?),
+            None => item.parse().ok().map(|line| line..=line),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fence() {
+        assert_eq!(
+            Fence::parse(r#"rust {hl_lines=[2, "4-6"], linenos=true, linenostart=10}"#),
+            Fence {
+                lang: Some("rust"),
+                hl_lines: vec![2..=2, 4..=6],
+                maybe_line_numbers: Some(true),
+                line_number_start: 10,
+            }
+        );
+        assert_eq!(Fence::parse("").lang, None);
+    }
+
+    #[test]
+    fn test_highlight_with_classes() {
+        let highlighter = Highlighter::new(&HighlightOptions {
+            classes: true,
+            line_numbers: true,
+            ..HighlightOptions::default()
+        })
+        .unwrap();
+
+        let html = highlighter.highlight("rust {hl_lines=[2]}", "/* a\nb */\nfn main() {}\n");
+
+        assert!(html.starts_with(
+            r#"<pre class="hl-code"><code class="language-rust" data-lang="rust"><span class="line-number">1</span>"#
+        ));
+        // The comment spans two lines, so its span is reopened on the second line.
+        assert!(html.contains(
+            r#"<mark class="hl-line"><span class="line-number">2</span><span class="hl-source hl-rust"><span class="hl-comment hl-block hl-rust">b "#
+        ));
+        assert!(highlighter.stylesheet().unwrap().contains(".hl-code {"));
+    }
+}
@@ -1,4 +1,21 @@
+mod callout;
+mod heading;
+mod highlight;
 mod markdown;
+mod math;
+mod raw_html;
+mod schema;
 mod shortcode;
+mod summary;
+mod wiki_links;
 
-pub use markdown::{handle_shortcodes, markdown_to_html, string_to_html, MarkdownOptions};
+pub use highlight::{HighlightOptions, Highlighter};
+pub use markdown::{
+    handle_shortcodes, markdown_to_html, page_options, string_to_html, MarkdownOptions,
+};
+pub use math::handle_math;
+pub use raw_html::RawHtml;
+pub use schema::{validate_front_matter, FieldType, FrontMatterSchema};
+pub use shortcode::{BodyLinks, ShortcodeContext, ShortcodeTemplates, SourceMap};
+pub use summary::{summarize, Summary};
+pub use wiki_links::handle_wiki_links;
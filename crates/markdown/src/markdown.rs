@@ -1,61 +1,189 @@
-use crate::shortcode::parse_for_shortcodes;
-use berlin_core::{FrontMatter, ModuleSpecifier};
-use libs::comrak::nodes::{AstNode, NodeValue};
-use libs::comrak::plugins::syntect::SyntectAdapter;
-use libs::comrak::{format_html_with_plugins, parse_document, Arena, ComrakOptions, ComrakPlugins};
+use crate::callout::Callout;
+use crate::heading::Heading;
+use crate::highlight::DEFAULT_HIGHLIGHTER;
+use crate::raw_html::RawHtml;
+use crate::shortcode::{
+    diagnostic_at, page_context, parse_for_shortcodes, replace_shortcodes, ShortcodeContext,
+    SourceMap,
+};
+use crate::summary::is_more_marker;
+use berlin_core::{
+    extract_yaml, is_content_link, parse_front_matter, split_front_matter, FrontMatter,
+    ModuleSpecifier, Severity, TocEntry,
+};
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+use libs::comrak::{
+    format_html_with_plugins, parse_document, Anchorizer, Arena, ComrakOptions, ComrakPlugins,
+};
 use libs::lazy_static;
 pub use libs::regex::Regex;
-use serde::Deserialize;
-use std::borrow::BorrowMut;
+use libs::serde_yaml::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::sync::Arc;
 
 lazy_static::lazy_static! {
     static ref RELREF_RE: Regex = Regex::new(r#"\[(?P<label>.+)\]\(\{\{< relref "(?P<name>.*)" >\}\}\)"#).unwrap();
 }
 
-#[derive(Clone)]
-pub struct MarkdownOptions;
+/// The options pages are converted with, read from the `[markdown]` section
+/// of `berlin.toml`. A page can override them with the `markdown` key of its
+/// front matter, e.g. `markdown: { smart_punctuation: true }`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownOptions {
+    /// Convert quotes to curly quotes, `--` and `---` to dashes and `...` to
+    /// an ellipsis.
+    pub smart_punctuation: bool,
+    /// Render soft line breaks as `<br>`.
+    pub hard_breaks: bool,
+    /// Add ids to headings, so they can be linked to.
+    pub header_ids: bool,
+    /// The prefix of the ids added to headings.
+    pub header_id_prefix: String,
+    /// Add a `¶` link to itself to every heading with an id.
+    pub heading_anchors: bool,
+    /// Escape the HTML tags disallowed by GitHub, like `<script>`.
+    pub tagfilter: bool,
+    /// Keep raw HTML and potentially dangerous links.
+    pub unsafe_html: bool,
+    pub superscript: bool,
+    pub strikethrough: bool,
+    pub table: bool,
+    pub autolink: bool,
+    pub tasklist: bool,
+    pub footnotes: bool,
+    pub description_lists: bool,
+    /// The number of words of the summary of pages without a
+    /// `<!-- more -->` line.
+    pub summary_words: usize,
+    /// The reading speed the reading time of pages is computed with.
+    pub words_per_minute: usize,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            smart_punctuation: false,
+            hard_breaks: false,
+            header_ids: true,
+            header_id_prefix: "".to_string(),
+            heading_anchors: false,
+            tagfilter: true,
+            unsafe_html: true,
+            superscript: false,
+            strikethrough: true,
+            table: true,
+            autolink: true,
+            tasklist: true,
+            footnotes: true,
+            description_lists: true,
+            summary_words: 50,
+            words_per_minute: 200,
+        }
+    }
+}
 
 impl MarkdownOptions {
-    pub fn default() -> ComrakOptions {
+    /// Returns these options with the fields set in `overrides` replaced.
+    pub fn with_overrides(&self, overrides: Value) -> Result<Self, Error> {
+        let mut options = libs::serde_yaml::to_value(self)?;
+        match (&mut options, overrides) {
+            (Value::Mapping(options), Value::Mapping(overrides)) => {
+                for (key, value) in overrides {
+                    options.insert(key, value);
+                }
+            }
+            (_, Value::Null) => {}
+            _ => return Err(generic_error("markdown options should be an object")),
+        }
+        Ok(libs::serde_yaml::from_value(options)?)
+    }
+
+    pub fn to_comrak_options(&self) -> ComrakOptions {
         let mut options = ComrakOptions::default();
         options.extension.front_matter_delimiter = Some("---".to_owned());
-        options.extension.table = true;
-        options.extension.strikethrough = true;
-        options.extension.tagfilter = true;
-        options.render.unsafe_ = true;
-        options.extension.autolink = true;
-        options.extension.tasklist = true;
-        options.extension.header_ids = Some("".to_string());
-        options.extension.footnotes = true;
-        options.extension.description_lists = true;
+        options.extension.table = self.table;
+        options.extension.strikethrough = self.strikethrough;
+        options.extension.tagfilter = self.tagfilter;
+        options.extension.autolink = self.autolink;
+        options.extension.tasklist = self.tasklist;
+        options.extension.superscript = self.superscript;
+        options.extension.header_ids = self.header_ids.then(|| self.header_id_prefix.clone());
+        options.extension.footnotes = self.footnotes;
+        options.extension.description_lists = self.description_lists;
+        options.parse.smart = self.smart_punctuation;
+        options.render.hardbreaks = self.hard_breaks;
+        options.render.unsafe_ = self.unsafe_html;
 
         options
     }
 }
 
-pub fn handle_shortcodes(specifier: &ModuleSpecifier, content: &mut String) {
-    if let Ok((_name, mut shortcodes)) = parse_for_shortcodes(&specifier, &content) {
-        // the ranges of the shortcodes are computed based on the original file
-        // and differences in ranges after a rendering step of a short code
-        // are not considered.
-        // So applying the shortcodes sequentially without taking the
-        // change of ranges into considerations leads to malformed output.
-        // We could add logic to update the ranges by an offset that gets updated
-        // after the application of each shortcode
-        // OR
-        // we simply reverse the array and update the file bottom-up instead of
-        // top-down.
-        shortcodes.reverse();
-        for sc in shortcodes {
-            if let Some(ref body) = sc.body {
-                content.replace_range(sc.span, body);
+/// Returns the options the page at `specifier` is converted with: the
+/// options of `ctx`, overridden by the `markdown` key of its front matter.
+///
+/// Invalid overrides are reported as errors and ignored.
+pub fn page_options(
+    specifier: &ModuleSpecifier,
+    source: &str,
+    ctx: &ShortcodeContext,
+) -> MarkdownOptions {
+    match front_matter_options(source, &ctx.markdown_options) {
+        Ok(options) => options,
+        Err(e) => {
+            let start = source.find("markdown:").unwrap_or_default();
+            ctx.diagnostics.push(diagnostic_at(
+                specifier,
+                source,
+                Severity::Error,
+                start,
+                "markdown:",
+                format!("invalid markdown options in front matter: {e}"),
+            ));
+            ctx.markdown_options.clone()
+        }
+    }
+}
+
+/// Returns `options` overridden by the `markdown` key of the front matter of
+/// `source`, failing if the overrides are invalid.
+pub(crate) fn front_matter_options(
+    source: &str,
+    options: &MarkdownOptions,
+) -> Result<MarkdownOptions, Error> {
+    match libs::serde_yaml::from_str::<Value>(&extract_yaml(source)) {
+        Ok(Value::Mapping(mut front_matter)) => {
+            match front_matter.remove(&Value::String("markdown".to_string())) {
+                Some(overrides) => options.with_overrides(overrides),
+                None => Ok(options.clone()),
             }
         }
+        _ => Ok(options.clone()),
+    }
+}
+
+/// Replaces the shortcodes of `content` by their rendered bodies and returns
+/// where they were.
+pub fn handle_shortcodes(
+    specifier: &ModuleSpecifier,
+    content: &mut String,
+    ctx: &ShortcodeContext,
+) -> SourceMap {
+    match parse_for_shortcodes(specifier, content, ctx) {
+        Ok((_name, shortcodes)) => {
+            let source_map = SourceMap::new(&shortcodes);
+            replace_shortcodes(content, shortcodes);
+            source_map
+        }
+        Err(_) => SourceMap::default(),
     }
 }
 
-pub fn string_to_html(source: &String, options: &ComrakOptions) -> String {
+pub fn string_to_html(source: &String, options: &MarkdownOptions) -> String {
+    let options = options.to_comrak_options();
     let arena = Arena::new();
     let mut html = Vec::new();
     let plugins = ComrakPlugins::default();
@@ -64,17 +192,113 @@ pub fn string_to_html(source: &String, options: &ComrakOptions) -> String {
     String::from_utf8(html).unwrap()
 }
 
+/// Converts the page at `specifier` to HTML with `options`, usually the
+/// result of [`page_options`].
+///
+/// Links to other markdown and org files are replaced by the permalinks of
+/// their pages; links not found in the content index are reported as errors.
+/// Returns the permalinks of all pages the page links to.
+///
+/// Headings get the id, classes and attributes written after their text like
+/// `{#id .class}`, and otherwise an id generated by comrak if `header_ids` is
+/// enabled. Also returns the table of contents of the page, made of the
+/// headings with an id.
+///
+/// Blockquotes starting with a marker like `[!NOTE]` become callouts.
+///
+/// Code blocks are highlighted by the highlighter of `ctx`, or by the
+/// default highlighter if there is none.
+///
+/// Without `unsafe_html`, only the raw HTML written in the page is omitted;
+/// the HTML generated for it, like the above or the [`RawHtml`] of `ctx`, is
+/// kept.
 pub fn markdown_to_html(
+    specifier: &ModuleSpecifier,
     source: Arc<str>,
-    options: ComrakOptions,
-) -> (Option<FrontMatter>, Vec<u8>) {
+    options: &MarkdownOptions,
+    ctx: &ShortcodeContext,
+) -> (Option<FrontMatter>, Vec<u8>, Vec<String>, Vec<TocEntry>) {
     // let preprocessed_source = RELREF_RE.replace_all(&content, |caps: &Captures| {
     //     format!("[{}](/notes/{}.html)", &caps["label"], &caps["name"])
     // });
 
-    let mut maybe_front_matter = None;
+    let (maybe_front_matter, html, mut links, toc) = convert(specifier, source, options, ctx, true);
+    // The links of the bodies of block shortcodes are part of the page.
+    links.extend(ctx.body_links.take());
+    (maybe_front_matter, html, links, toc)
+}
+
+/// Converts the body of a block shortcode of the page `specifier` to HTML
+/// like the page, and returns the permalinks it links to. The problems found
+/// are located in `body`.
+pub(crate) fn body_to_html(
+    specifier: &ModuleSpecifier,
+    body: &str,
+    options: &MarkdownOptions,
+    ctx: &ShortcodeContext,
+) -> (String, Vec<String>) {
+    let (_, html, links, _) = convert(specifier, Arc::from(body), options, ctx, false);
+    (String::from_utf8(html).unwrap(), links)
+}
+
+fn convert(
+    specifier: &ModuleSpecifier,
+    source: Arc<str>,
+    options: &MarkdownOptions,
+    ctx: &ShortcodeContext,
+    has_front_matter: bool,
+) -> (Option<FrontMatter>, Vec<u8>, Vec<String>, Vec<TocEntry>) {
+    let parsed = match has_front_matter {
+        true => parse_front_matter(&source),
+        false => Ok(None),
+    };
+    let maybe_front_matter = match parsed {
+        Ok(Some(Value::Null)) | Ok(None) => None,
+        Ok(Some(value)) => match libs::serde_yaml::from_value::<FrontMatter>(value) {
+            Ok(front_matter) => Some(front_matter),
+            Err(e) => {
+                report_front_matter_error(
+                    specifier,
+                    &source,
+                    format!("invalid front matter: {e}"),
+                    ctx,
+                );
+                None
+            }
+        },
+        Err(e) => {
+            report_front_matter_error(specifier, &source, e.to_string(), ctx);
+            None
+        }
+    };
+    let mut links = Vec::new();
+    let mut unresolved_links = Vec::new();
+    let mut callouts = Vec::new();
+    let mut headings = Vec::new();
+    let maybe_referrer = specifier.to_file_path().ok();
+    let content_index = ctx.content_index.read();
+    let highlighter = ctx
+        .maybe_highlighter
+        .as_deref()
+        .unwrap_or(&DEFAULT_HIGHLIGHTER);
+    let heading_anchors = options.heading_anchors;
+    let unsafe_html = options.unsafe_html;
+    // The raw HTML of the page is omitted while parsing if it is unsafe, the
+    // HTML generated for headings, code blocks, callouts and math is always
+    // rendered.
+    let mut options = options.to_comrak_options();
+    options.render.unsafe_ = true;
     let arena = Arena::new();
-    let root = parse_document(&arena, &source, &options);
+    // comrak only knows YAML front matter, so every front matter is blanked
+    // out, keeping its lines for the positions of the diagnostics.
+    let content = match split_front_matter(&source).filter(|_| has_front_matter) {
+        Some((_, _, range)) => {
+            let blank = source[range.clone()].replace(|c| c != '\n', "");
+            format!("{}{blank}{}", &source[..range.start], &source[range.end..])
+        }
+        None => source.to_string(),
+    };
+    let root = parse_document(&arena, &content, &options);
 
     fn iter_nodes<'a, F>(node: &'a AstNode<'a>, f: &mut F)
     where
@@ -87,38 +311,407 @@ pub fn markdown_to_html(
     }
 
     iter_nodes(root, &mut |node| {
-        if let NodeValue::FrontMatter(ref mut text) = node.data.borrow_mut().value {
-            let mut documents = libs::serde_yaml::Deserializer::from_slice(text);
-            if let Some(document) = documents.nth(1) {
-                maybe_front_matter = FrontMatter::deserialize(document).ok();
-            }
+        if !unsafe_html {
+            omit_raw_html(node);
+        }
+        if let Some(callout) = Callout::parse(node) {
+            callouts.push(callout);
         }
-        if let NodeValue::Text(ref mut text) = node.data.borrow_mut().value {
-            if let Some(parent) = node.parent().borrow_mut() {
-                // ox-hugo generates anchor links of the form `# Header {#header},
-                // comrak interprets the whole line as text, which is not wanted here.
-                // So we need to remove it`
-                if let NodeValue::Heading(_) = parent.data.borrow().value {
-                    for i in 0..text.len() {
-                        let j = match i {
-                            0 => 0,
-                            n => n - 1,
-                        };
-                        if text[i] == 123 && text[j] == 32 {
-                            *text = text[..j].to_vec();
-                            break;
-                        }
+        if let Some(heading) = Heading::parse(node) {
+            headings.push(heading);
+        }
+        let maybe_highlighted = match node.data.borrow().value {
+            NodeValue::CodeBlock(ref code_block) => Some(highlighter.highlight(
+                &String::from_utf8_lossy(&code_block.info),
+                &String::from_utf8_lossy(&code_block.literal),
+            )),
+            _ => None,
+        };
+        if let Some(html) = maybe_highlighted {
+            node.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 0,
+                literal: html.into_bytes(),
+            });
+        }
+        if let NodeValue::Link(ref mut link) = node.data.borrow_mut().value {
+            let url = String::from_utf8_lossy(&link.url).to_string();
+            if is_content_link(&url) {
+                match maybe_referrer
+                    .as_ref()
+                    .and_then(|referrer| content_index.resolve(referrer, &url))
+                {
+                    Some(permalink) => {
+                        link.url = permalink.clone().into_bytes();
+                        links.push(permalink);
                     }
+                    None => unresolved_links.push((url, start_line(node))),
                 }
+            } else if content_index.is_permalink(&url) {
+                links.push(url);
             }
         }
     });
 
+    for (url, line) in unresolved_links {
+        // Inline nodes do not know their position, so look for the link on
+        // the lines of the enclosing block.
+        let line_offset = line_offset(&source, line);
+        let start = source[line_offset..]
+            .find(&format!("]({url}"))
+            .map_or(line_offset, |i| line_offset + i + 2);
+        ctx.diagnostics.push(diagnostic_at(
+            specifier,
+            &source,
+            Severity::Error,
+            start,
+            &url,
+            format!("link target not found: {url}"),
+        ));
+    }
+
+    // The generated ids are assigned like comrak does when rendering the
+    // headings, skipping the ids given in the page, before the callouts so
+    // headings in them are rendered too.
+    let mut toc = Vec::new();
+    let mut anchorizer = Anchorizer::new();
+    let custom_ids = headings
+        .iter()
+        .filter_map(|heading| heading.maybe_id.clone())
+        .collect::<HashSet<_>>();
+    for heading in headings {
+        let text = RawHtml::strip(&heading.text());
+        let maybe_id = heading.maybe_id.clone().or_else(|| {
+            let prefix = options.extension.header_ids.as_ref()?;
+            loop {
+                let id = format!("{prefix}{}", anchorizer.anchorize(text.clone()));
+                if !custom_ids.contains(&id) {
+                    break Some(id);
+                }
+            }
+        });
+        if let Some(id) = maybe_id.as_ref() {
+            add_to_toc(
+                &mut toc,
+                TocEntry {
+                    level: heading.level(),
+                    id: id.clone(),
+                    text,
+                    children: Vec::new(),
+                },
+            );
+        }
+        heading
+            .render(maybe_id.as_deref(), heading_anchors, &options)
+            .unwrap();
+    }
+
+    if !callouts.is_empty() {
+        let page = match ctx.maybe_templates {
+            Some(_) => page_context(specifier, &source),
+            None => libs::tera::Value::Null,
+        };
+        // Nested callouts come last, render them first so they are part of
+        // the body of the enclosing callout.
+        for callout in callouts.into_iter().rev() {
+            let start = line_offset(&source, callout.line);
+            if let Err(e) = callout.render(&options, ctx.maybe_templates.as_deref(), &page) {
+                ctx.diagnostics.push(diagnostic_at(
+                    specifier,
+                    &source,
+                    Severity::Error,
+                    start,
+                    &source[start..],
+                    e.to_string(),
+                ));
+            }
+        }
+    }
+
     let mut html = Vec::new();
-    let mut plugins = ComrakPlugins::default();
-    let adapter = SyntectAdapter::new("base16-ocean.dark");
-    plugins.render.codefence_syntax_highlighter = Some(&adapter);
+    let plugins = ComrakPlugins::default();
     format_html_with_plugins(root, &options, &mut html, &plugins).unwrap();
+    let html = ctx.raw_html.restore(&String::from_utf8_lossy(&html));
 
-    (maybe_front_matter, html)
+    (maybe_front_matter, html.into_bytes(), links, toc)
+}
+
+/// Replaces the raw HTML of `node` like comrak does without `unsafe_`, except
+/// a `<!-- more -->` line, and drops its URL if it is potentially dangerous.
+fn omit_raw_html<'a>(node: &'a AstNode<'a>) {
+    const OMITTED: &[u8] = b"<!-- raw HTML omitted -->";
+    match node.data.borrow_mut().value {
+        NodeValue::HtmlBlock(ref mut html_block)
+            if !is_more_marker(&String::from_utf8_lossy(&html_block.literal)) =>
+        {
+            html_block.literal = OMITTED.to_vec()
+        }
+        NodeValue::HtmlInline(ref mut literal) => *literal = OMITTED.to_vec(),
+        NodeValue::Link(ref mut link) | NodeValue::Image(ref mut link)
+            if is_dangerous_url(&link.url) =>
+        {
+            link.url.clear()
+        }
+        _ => {}
+    }
+}
+
+/// Returns whether `url` runs a script or reads a file, like comrak checks
+/// it: `javascript:`, `vbscript:`, `file:` and `data:` URLs but images.
+fn is_dangerous_url(url: &[u8]) -> bool {
+    let url = String::from_utf8_lossy(url).to_lowercase();
+    ["javascript:", "vbscript:", "file:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+        || url.strip_prefix("data:").is_some_and(|data| {
+            !["image/png", "image/gif", "image/jpeg", "image/webp"]
+                .iter()
+                .any(|image| data.starts_with(image))
+        })
+}
+
+fn report_front_matter_error(
+    specifier: &ModuleSpecifier,
+    source: &str,
+    message: String,
+    ctx: &ShortcodeContext,
+) {
+    let start = source.len() - source.trim_start().len();
+    ctx.diagnostics.push(diagnostic_at(
+        specifier,
+        source,
+        Severity::Error,
+        start,
+        source[start..].lines().next().unwrap_or_default(),
+        message,
+    ));
+}
+
+/// Adds `entry` below the last entry of `toc` with a lower level, if any.
+fn add_to_toc(toc: &mut Vec<TocEntry>, entry: TocEntry) {
+    match toc.last_mut() {
+        Some(last) if last.level < entry.level => add_to_toc(&mut last.children, entry),
+        _ => toc.push(entry),
+    }
+}
+
+/// Returns the byte offset of the 1-based `line` in `source`.
+fn line_offset(source: &str, line: usize) -> usize {
+    source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(|l| l.len())
+        .sum()
+}
+
+/// Returns the line of the block containing the inline `node`.
+fn start_line<'a>(node: &'a AstNode<'a>) -> usize {
+    node.ancestors()
+        .skip(1)
+        .map(|n| n.data.borrow().start_line as usize)
+        .find(|line| *line > 0)
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::handle_math;
+    use berlin_core::MediaType;
+
+    #[test]
+    fn test_page_options() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let source =
+            "---\ntitle: A\nmarkdown:\n  smart_punctuation: true\n---\n\"Quoted\" -- text\n";
+
+        let options = page_options(&specifier, source, &ctx);
+        let (_, html, _, _) = markdown_to_html(&specifier, Arc::from(source), &options, &ctx);
+
+        assert!(options.smart_punctuation);
+        assert_eq!(String::from_utf8(html).unwrap(), "<p>“Quoted” – text</p>\n");
+
+        let source = "---\nmarkdown:\n  smart: true\n---\n";
+        assert!(!page_options(&specifier, source, &ctx).smart_punctuation);
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 1));
+    }
+
+    #[test]
+    fn test_callouts() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let source = concat!(
+            "> [!NOTE]\n> Plain *note*.\n\n",
+            "> [!warning]- Mind the `gap`\n> Folded.\n>\n> > [!TIP]+\n> > Nested.\n\n",
+            "> Just a quote.\n",
+        );
+
+        let (_, html, _, _) =
+            markdown_to_html(&specifier, Arc::from(source), &ctx.markdown_options, &ctx);
+
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            concat!(
+                "<aside class=\"callout callout-note\">\n<p class=\"callout-title\">Note</p>\n",
+                "<div class=\"callout-body\">\n<p>Plain <em>note</em>.</p>\n</div>\n</aside>\n",
+                "<aside class=\"callout callout-warning\">\n<details>\n",
+                "<summary class=\"callout-title\">Mind the <code>gap</code></summary>\n",
+                "<div class=\"callout-body\">\n<p>Folded.</p>\n",
+                "<aside class=\"callout callout-tip\">\n<details open>\n",
+                "<summary class=\"callout-title\">Tip</summary>\n",
+                "<div class=\"callout-body\">\n<p>Nested.</p>\n</div>\n</details>\n</aside>\n",
+                "</div>\n</details>\n</aside>\n",
+                "<blockquote>\n<p>Just a quote.</p>\n</blockquote>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_omit_raw_html_only() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let options = MarkdownOptions {
+            unsafe_html: false,
+            ..MarkdownOptions::default()
+        };
+        let mut content = concat!(
+            "## Setup {#setup}\n## Setup\n\n",
+            "```rust\nfn main() {}\n```\n\n",
+            "> [!NOTE]\n> Euler $e^{i\\pi}$.\n\n",
+            "<script>alert(1)</script>\n\n",
+            "A <b>bold</b> [link](javascript:alert(1)).\n",
+        )
+        .to_string();
+        handle_math(&specifier, &mut content, MediaType::Markdown, &ctx);
+
+        let (_, html, _, _) = markdown_to_html(&specifier, content.into(), &options, &ctx);
+        let html = String::from_utf8(html).unwrap();
+
+        assert!(html.starts_with("<h2 id=\"setup\">Setup</h2>\n<h2 id=\"setup-1\">Setup</h2>\n"));
+        assert!(html.contains("<pre"));
+        assert!(!html.contains("fn main() {}"));
+        assert!(html.contains("<aside class=\"callout callout-note\">"));
+        assert!(html.contains("<math"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<!-- raw HTML omitted -->\n<p>A <!-- raw HTML omitted -->bold"));
+        assert!(html.contains("<a href=\"\">link</a>"));
+    }
+
+    #[test]
+    fn test_keep_more_marker_without_unsafe_html() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let options = MarkdownOptions {
+            unsafe_html: false,
+            summary_words: 3,
+            ..MarkdownOptions::default()
+        };
+        let content = "Intro.\n\n<!-- more -->\n\n<!-- note -->\n\nThe rest of it.\n";
+
+        let (_, html, _, _) = markdown_to_html(&specifier, content.into(), &options, &ctx);
+        let html = String::from_utf8(html).unwrap();
+
+        assert!(html.contains("<!-- more -->\n<!-- raw HTML omitted -->"));
+        assert_eq!(crate::summarize(&html, &options).html, "<p>Intro.</p>");
+    }
+
+    #[test]
+    fn test_toc() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let source = "# Intro\n## The `code`\n### Deep\n## Setup\n# Intro\n";
+
+        let (_, html, _, toc) =
+            markdown_to_html(&specifier, Arc::from(source), &ctx.markdown_options, &ctx);
+
+        let entry = |level, id: &str, text: &str, children| TocEntry {
+            level,
+            id: id.to_string(),
+            text: text.to_string(),
+            children,
+        };
+        assert_eq!(
+            toc,
+            vec![
+                entry(
+                    1,
+                    "intro",
+                    "Intro",
+                    vec![
+                        entry(
+                            2,
+                            "the-code",
+                            "The code",
+                            vec![entry(3, "deep", "Deep", vec![])]
+                        ),
+                        entry(2, "setup", "Setup", vec![]),
+                    ]
+                ),
+                entry(1, "intro-1", "Intro", vec![]),
+            ]
+        );
+        // The ids match the ones of the rendered headings.
+        assert!(String::from_utf8(html)
+            .unwrap()
+            .contains(r#"<h1 id="intro-1">Intro</h1>"#));
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let toml = "+++\ntitle = \"Notes\"\ndate = 2023-04-03\ntags = [\"rust\"]\n+++\n# Notes\n";
+        let json = "{\n  \"title\": \"Notes\",\n  \"date\": \"2023-04-03\",\n  \"tags\": [\"rust\"]\n}\n# Notes\n";
+
+        for source in [toml, json] {
+            let (maybe_front_matter, html, _, _) =
+                markdown_to_html(&specifier, Arc::from(source), &ctx.markdown_options, &ctx);
+            let front_matter = maybe_front_matter.unwrap();
+            assert_eq!(front_matter.title.as_deref(), Some("Notes"));
+            assert_eq!(front_matter.published.as_deref(), Some("2023-04-03"));
+            assert_eq!(front_matter.tags, Some(vec!["rust".to_string()]));
+            assert_eq!(
+                String::from_utf8(html).unwrap(),
+                "<h1 id=\"notes\">Notes</h1>\n"
+            );
+            assert!(extract_yaml(source).contains("title: Notes\n"));
+        }
+
+        let source = "+++\ntitle = \"Notes\"\ntags = \"rust\"\n+++\n[a](missing.md)\n";
+        let (maybe_front_matter, _, _, _) =
+            markdown_to_html(&specifier, Arc::from(source), &ctx.markdown_options, &ctx);
+        assert!(maybe_front_matter.is_none());
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].message.starts_with("invalid front matter:"));
+        assert_eq!(diagnostics[0].line, 1);
+        // The lines of the front matter are kept for the positions.
+        assert_eq!(diagnostics[1].line, 5);
+    }
+
+    #[test]
+    fn test_heading_attributes() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let source = "# Old *deep* link {#custom-id .wide}\n## Sets {a, b}\n";
+        let options = MarkdownOptions {
+            heading_anchors: true,
+            ..MarkdownOptions::default()
+        };
+
+        let (_, html, _, toc) = markdown_to_html(&specifier, Arc::from(source), &options, &ctx);
+
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            concat!(
+                r##"<h1 id="custom-id" class="wide">Old <em>deep</em> link<a class="anchor" href="#custom-id" aria-hidden="true">¶</a></h1>"##,
+                "\n",
+                r##"<h2 id="sets-a-b">Sets {a, b}<a class="anchor" href="#sets-a-b" aria-hidden="true">¶</a></h2>"##,
+                "\n",
+            )
+        );
+        assert_eq!(toc[0].text, "Old deep link");
+    }
 }
@@ -0,0 +1,1323 @@
+use std::mem;
+
+/// Converts the LaTeX math `tex` to MathML.
+///
+/// Covers the commands commonly used in notes: fractions, roots, scripts,
+/// accents, fonts, `\left`/`\right`, matrices, cases and aligned equations,
+/// and the usual symbols. Unsupported commands are rendered as `<merror>`
+/// and returned, along with other problems, as the second element.
+///
+/// Every ASCII punctuation character of the text content is written as a
+/// character reference, so the markup is safe to embed in markdown.
+pub(crate) fn latex_to_mathml(tex: &str, display: bool) -> (String, Vec<String>) {
+    let mut parser = Parser::new(tex, display);
+    let items = parser.parse_top();
+
+    let mathml = format!(
+        r#"<math xmlns="http://www.w3.org/1998/Math/MathML"{}><semantics>{}<annotation encoding="application/x-tex">{}</annotation></semantics></math>"#,
+        if display { r#" display="block""# } else { "" },
+        mrow(items),
+        escape(tex),
+    );
+    (mathml, parser.errors)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push_str(&format!("&#x{:X};", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn mrow(items: Vec<String>) -> String {
+    match items.len() {
+        1 => items.into_iter().next().unwrap(),
+        _ => format!("<mrow>{}</mrow>", items.concat()),
+    }
+}
+
+fn mo(c: &str) -> String {
+    format!("<mo>{}</mo>", escape(c))
+}
+
+fn mspace(width: &str) -> String {
+    format!(r#"<mspace width="{width}"/>"#)
+}
+
+fn fence(delimiter: &str) -> String {
+    match delimiter {
+        "" => String::new(),
+        delimiter => format!(
+            r#"<mo fence="true" stretchy="true">{}</mo>"#,
+            escape(delimiter)
+        ),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Font {
+    Normal,
+    Bold,
+    Italic,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+impl Font {
+    /// Returns the character of the Mathematical Alphanumeric Symbols block
+    /// for `c` in this font.
+    fn style(self, c: char) -> char {
+        let exception = match (self, c) {
+            (Font::Italic, 'h') => Some('ℎ'),
+            (Font::Script, _) => match c {
+                'B' => Some('ℬ'),
+                'E' => Some('ℰ'),
+                'F' => Some('ℱ'),
+                'H' => Some('ℋ'),
+                'I' => Some('ℐ'),
+                'L' => Some('ℒ'),
+                'M' => Some('ℳ'),
+                'R' => Some('ℛ'),
+                'e' => Some('ℯ'),
+                'g' => Some('ℊ'),
+                'o' => Some('ℴ'),
+                _ => None,
+            },
+            (Font::Fraktur, _) => match c {
+                'C' => Some('ℭ'),
+                'H' => Some('ℌ'),
+                'I' => Some('ℑ'),
+                'R' => Some('ℜ'),
+                'Z' => Some('ℨ'),
+                _ => None,
+            },
+            (Font::DoubleStruck, _) => match c {
+                'C' => Some('ℂ'),
+                'H' => Some('ℍ'),
+                'N' => Some('ℕ'),
+                'P' => Some('ℙ'),
+                'Q' => Some('ℚ'),
+                'R' => Some('ℝ'),
+                'Z' => Some('ℤ'),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(c) = exception {
+            return c;
+        }
+
+        let (upper, lower, maybe_digit) = match self {
+            Font::Normal => return c,
+            Font::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+            Font::Italic => (0x1D434, 0x1D44E, None),
+            Font::Script => (0x1D49C, 0x1D4B6, None),
+            Font::Fraktur => (0x1D504, 0x1D51E, None),
+            Font::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+            Font::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+            Font::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+        };
+        let code_point = match c {
+            'A'..='Z' => upper + (c as u32 - 'A' as u32),
+            'a'..='z' => lower + (c as u32 - 'a' as u32),
+            '0'..='9' => match maybe_digit {
+                Some(digit) => digit + (c as u32 - '0' as u32),
+                None => return c,
+            },
+            _ => return c,
+        };
+        char::from_u32(code_point).unwrap_or(c)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    Command(String),
+    Eof,
+}
+
+impl Token {
+    /// Checks whether the token ends the current list of elements.
+    fn is_stop(&self) -> bool {
+        match self {
+            Token::Eof | Token::Char('}') | Token::Char('&') => true,
+            Token::Command(name) => matches!(name.as_str(), "\\" | "right" | "middle" | "end"),
+            Token::Char(_) => false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Token::Char(c) => format!("`{c}`"),
+            Token::Command(name) => format!("`\\{name}`"),
+            Token::Eof => "end of formula".to_string(),
+        }
+    }
+}
+
+/// An element and how its scripts are placed.
+struct Atom {
+    html: String,
+    /// Scripts go below and above in display style, like for `\sum`.
+    limits: bool,
+    /// A function like `\sin`, followed by an invisible function application.
+    function: bool,
+}
+
+impl Atom {
+    fn new(html: String) -> Self {
+        Self {
+            html,
+            limits: false,
+            function: false,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+    maybe_font: Option<Font>,
+    errors: Vec<String>,
+}
+
+impl Parser {
+    fn new(tex: &str, display: bool) -> Self {
+        Self {
+            chars: tex.chars().collect(),
+            pos: 0,
+            display,
+            maybe_font: None,
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        if !self.errors.contains(&message) {
+            self.errors.push(message);
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn next_token(&mut self) -> Token {
+        self.skip_whitespace();
+        let c = match self.chars.get(self.pos) {
+            Some(c) => *c,
+            None => return Token::Eof,
+        };
+        self.pos += 1;
+        if c != '\\' {
+            return Token::Char(c);
+        }
+
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            self.pos += 1;
+        }
+        if self.pos > start {
+            return Token::Command(self.chars[start..self.pos].iter().collect());
+        }
+        // A control symbol like `\,` or `\{`.
+        match self.chars.get(self.pos) {
+            Some(c) => {
+                self.pos += 1;
+                Token::Command(c.to_string())
+            }
+            None => Token::Command(String::new()),
+        }
+    }
+
+    fn peek(&mut self) -> Token {
+        let pos = self.pos;
+        let token = self.next_token();
+        self.pos = pos;
+        token
+    }
+
+    fn parse_top(&mut self) -> Vec<String> {
+        let mut items = Vec::new();
+        loop {
+            items.extend(self.parse_list());
+            match self.next_token() {
+                Token::Eof => break,
+                // Line breaks outside of environments are ignored.
+                Token::Command(name) if name == "\\" => {}
+                token => self.error(format!("unexpected {} in math", token.describe())),
+            }
+        }
+        items
+    }
+
+    /// Parses elements up to the next `}`, `&`, `\\`, `\right`, `\middle` or
+    /// `\end`, which is not consumed.
+    fn parse_list(&mut self) -> Vec<String> {
+        let mut items = Vec::new();
+        while !self.peek().is_stop() {
+            if let Some(item) = self.parse_scripted() {
+                items.push(item);
+            }
+        }
+        items
+    }
+
+    /// Parses `{...}`, expecting the `{` to be consumed already.
+    fn parse_group(&mut self) -> String {
+        let items = self.parse_list();
+        match self.peek() {
+            Token::Char('}') => {
+                self.next_token();
+            }
+            _ => self.error("missing `}` in math".to_string()),
+        }
+        mrow(items)
+    }
+
+    /// Parses an element with its subscript, superscript and primes.
+    fn parse_scripted(&mut self) -> Option<String> {
+        let base = self.parse_atom()?;
+        let mut maybe_sub = None;
+        let mut maybe_sup = None;
+        let mut primes = String::new();
+        loop {
+            match self.peek() {
+                Token::Char('_') => {
+                    self.next_token();
+                    maybe_sub = Some(self.parse_argument());
+                }
+                Token::Char('^') => {
+                    self.next_token();
+                    maybe_sup = Some(self.parse_argument());
+                }
+                Token::Char('\'') => {
+                    self.next_token();
+                    primes.push('′');
+                }
+                _ => break,
+            }
+        }
+        if !primes.is_empty() {
+            maybe_sup = Some(match maybe_sup {
+                Some(sup) => format!("<mrow>{}{sup}</mrow>", mo(&primes)),
+                None => mo(&primes),
+            });
+        }
+
+        let (sub_tag, sup_tag, subsup_tag) = match base.limits && self.display {
+            true => ("munder", "mover", "munderover"),
+            false => ("msub", "msup", "msubsup"),
+        };
+        let html = match (maybe_sub, maybe_sup) {
+            (None, None) => base.html,
+            (Some(sub), None) => format!("<{sub_tag}>{}{sub}</{sub_tag}>", base.html),
+            (None, Some(sup)) => format!("<{sup_tag}>{}{sup}</{sup_tag}>", base.html),
+            (Some(sub), Some(sup)) => {
+                format!("<{subsup_tag}>{}{sub}{sup}</{subsup_tag}>", base.html)
+            }
+        };
+        Some(match base.function {
+            true => format!("<mrow>{html}<mo>&#x2061;</mo></mrow>"),
+            false => html,
+        })
+    }
+
+    /// Parses the argument of a command or a script: a group or a single
+    /// token, so `x^12` only raises the `1`.
+    fn parse_argument(&mut self) -> String {
+        let token = self.peek();
+        if token.is_stop() {
+            self.error(format!(
+                "missing argument before {} in math",
+                token.describe()
+            ));
+            return "<mrow></mrow>".to_string();
+        }
+        match token {
+            Token::Char(c) if c.is_ascii_digit() => {
+                self.next_token();
+                self.number(c.to_string())
+            }
+            _ => self
+                .parse_atom()
+                .map_or_else(|| "<mrow></mrow>".to_string(), |atom| atom.html),
+        }
+    }
+
+    /// Parses an optional argument in brackets, like the index of `\sqrt[3]{x}`.
+    fn parse_optional_argument(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) != Some(&'[') {
+            return None;
+        }
+        let start = self.pos + 1;
+        let mut depth = 0;
+        let mut end = start;
+        while let Some(c) = self.chars.get(end) {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ']' if depth == 0 => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        let tex = self.chars[start..end.min(self.chars.len())]
+            .iter()
+            .collect::<String>();
+        self.pos = (end + 1).min(self.chars.len());
+
+        let mut parser = Parser::new(&tex, false);
+        parser.maybe_font = self.maybe_font;
+        let items = parser.parse_top();
+        for error in parser.errors {
+            self.error(error);
+        }
+        Some(mrow(items))
+    }
+
+    /// Reads the argument of a command as plain text, like the content of
+    /// `\text{...}` or the name of an environment.
+    fn parse_text_argument(&mut self) -> String {
+        self.skip_whitespace();
+        let mut text = String::new();
+        match self.chars.get(self.pos) {
+            Some('{') => self.pos += 1,
+            Some(c) => {
+                text.push(*c);
+                self.pos += 1;
+                return text;
+            }
+            None => return text,
+        }
+
+        let mut depth = 0;
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return text,
+                '}' => depth -= 1,
+                '\\' => match self.chars.get(self.pos) {
+                    Some(&next) if "{}$%&#_ ".contains(next) => {
+                        text.push(next);
+                        self.pos += 1;
+                    }
+                    _ => text.push(c),
+                },
+                c => text.push(c),
+            }
+        }
+        self.error("missing `}` in math".to_string());
+        text
+    }
+
+    fn styled(&self, text: &str) -> String {
+        match self.maybe_font {
+            Some(font) => text.chars().map(|c| font.style(c)).collect(),
+            None => text.to_string(),
+        }
+    }
+
+    fn number(&mut self, mut digits: String) -> String {
+        format!("<mn>{}</mn>", escape(&self.styled(&mem::take(&mut digits))))
+    }
+
+    fn identifier(&self, c: char) -> String {
+        match self.maybe_font {
+            Some(Font::Normal) => format!(
+                r#"<mi mathvariant="normal">{}</mi>"#,
+                escape(&c.to_string())
+            ),
+            _ => format!("<mi>{}</mi>", escape(&self.styled(&c.to_string()))),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Option<Atom> {
+        let pos = self.pos;
+        let atom = match self.next_token() {
+            Token::Eof => return None,
+            Token::Char('{') => Atom::new(self.parse_group()),
+            Token::Char('^' | '_' | '\'') => {
+                // Scripts without a base, like `^{14}C`.
+                self.pos = pos;
+                Atom::new("<mrow></mrow>".to_string())
+            }
+            Token::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut digits = c.to_string();
+                while let Some(&d) = self.chars.get(self.pos) {
+                    let is_decimal_point = d == '.'
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_some_and(|c| c.is_ascii_digit());
+                    if !d.is_ascii_digit() && !is_decimal_point {
+                        break;
+                    }
+                    digits.push(d);
+                    self.pos += 1;
+                }
+                match digits.as_str() {
+                    "." => Atom::new(mo(".")),
+                    _ => Atom::new(self.number(digits)),
+                }
+            }
+            Token::Char(c) if c.is_alphabetic() => Atom::new(self.identifier(c)),
+            Token::Char('~') => Atom::new("<mtext>&#xA0;</mtext>".to_string()),
+            Token::Char(c) => Atom::new(match c {
+                '-' => mo("−"),
+                '*' => mo("∗"),
+                '(' | ')' | '[' | ']' | '|' | '/' => {
+                    format!(r#"<mo stretchy="false">{}</mo>"#, escape(&c.to_string()))
+                }
+                '<' => mo("<"),
+                '>' => mo(">"),
+                c => mo(&c.to_string()),
+            }),
+            Token::Command(name) => return self.parse_command(&name),
+        };
+        Some(atom)
+    }
+
+    fn parse_command(&mut self, name: &str) -> Option<Atom> {
+        let html = match name {
+            "," | "thinspace" => mspace("0.1667em"),
+            ":" | ">" | "medspace" => mspace("0.2222em"),
+            ";" | "thickspace" => mspace("0.2778em"),
+            "!" | "negthinspace" => mspace("-0.1667em"),
+            "quad" => mspace("1em"),
+            "qquad" => mspace("2em"),
+            " " => "<mtext>&#xA0;</mtext>".to_string(),
+            "{" | "lbrace" => format!(r#"<mo stretchy="false">{}</mo>"#, escape("{")),
+            "}" | "rbrace" => format!(r#"<mo stretchy="false">{}</mo>"#, escape("}")),
+            "%" | "$" | "&" | "#" | "_" => {
+                format!(r#"<mi mathvariant="normal">{}</mi>"#, escape(name))
+            }
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument();
+                let denominator = self.parse_argument();
+                format!("<mfrac>{numerator}{denominator}</mfrac>")
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.parse_argument();
+                let k = self.parse_argument();
+                format!(
+                    r#"<mrow>{}<mfrac linethickness="0">{n}{k}</mfrac>{}</mrow>"#,
+                    mo("("),
+                    mo(")")
+                )
+            }
+            "sqrt" => match self.parse_optional_argument() {
+                Some(index) => format!("<mroot>{}{index}</mroot>", self.parse_argument()),
+                None => format!("<msqrt>{}</msqrt>", self.parse_argument()),
+            },
+            "left" => self.parse_fenced(),
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "Bigl" | "biggl" | "Biggl" | "bigr"
+            | "Bigr" | "biggr" | "Biggr" | "bigm" | "Bigm" | "biggm" | "Biggm" => {
+                let size = match name.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => "1.2em",
+                    "Big" => "1.623em",
+                    "bigg" => "2.047em",
+                    _ => "2.470em",
+                };
+                let delimiter = self.parse_delimiter();
+                format!(
+                    r#"<mo minsize="{size}" maxsize="{size}">{}</mo>"#,
+                    escape(&delimiter)
+                )
+            }
+            "text" | "textrm" | "textup" | "textnormal" | "textit" | "mbox" | "hbox" => {
+                let text = self.parse_text_argument();
+                format!("<mtext>{}</mtext>", escape(&text))
+            }
+            "textbf" | "textsf" | "texttt" => {
+                let font = match name {
+                    "textbf" => Font::Bold,
+                    "textsf" => Font::SansSerif,
+                    _ => Font::Monospace,
+                };
+                let text = self
+                    .parse_text_argument()
+                    .chars()
+                    .map(|c| font.style(c))
+                    .collect::<String>();
+                format!("<mtext>{}</mtext>", escape(&text))
+            }
+            "operatorname" => {
+                let text = self.parse_text_argument();
+                return Some(Atom {
+                    html: match text.chars().count() {
+                        1 => format!(r#"<mi mathvariant="normal">{}</mi>"#, escape(&text)),
+                        _ => format!("<mi>{}</mi>", escape(&text)),
+                    },
+                    limits: false,
+                    function: true,
+                });
+            }
+            "mathrm" | "mathup" | "mathbf" | "boldsymbol" | "bm" | "mathit" | "mathcal"
+            | "mathscr" | "mathfrak" | "mathbb" | "mathsf" | "mathtt" => {
+                let font = match name {
+                    "mathrm" | "mathup" => Font::Normal,
+                    "mathbf" | "boldsymbol" | "bm" => Font::Bold,
+                    "mathit" => Font::Italic,
+                    "mathcal" | "mathscr" => Font::Script,
+                    "mathfrak" => Font::Fraktur,
+                    "mathbb" => Font::DoubleStruck,
+                    "mathsf" => Font::SansSerif,
+                    _ => Font::Monospace,
+                };
+                let maybe_font = self.maybe_font.replace(font);
+                let html = self.parse_argument();
+                self.maybe_font = maybe_font;
+                html
+            }
+            "hat" | "widehat" | "tilde" | "widetilde" | "bar" | "overline" | "vec"
+            | "overrightarrow" | "overleftarrow" | "dot" | "ddot" | "check" | "breve" | "acute"
+            | "grave" | "mathring" => {
+                let (accent, stretchy) = match name {
+                    "hat" => ("^", false),
+                    "widehat" => ("^", true),
+                    "tilde" => ("~", false),
+                    "widetilde" => ("~", true),
+                    "bar" => ("¯", false),
+                    "overline" => ("‾", true),
+                    "vec" => ("→", false),
+                    "overrightarrow" => ("→", true),
+                    "overleftarrow" => ("←", true),
+                    "dot" => ("˙", false),
+                    "ddot" => ("¨", false),
+                    "check" => ("ˇ", false),
+                    "breve" => ("˘", false),
+                    "acute" => ("´", false),
+                    "grave" => ("`", false),
+                    _ => ("˚", false),
+                };
+                format!(
+                    r#"<mover accent="true">{}<mo stretchy="{stretchy}">{}</mo></mover>"#,
+                    self.parse_argument(),
+                    escape(accent)
+                )
+            }
+            "underline" => format!(
+                r#"<munder accentunder="true">{}<mo stretchy="true">‾</mo></munder>"#,
+                self.parse_argument()
+            ),
+            "overbrace" | "underbrace" => {
+                let (tag, brace) = match name {
+                    "overbrace" => ("mover", "⏞"),
+                    _ => ("munder", "⏟"),
+                };
+                return Some(Atom {
+                    html: format!(
+                        r#"<{tag}>{}<mo stretchy="true">{brace}</mo></{tag}>"#,
+                        self.parse_argument()
+                    ),
+                    limits: true,
+                    function: false,
+                });
+            }
+            "overset" | "stackrel" | "underset" => {
+                let script = self.parse_argument();
+                let base = self.parse_argument();
+                let tag = match name {
+                    "underset" => "munder",
+                    _ => "mover",
+                };
+                format!("<{tag}>{base}{script}</{tag}>")
+            }
+            "not" => {
+                let negated = match self.next_token() {
+                    Token::Char('=') => Some("≠"),
+                    Token::Char('<') => Some("≮"),
+                    Token::Char('>') => Some("≯"),
+                    Token::Command(name) => match name.as_str() {
+                        "in" => Some("∉"),
+                        "ni" => Some("∌"),
+                        "subset" => Some("⊄"),
+                        "supset" => Some("⊅"),
+                        "subseteq" => Some("⊈"),
+                        "supseteq" => Some("⊉"),
+                        "equiv" => Some("≢"),
+                        "sim" => Some("≁"),
+                        "simeq" => Some("≄"),
+                        "approx" => Some("≉"),
+                        "cong" => Some("≇"),
+                        "le" | "leq" => Some("≰"),
+                        "ge" | "geq" => Some("≱"),
+                        "mid" => Some("∤"),
+                        "parallel" => Some("∦"),
+                        "exists" => Some("∄"),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match negated {
+                    Some(negated) => mo(negated),
+                    None => {
+                        self.error("unsupported use of `\\not` in math".to_string());
+                        "<merror><mtext>&#x5C;not</mtext></merror>".to_string()
+                    }
+                }
+            }
+            "begin" => self.parse_environment(),
+            "textcolor" => {
+                let color = self.parse_text_argument();
+                let html = self.parse_argument();
+                match color.chars().all(|c| c.is_ascii_alphanumeric() || c == '#') {
+                    true => format!(r#"<mstyle mathcolor="{color}">{html}</mstyle>"#),
+                    false => html,
+                }
+            }
+            "bmod" => mo("mod"),
+            "pmod" => format!(
+                "<mrow>{}{}<mi>mod</mi>{}{}{}</mrow>",
+                mspace("1em"),
+                mo("("),
+                mspace("0.3333em"),
+                self.parse_argument(),
+                mo(")")
+            ),
+            "mod" => format!(
+                "<mrow>{}<mi>mod</mi>{}</mrow>",
+                mspace("1em"),
+                mspace("0.3333em")
+            ),
+            "mathop" => {
+                return Some(Atom {
+                    html: self.parse_argument(),
+                    limits: true,
+                    function: false,
+                })
+            }
+            "mathbin" | "mathrel" | "mathord" | "mathopen" | "mathclose" | "mathpunct" => {
+                self.parse_argument()
+            }
+            "displaystyle" | "textstyle" | "scriptstyle" | "scriptscriptstyle" | "limits"
+            | "nolimits" | "nonumber" | "notag" | "hline" | "middle" | "right" | "\\" => {
+                return None
+            }
+            name => {
+                if let Some((symbol, upright)) = identifier_symbol(name) {
+                    match upright {
+                        true => format!(r#"<mi mathvariant="normal">{symbol}</mi>"#),
+                        false => format!("<mi>{symbol}</mi>"),
+                    }
+                } else if let Some(symbol) = operator_symbol(name) {
+                    mo(symbol)
+                } else if let Some((symbol, limits)) = large_operator(name) {
+                    return Some(Atom {
+                        html: match limits {
+                            true => {
+                                format!(r#"<mo largeop="true" movablelimits="true">{symbol}</mo>"#)
+                            }
+                            false => format!(r#"<mo largeop="true">{symbol}</mo>"#),
+                        },
+                        limits,
+                        function: false,
+                    });
+                } else if let Some((text, limits)) = function(name) {
+                    return Some(Atom {
+                        html: format!("<mi>{text}</mi>"),
+                        limits,
+                        function: true,
+                    });
+                } else {
+                    self.error(format!("unsupported math command `\\{name}`"));
+                    format!(
+                        "<merror><mtext>{}</mtext></merror>",
+                        escape(&format!("\\{name}"))
+                    )
+                }
+            }
+        };
+        Some(Atom::new(html))
+    }
+
+    /// Reads the delimiter following `\left`, `\right`, `\middle` or `\big`,
+    /// an empty string for `.`.
+    fn parse_delimiter(&mut self) -> String {
+        let delimiter = match self.next_token() {
+            Token::Char('.') => "",
+            Token::Char('<') => "⟨",
+            Token::Char('>') => "⟩",
+            Token::Char(c) if "()[]|/".contains(c) => return c.to_string(),
+            Token::Command(name) => match name.as_str() {
+                "{" | "lbrace" => "{",
+                "}" | "rbrace" => "}",
+                "langle" => "⟨",
+                "rangle" => "⟩",
+                "|" | "Vert" | "lVert" | "rVert" => "‖",
+                "vert" | "lvert" | "rvert" => "|",
+                "lfloor" => "⌊",
+                "rfloor" => "⌋",
+                "lceil" => "⌈",
+                "rceil" => "⌉",
+                "uparrow" => "↑",
+                "downarrow" => "↓",
+                "updownarrow" => "↕",
+                "backslash" => "\\",
+                name => {
+                    self.error(format!("unsupported delimiter `\\{name}` in math"));
+                    ""
+                }
+            },
+            token => {
+                self.error(format!(
+                    "missing delimiter before {} in math",
+                    token.describe()
+                ));
+                ""
+            }
+        };
+        delimiter.to_string()
+    }
+
+    /// Parses `\left( ... \middle| ... \right)`, expecting `\left` to be
+    /// consumed already.
+    fn parse_fenced(&mut self) -> String {
+        let mut html = format!("<mrow>{}", fence(&self.parse_delimiter()));
+        loop {
+            html.push_str(&self.parse_list().concat());
+            let pos = self.pos;
+            match self.next_token() {
+                Token::Command(name) if name == "middle" => {
+                    let delimiter = self.parse_delimiter();
+                    html.push_str(&format!(
+                        r#"<mo stretchy="true">{}</mo>"#,
+                        escape(&delimiter)
+                    ));
+                }
+                Token::Command(name) if name == "right" => {
+                    html.push_str(&fence(&self.parse_delimiter()));
+                    break;
+                }
+                _ => {
+                    self.pos = pos;
+                    self.error("missing `\\right` in math".to_string());
+                    break;
+                }
+            }
+        }
+        html.push_str("</mrow>");
+        html
+    }
+
+    /// Parses `\begin{name} ... \end{name}`, expecting `\begin` to be
+    /// consumed already.
+    fn parse_environment(&mut self) -> String {
+        let name = self.parse_text_argument();
+        let (open, close, maybe_align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some(["left", "left"])),
+            "aligned" | "align" | "align*" | "alignat" | "alignat*" | "split" | "eqnarray"
+            | "eqnarray*" => ("", "", Some(["right", "left"])),
+            "gathered" | "gather" | "gather*" | "equation" | "equation*" => ("", "", None),
+            "array" => {
+                // The column specification.
+                self.parse_text_argument();
+                ("", "", None)
+            }
+            _ => {
+                self.error(format!("unsupported math environment `{name}`"));
+                ("", "", None)
+            }
+        };
+
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            row.push(mrow(self.parse_list()));
+            let pos = self.pos;
+            match self.next_token() {
+                Token::Char('&') => {}
+                Token::Command(command) if command == "\\" => {
+                    rows.push(mem::take(&mut row));
+                    // An optional vertical space, like `\\[2pt]`.
+                    self.parse_optional_argument();
+                }
+                Token::Command(command) if command == "end" => {
+                    let end = self.parse_text_argument();
+                    if end != name {
+                        self.error(format!(
+                            "`\\begin{{{name}}}` ended by `\\end{{{end}}}` in math"
+                        ));
+                    }
+                    break;
+                }
+                Token::Eof => {
+                    self.error(format!("missing `\\end{{{name}}}` in math"));
+                    break;
+                }
+                token => {
+                    self.error(format!("unexpected {} in math", token.describe()));
+                    if token == Token::Char('}') {
+                        self.pos = pos;
+                        break;
+                    }
+                }
+            }
+        }
+        // A trailing `\\` leaves an empty row.
+        if rows.is_empty() || row.len() > 1 || row.first().is_some_and(|c| c != "<mrow></mrow>") {
+            rows.push(row);
+        }
+
+        let mut table = String::from("<mtable>");
+        for row in rows {
+            table.push_str("<mtr>");
+            for (i, cell) in row.into_iter().enumerate() {
+                match maybe_align {
+                    Some(align) => table.push_str(&format!(
+                        r#"<mtd columnalign="{}">{cell}</mtd>"#,
+                        align[i % 2]
+                    )),
+                    None => table.push_str(&format!("<mtd>{cell}</mtd>")),
+                }
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        match (open, close) {
+            ("", "") => table,
+            (open, close) => format!("<mrow>{}{table}{}</mrow>", fence(open), fence(close)),
+        }
+    }
+}
+
+/// Returns the symbols written as identifiers and whether they are upright.
+fn identifier_symbol(name: &str) -> Option<(&'static str, bool)> {
+    let lower = match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "varkappa" => "ϰ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "omicron" => "ο",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "ell" => "ℓ",
+        "imath" => "ı",
+        "jmath" => "ȷ",
+        "wp" => "℘",
+        _ => "",
+    };
+    if !lower.is_empty() {
+        return Some((lower, false));
+    }
+
+    let upright = match name {
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "infty" => "∞",
+        "emptyset" => "∅",
+        "varnothing" => "∅",
+        "hbar" | "hslash" => "ℏ",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "aleph" => "ℵ",
+        "beth" => "ℶ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "top" => "⊤",
+        "bot" => "⊥",
+        "angle" => "∠",
+        "triangle" => "△",
+        "square" | "Box" => "□",
+        "degree" => "°",
+        "checkmark" => "✓",
+        _ => return None,
+    };
+    Some((upright, true))
+}
+
+fn operator_symbol(name: &str) -> Option<&'static str> {
+    let symbol = match name {
+        "pm" => "±",
+        "mp" => "∓",
+        "times" => "×",
+        "div" => "÷",
+        "cdot" => "⋅",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "ominus" => "⊖",
+        "otimes" => "⊗",
+        "oslash" => "⊘",
+        "odot" => "⊙",
+        "dagger" => "†",
+        "ddagger" => "‡",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "cap" => "∩",
+        "cup" => "∪",
+        "setminus" => "∖",
+        "sqcap" => "⊓",
+        "sqcup" => "⊔",
+        "uplus" => "⊎",
+        "amalg" => "⨿",
+        "wr" => "≀",
+        "diamond" => "⋄",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "leqslant" => "⩽",
+        "geqslant" => "⩾",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "ll" => "≪",
+        "gg" => "≫",
+        "lesssim" => "≲",
+        "gtrsim" => "≳",
+        "prec" => "≺",
+        "succ" => "≻",
+        "preceq" => "⪯",
+        "succeq" => "⪰",
+        "subset" => "⊂",
+        "supset" => "⊃",
+        "subseteq" => "⊆",
+        "supseteq" => "⊇",
+        "subsetneq" => "⊊",
+        "supsetneq" => "⊋",
+        "sqsubseteq" => "⊑",
+        "sqsupseteq" => "⊒",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "mid" => "∣",
+        "nmid" => "∤",
+        "parallel" => "∥",
+        "nparallel" => "∦",
+        "perp" => "⊥",
+        "vdash" => "⊢",
+        "dashv" => "⊣",
+        "models" => "⊨",
+        "asymp" => "≍",
+        "doteq" => "≐",
+        "coloneqq" => "≔",
+        "triangleq" => "≜",
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" => "⇔",
+        "implies" | "Longrightarrow" => "⟹",
+        "impliedby" | "Longleftarrow" => "⟸",
+        "iff" | "Longleftrightarrow" => "⟺",
+        "longrightarrow" => "⟶",
+        "longleftarrow" => "⟵",
+        "longleftrightarrow" => "⟷",
+        "mapsto" => "↦",
+        "longmapsto" => "⟼",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "updownarrow" => "↕",
+        "Uparrow" => "⇑",
+        "Downarrow" => "⇓",
+        "nearrow" => "↗",
+        "searrow" => "↘",
+        "swarrow" => "↙",
+        "nwarrow" => "↖",
+        "hookrightarrow" => "↪",
+        "hookleftarrow" => "↩",
+        "rightharpoonup" => "⇀",
+        "leftharpoonup" => "↼",
+        "rightleftharpoons" => "⇌",
+        "leadsto" => "⇝",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "neg" | "lnot" => "¬",
+        "ldots" | "dots" | "dotsc" | "dotsb" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "colon" => ":",
+        "vert" | "lvert" | "rvert" => "|",
+        "|" | "Vert" | "lVert" | "rVert" => "‖",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "backslash" => "\\",
+        "therefore" => "∴",
+        "because" => "∵",
+        "prime" => "′",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Returns the large operators and whether their limits go below and above.
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    let operator = match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "bigodot" => ("⨀", true),
+        "biguplus" => ("⨄", true),
+        "bigsqcup" => ("⨆", true),
+        "bigvee" => ("⋁", true),
+        "bigwedge" => ("⋀", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        "oiint" => ("∯", false),
+        _ => return None,
+    };
+    Some(operator)
+}
+
+/// Returns the named functions and whether their limits go below.
+fn function(name: &str) -> Option<(&'static str, bool)> {
+    let function = match name {
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "ker" | "dim" | "deg"
+        | "arg" | "hom" => (name, false),
+        "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => (name, true),
+        "limsup" => ("lim sup", true),
+        "liminf" => ("lim inf", true),
+        _ => return None,
+    };
+    // The names are static, this only turns the lifetime of `name` into one.
+    let text = match function.0 {
+        "lim sup" => "lim sup",
+        "lim inf" => "lim inf",
+        _ => FUNCTIONS.iter().find(|f| **f == function.0)?,
+    };
+    Some((text, function.1))
+}
+
+const FUNCTIONS: [&str; 30] = [
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "coth", "log", "ln", "lg", "exp", "ker", "dim", "deg", "arg", "hom", "lim", "max", "min",
+    "sup", "inf", "det", "gcd", "Pr",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latex_to_mathml() {
+        let (mathml, errors) = latex_to_mathml(r"\sum_{i=1}^n \frac{x_i^2}{\sqrt{2}}", true);
+        assert!(errors.is_empty());
+        assert_eq!(
+            mathml,
+            concat!(
+                r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block"><semantics><mrow>"#,
+                r#"<munderover><mo largeop="true" movablelimits="true">∑</mo><mrow><mi>i</mi><mo>&#x3D;</mo><mn>1</mn></mrow><mi>n</mi></munderover>"#,
+                r#"<mfrac><msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup><msqrt><mn>2</mn></msqrt></mfrac>"#,
+                r#"</mrow><annotation encoding="application/x-tex">&#x5C;sum&#x5F;&#x7B;i&#x3D;1&#x7D;&#x5E;n &#x5C;frac&#x7B;x&#x5F;i&#x5E;2&#x7D;&#x7B;&#x5C;sqrt&#x7B;2&#x7D;&#x7D;</annotation></semantics></math>"#,
+            )
+        );
+
+        let (mathml, _) = latex_to_mathml(r"\mathbb{R}^2 \left( a \right)", false);
+        assert!(mathml.contains(
+            r#"<msup><mi>ℝ</mi><mn>2</mn></msup><mrow><mo fence="true" stretchy="true">&#x28;</mo><mi>a</mi><mo fence="true" stretchy="true">&#x29;</mo></mrow>"#
+        ));
+
+        let (mathml, errors) = latex_to_mathml(
+            r"\foo{x} + \begin{pmatrix} a & b \\ c & d \end{pmatrix}",
+            false,
+        );
+        assert_eq!(errors, vec!["unsupported math command `\\foo`"]);
+        assert!(mathml.contains("<merror><mtext>&#x5C;foo</mtext></merror>"));
+        assert!(mathml.contains("<mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>"));
+    }
+
+    /// Returns the MathML of `tex` without the `<math>` element and the
+    /// annotation, and the errors.
+    fn convert(tex: &str) -> (String, Vec<String>) {
+        let (mathml, errors) = latex_to_mathml(tex, false);
+        let start = mathml.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+        (mathml[start..end].to_string(), errors)
+    }
+
+    #[test]
+    fn test_convert_commands() {
+        let fence = |c: &str| format!(r#"<mo fence="true" stretchy="true">{c}</mo>"#);
+        let cases = [
+            // fractions
+            (r"\frac12", "<mfrac><mn>1</mn><mn>2</mn></mfrac>".to_string()),
+            (
+                r"\dfrac{1}{x+1}",
+                "<mfrac><mn>1</mn><mrow><mi>x</mi><mo>&#x2B;</mo><mn>1</mn></mrow></mfrac>".to_string(),
+            ),
+            (r"\tfrac a b", "<mfrac><mi>a</mi><mi>b</mi></mfrac>".to_string()),
+            // sub and superscripts
+            (r"x_i", "<msub><mi>x</mi><mi>i</mi></msub>".to_string()),
+            (r"x^2", "<msup><mi>x</mi><mn>2</mn></msup>".to_string()),
+            (r"x^{2}_{i}", "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>".to_string()),
+            (
+                r"e^{-x^2}",
+                "<msup><mi>e</mi><mrow><mo>−</mo><msup><mi>x</mi><mn>2</mn></msup></mrow></msup>".to_string(),
+            ),
+            (
+                r"a_{b_c}",
+                "<msub><mi>a</mi><msub><mi>b</mi><mi>c</mi></msub></msub>".to_string(),
+            ),
+            (r"x''", "<msup><mi>x</mi><mo>′′</mo></msup>".to_string()),
+            (r"\sqrt[3]{x}", "<mroot><mi>x</mi><mn>3</mn></mroot>".to_string()),
+            // \left ... \right
+            (
+                r"\left\{ x \right\}",
+                format!("<mrow>{}<mi>x</mi>{}</mrow>", fence("&#x7B;"), fence("&#x7D;")),
+            ),
+            (
+                r"\left[ \frac{a}{b} \right.",
+                format!("<mrow>{}<mfrac><mi>a</mi><mi>b</mi></mfrac></mrow>", fence("&#x5B;")),
+            ),
+            // environments
+            (
+                r"\begin{matrix} a & b \\ c & d \end{matrix}",
+                "<mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>".to_string(),
+            ),
+            (
+                r"\begin{bmatrix} 1 \end{bmatrix}",
+                format!(
+                    "<mrow>{}<mtable><mtr><mtd><mn>1</mn></mtd></mtr></mtable>{}</mrow>",
+                    fence("&#x5B;"),
+                    fence("&#x5D;")
+                ),
+            ),
+            (
+                r"\begin{align} a &= b \\ c &= d \end{align}",
+                concat!(
+                    r#"<mtable><mtr><mtd columnalign="right"><mi>a</mi></mtd><mtd columnalign="left"><mrow><mo>&#x3D;</mo><mi>b</mi></mrow></mtd></mtr>"#,
+                    r#"<mtr><mtd columnalign="right"><mi>c</mi></mtd><mtd columnalign="left"><mrow><mo>&#x3D;</mo><mi>d</mi></mrow></mtd></mtr></mtable>"#,
+                )
+                .to_string(),
+            ),
+            (
+                r"\begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \end{cases}",
+                format!(
+                    concat!(
+                        r#"<mrow>{}<mtable><mtr><mtd columnalign="left"><mn>1</mn></mtd><mtd columnalign="left"><mrow><mi>x</mi><mo>&#x3E;</mo><mn>0</mn></mrow></mtd></mtr>"#,
+                        r#"<mtr><mtd columnalign="left"><mn>0</mn></mtd><mtd columnalign="left"><mtext>otherwise</mtext></mtd></mtr></mtable></mrow>"#,
+                    ),
+                    fence("&#x7B;")
+                ),
+            ),
+        ];
+
+        for (tex, expected) in cases {
+            assert_eq!(convert(tex), (expected, vec![]), "{tex}");
+        }
+    }
+
+    #[test]
+    fn test_report_errors() {
+        let cases = [
+            (
+                r"\alpha + \unknown{x}",
+                r"<mrow><mi>α</mi><mo>&#x2B;</mo><merror><mtext>&#x5C;unknown</mtext></merror><mi>x</mi></mrow>",
+                r"unsupported math command `\unknown`",
+            ),
+            (
+                r"\begin{foo} a \end{foo}",
+                "<mtable><mtr><mtd><mi>a</mi></mtd></mtr></mtable>",
+                "unsupported math environment `foo`",
+            ),
+            (
+                r"\frac{a}{b",
+                "<mfrac><mi>a</mi><mi>b</mi></mfrac>",
+                "missing `}` in math",
+            ),
+            ("{a", "<mi>a</mi>", "missing `}` in math"),
+            ("a}", "<mi>a</mi>", "unexpected `}` in math"),
+            (
+                "x^",
+                "<msup><mi>x</mi><mrow></mrow></msup>",
+                "missing argument before end of formula in math",
+            ),
+            (
+                r"\left( x",
+                r#"<mrow><mo fence="true" stretchy="true">&#x28;</mo><mi>x</mi></mrow>"#,
+                r"missing `\right` in math",
+            ),
+            (
+                r"\begin{matrix} a",
+                "<mtable><mtr><mtd><mi>a</mi></mtd></mtr></mtable>",
+                r"missing `\end{matrix}` in math",
+            ),
+        ];
+
+        for (tex, expected, error) in cases {
+            assert_eq!(
+                convert(tex),
+                (expected.to_string(), vec![error.to_string()]),
+                "{tex}"
+            );
+        }
+    }
+}
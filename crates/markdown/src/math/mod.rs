@@ -0,0 +1,273 @@
+mod latex;
+
+#[cfg(doc)]
+use crate::raw_html::RawHtml;
+use crate::shortcode::{diagnostic_at, ShortcodeContext};
+use crate::wiki_links::is_code_fence;
+use berlin_core::{split_front_matter, MediaType, ModuleSpecifier, Severity};
+use latex::latex_to_mathml;
+use std::ops::Range;
+
+/// Replaces the LaTeX math in `content` by MathML, so formulas render
+/// without any JavaScript.
+///
+/// `$...$` is inline math and `$$...$$` display math; org also has `\(...\)`
+/// and `\[...\]`. Like in pandoc, an inline formula cannot start with a
+/// space nor end with a space or before a digit, so `$5 and $10` stays text.
+/// Math in code, escaped dollars and formulas spanning a blank line are left
+/// alone. The MathML is kept in the [`RawHtml`] of `ctx` until the page is
+/// converted, and display math on lines of its own becomes a
+/// `<div class="math">` block.
+/// Unsupported commands are reported as warnings and rendered as errors in
+/// the formula.
+pub fn handle_math(
+    specifier: &ModuleSpecifier,
+    content: &mut String,
+    media_type: MediaType,
+    ctx: &ShortcodeContext,
+) {
+    let code = code_ranges(content, media_type);
+    let bytes = content.as_bytes();
+    let mut output = String::with_capacity(content.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(range) = code.iter().find(|r| r.contains(&i)) {
+            i = range.end;
+            continue;
+        }
+        // The formula, the end of the math and whether it is display math.
+        let maybe_math = match bytes[i] {
+            b'\\' => match (media_type, bytes.get(i + 1)) {
+                (MediaType::Org, Some(b'(')) => find_closing(content, i + 2, r"\)", false)
+                    .map(|end| (i + 2..end, end + 2, false)),
+                (MediaType::Org, Some(b'[')) => find_closing(content, i + 2, r"\]", false)
+                    .map(|end| (i + 2..end, end + 2, true)),
+                _ => {
+                    // An escaped character, like `\$`.
+                    i += 2;
+                    continue;
+                }
+            },
+            b'$' if bytes.get(i + 1) == Some(&b'$') => {
+                find_closing(content, i + 2, "$$", false).map(|end| (i + 2..end, end + 2, true))
+            }
+            b'$' if bytes.get(i + 1).is_some_and(|b| !b.is_ascii_whitespace()) => {
+                find_closing(content, i + 1, "$", true).map(|end| (i + 1..end, end + 1, false))
+            }
+            _ => None,
+        };
+
+        match maybe_math {
+            Some((tex, end, display))
+                if !content[tex.clone()].trim().is_empty()
+                    && !code.iter().any(|r| r.start < end && r.end > i) =>
+            {
+                let (mathml, errors) = latex_to_mathml(&content[tex], display);
+                let text = content[i..end].lines().next().unwrap_or_default();
+                for error in errors {
+                    ctx.diagnostics.push(diagnostic_at(
+                        specifier,
+                        content,
+                        Severity::Warning,
+                        i,
+                        text,
+                        error,
+                    ));
+                }
+
+                let line_start = content[..i].rfind('\n').map_or(0, |n| n + 1);
+                let line_end = content[end..].find('\n').map_or(content.len(), |n| end + n);
+                let block = display
+                    && content[line_start..i].trim().is_empty()
+                    && content[end..line_end].trim().is_empty();
+
+                let html = match block {
+                    true => format!(r#"<div class="math">{mathml}</div>"#),
+                    false => mathml,
+                };
+                // The placeholder of markdown math spans the lines of the
+                // formula, so the lines of the diagnostics still match the
+                // source. Org is converted by pandoc, which reflows paragraphs.
+                let lines = match media_type {
+                    MediaType::Org => 1,
+                    _ => content[i..end].lines().count(),
+                };
+                output.push_str(&content[copied..i]);
+                output.push_str(&ctx.raw_html.insert(html, block, lines));
+                copied = end;
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+
+    if copied > 0 {
+        output.push_str(&content[copied..]);
+        *content = output;
+    }
+}
+
+/// Returns the offset of the `delimiter` closing the math starting at `from`.
+fn find_closing(content: &str, from: usize, delimiter: &str, inline: bool) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let mut start = from;
+    while let Some(end) = content[start..].find(delimiter).map(|i| start + i) {
+        if has_blank_line(&content[from..end]) {
+            return None;
+        }
+        let escaped = delimiter.starts_with('$') && end > from && bytes[end - 1] == b'\\';
+        let inline_mismatch = inline
+            && (end == from
+                || bytes[end - 1].is_ascii_whitespace()
+                || bytes.get(end + 1).is_some_and(|b| b.is_ascii_digit()));
+        if !escaped && !inline_mismatch {
+            return Some(end);
+        }
+        start = end + 1;
+    }
+    None
+}
+
+fn has_blank_line(text: &str) -> bool {
+    let lines = text.split('\n').collect::<Vec<_>>();
+    lines.len() > 2
+        && lines[1..lines.len() - 1]
+            .iter()
+            .any(|l| l.trim().is_empty())
+}
+
+/// Returns the ranges of `content` without math: the front matter, the code
+/// blocks and, in markdown, the code spans.
+fn code_ranges(content: &str, media_type: MediaType) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    let mut maybe_block_start = None;
+    // The front matter, whatever its format.
+    let front_matter_end = match media_type {
+        MediaType::Markdown => split_front_matter(content).map_or(0, |(_, _, range)| range.end),
+        _ => 0,
+    };
+    if front_matter_end > 0 {
+        ranges.push(0..front_matter_end);
+    }
+
+    for line in content.split_inclusive('\n') {
+        // Org keywords like `#+title:`.
+        let keyword = media_type == MediaType::Org && line.trim_start().starts_with("#+");
+        if offset < front_matter_end {
+            // in the range of the front matter already
+        } else if keyword {
+            ranges.push(offset..offset + line.len());
+        } else if is_code_fence(line, media_type, maybe_block_start.is_some()) {
+            match maybe_block_start.take() {
+                Some(start) => ranges.push(start..offset + line.len()),
+                None => maybe_block_start = Some(offset),
+            }
+        } else if maybe_block_start.is_none() && media_type == MediaType::Markdown {
+            let bytes = line.as_bytes();
+            let mut i = 0;
+            while i < bytes.len() {
+                if bytes[i] != b'`' {
+                    i += 1;
+                    continue;
+                }
+                let run = bytes[i..].iter().take_while(|b| **b == b'`').count();
+                let fence = "`".repeat(run);
+                let mut search = i + run;
+                let mut maybe_end = None;
+                while let Some(end) = line[search..].find(&fence).map(|j| search + j) {
+                    let end_run = bytes[end..].iter().take_while(|b| **b == b'`').count();
+                    if end_run == run {
+                        maybe_end = Some(end + run);
+                        break;
+                    }
+                    search = end + end_run;
+                }
+                match maybe_end {
+                    Some(end) => {
+                        ranges.push(offset + i..offset + end);
+                        i = end;
+                    }
+                    None => i += run,
+                }
+            }
+        }
+        offset += line.len();
+    }
+    if let Some(start) = maybe_block_start {
+        ranges.push(start..content.len());
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_math() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+        let mut content =
+            "---\ntitle: $a$\n---\nIt costs $5 and $10, `$x$` is code, \\$y$ is escaped.\n\
+                           Euler: $e^{i\\pi} = -1$.\n\
+                           $$\n\\oops{x}\n$$\n"
+                .to_string();
+
+        handle_math(&specifier, &mut content, MediaType::Markdown, &ctx);
+
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "title: $a$");
+        let lines = lines[3..].to_vec();
+        assert_eq!(
+            lines[0],
+            "It costs $5 and $10, `$x$` is code, \\$y$ is escaped."
+        );
+        assert_eq!(lines[1], "Euler: \u{E000}0\u{E001}.");
+        // The formula keeps its lines.
+        assert_eq!(lines[2..], ["\u{E000}1b", "\u{E002}", "\u{E002}\u{E001}"]);
+
+        let html = ctx
+            .raw_html
+            .restore(&crate::string_to_html(&content, &Default::default()));
+        assert!(html.contains(
+            r#"Euler: <math xmlns="http://www.w3.org/1998/Math/MathML"><semantics><mrow><msup><mi>e</mi><mrow><mi>i</mi><mi>π</mi></mrow></msup><mo>&#x3D;</mo><mo>−</mo><mn>1</mn></mrow>"#
+        ));
+        assert!(html.contains("</math>.</p>\n"));
+        assert!(html.contains(
+            r#"</p>
+<div class="math"><math xmlns="http://www.w3.org/1998/Math/MathML" display="block">"#
+        ));
+        assert!(html.ends_with("</math></div>\n"));
+
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unsupported math command `\\oops`");
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].line, 6);
+
+        let mut content = "Inline \\(x^2\\) in org.\n\\[\nx^2\n\\]\n".to_string();
+        handle_math(&specifier, &mut content, MediaType::Org, &ctx);
+        assert_eq!(
+            content,
+            "Inline \u{E000}2\u{E001} in org.\n\u{E000}3b\u{E001}\n"
+        );
+    }
+
+    #[test]
+    fn test_skip_toml_and_json_front_matter() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+        for front_matter in [
+            "+++\nsummary = \"$x$ notation\"\n+++\n",
+            "{\n\"summary\": \"$x$ notation\"\n}\n",
+        ] {
+            let mut content = format!("{front_matter}Then $y$.\n");
+            handle_math(&specifier, &mut content, MediaType::Markdown, &ctx);
+            assert!(content.starts_with(front_matter));
+            assert!(!content.contains("$y$"));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use libs::comrak::nodes::NodeValue;
+use libs::comrak::{parse_document, Arena, ComrakOptions};
+use libs::lazy_static;
+use libs::parking_lot::Mutex;
+use libs::regex::Regex;
+use std::sync::Arc;
+
+lazy_static::lazy_static! {
+    static ref PLACEHOLDER_RE: Regex =
+        Regex::new("\u{E000}(\\d+)(b?)[^\u{E001}]*\u{E001}").unwrap();
+}
+
+/// The HTML generated for a page before it is converted, like the output of
+/// its shortcodes or the MathML of its formulas.
+///
+/// It is kept out of the markdown as placeholders, so it is neither parsed
+/// as markdown nor omitted with the raw HTML of the page when `unsafe_html`
+/// is disabled.
+#[derive(Clone, Debug, Default)]
+pub struct RawHtml(Arc<Mutex<Vec<String>>>);
+
+impl RawHtml {
+    /// Stores `html` and returns its placeholder, which spans `lines` lines
+    /// so the following lines of the page keep their numbers. A `block`
+    /// placeholder on lines of its own ends up outside of any paragraph.
+    pub(crate) fn insert(&self, html: String, block: bool, lines: usize) -> String {
+        let mut fragments = self.0.lock();
+        fragments.push(html);
+        format!(
+            "\u{E000}{}{}{}\u{E001}",
+            fragments.len() - 1,
+            if block { "b" } else { "" },
+            "\n\u{E002}".repeat(lines.saturating_sub(1)),
+        )
+    }
+
+    /// Replaces the placeholders in `html` by the HTML they stand for,
+    /// including those inside that HTML, like the body of a block shortcode.
+    pub(crate) fn restore(&self, html: &str) -> String {
+        let fragments = self.0.lock();
+        if fragments.is_empty() {
+            return html.to_string();
+        }
+        restore_with(&fragments, html)
+    }
+
+    /// Whether comrak would make `html` an HTML block if it started a line of
+    /// its own, instead of raw HTML inside a paragraph.
+    pub(crate) fn is_block(html: &str) -> bool {
+        let arena = Arena::new();
+        let root = parse_document(&arena, html, &ComrakOptions::default());
+        let is_block = root
+            .first_child()
+            .is_some_and(|node| matches!(node.data.borrow().value, NodeValue::HtmlBlock(_)));
+        is_block
+    }
+
+    /// Removes the placeholders from `text`, e.g. the text of a heading.
+    pub(crate) fn strip(text: &str) -> String {
+        PLACEHOLDER_RE.replace_all(text, "").trim().to_string()
+    }
+}
+
+fn restore_with(fragments: &[String], html: &str) -> String {
+    let mut restored = String::with_capacity(html.len());
+    let mut pos = 0;
+    while let Some(caps) = PLACEHOLDER_RE.captures(&html[pos..]) {
+        let placeholder = caps.get(0).unwrap();
+        restored.push_str(&html[pos..pos + placeholder.start()]);
+        pos += placeholder.end();
+        let fragment = caps[1]
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| fragments.get(i))
+            .map_or_else(String::new, |f| restore_with(fragments, f));
+        if caps[2].is_empty() {
+            restored.push_str(&fragment);
+            continue;
+        }
+
+        // a block closes the paragraph it is in and the rest of the
+        // paragraph goes into a new one
+        let rest = &html[pos..];
+        let rest_of_paragraph = match ["</p>\n", "</p>", "<br />\n", "\n"]
+            .into_iter()
+            .find(|end| rest.starts_with(end))
+        {
+            Some(end) => {
+                pos += end.len();
+                !end.starts_with("</p>")
+            }
+            None => true,
+        };
+        if restored.ends_with("<p>") {
+            restored.truncate(restored.len() - "<p>".len());
+        } else {
+            if restored.ends_with("<br />\n") {
+                restored.truncate(restored.len() - "<br />\n".len());
+            }
+            restored.truncate(restored.trim_end().len());
+            restored.push_str("</p>\n");
+        }
+        restored.push_str(&fragment);
+        restored.push('\n');
+        if rest_of_paragraph {
+            restored.push_str("<p>");
+        }
+    }
+    restored.push_str(&html[pos..]);
+    restored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore() {
+        let raw_html = RawHtml::default();
+        let inline = raw_html.insert("<b>x</b>".to_string(), false, 1);
+        let block = raw_html.insert("<div>y</div>".to_string(), true, 3);
+        assert_eq!(block.lines().count(), 3);
+
+        assert_eq!(
+            raw_html.restore(&format!(
+                "<p>{inline}</p>\n<p>a {inline} b</p>\n<p>{}</p>\n",
+                block.replace('\n', "<br />\n")
+            )),
+            "<p><b>x</b></p>\n<p>a <b>x</b> b</p>\n<div>y</div>\n"
+        );
+        assert_eq!(
+            raw_html.restore(&format!("<p>a\n{block}\nb</p>\n")),
+            "<p>a</p>\n<div>y</div>\n<p>b</p>\n"
+        );
+        assert!(RawHtml::is_block("<div>\ny\n</div>"));
+        assert!(!RawHtml::is_block("<span>y</span>"));
+        assert_eq!(RawHtml::strip(&format!("Euler {inline}")), "Euler");
+    }
+}
@@ -0,0 +1,277 @@
+use crate::shortcode::{diagnostic_at, ShortcodeContext};
+use berlin_core::{extract_yaml, split_front_matter, FrontMatterFormat, ModuleSpecifier, Severity};
+use libs::chrono::{DateTime, NaiveDate, NaiveDateTime};
+use libs::serde_yaml::{Mapping, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The fields the front matter of the pages of a collection must have, read
+/// from the `[schemas.<collection>]` sections of `berlin.toml`:
+///
+/// ```toml
+/// [schemas.notes]
+/// required = ["title", "date", "author"]
+/// types = { date = "date", tags = "array", draft = "boolean" }
+/// allowed = { tags = ["rust", "emacs", "org"] }
+/// date_format = "%Y-%m-%d"
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontMatterSchema {
+    /// The fields that must be set.
+    pub required: Vec<String>,
+    /// The types of fields.
+    pub types: BTreeMap<String, FieldType>,
+    /// The values allowed in fields, for a list like `tags` in each item.
+    pub allowed: BTreeMap<String, Vec<String>>,
+    /// The format of the fields of type `date`, `%Y-%m-%d` by default.
+    pub date_format: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Array,
+    Object,
+    Date,
+}
+
+impl FrontMatterSchema {
+    /// Returns the violations of the schema by `front_matter`, with the
+    /// fields they are about.
+    pub fn validate(&self, front_matter: &Mapping) -> Vec<(String, String)> {
+        let mut violations = Vec::new();
+        let get = |field: &str| {
+            front_matter
+                .get(&Value::String(field.to_string()))
+                .filter(|value| !value.is_null())
+        };
+
+        for field in &self.required {
+            if get(field).is_none() {
+                violations.push((
+                    field.clone(),
+                    format!("missing required front matter field `{field}`"),
+                ));
+            }
+        }
+
+        let date_format = self.date_format.as_deref().unwrap_or("%Y-%m-%d");
+        for (field, field_type) in &self.types {
+            let value = match get(field) {
+                Some(value) => value,
+                None => continue,
+            };
+            let valid = match field_type {
+                FieldType::String => value.is_string(),
+                FieldType::Integer => value.is_i64() || value.is_u64(),
+                FieldType::Float => value.is_number(),
+                FieldType::Boolean => value.is_bool(),
+                FieldType::Array => value.is_sequence(),
+                FieldType::Object => value.is_mapping(),
+                FieldType::Date => value.as_str().is_some_and(|date| {
+                    NaiveDate::parse_from_str(date, date_format).is_ok()
+                        || NaiveDateTime::parse_from_str(date, date_format).is_ok()
+                        || DateTime::parse_from_str(date, date_format).is_ok()
+                }),
+            };
+            if !valid {
+                let expected = match field_type {
+                    FieldType::Date => format!("a date in the format `{date_format}`"),
+                    FieldType::Integer => "an integer".to_string(),
+                    FieldType::Array => "an array".to_string(),
+                    FieldType::Object => "an object".to_string(),
+                    field_type => format!("a {}", format!("{field_type:?}").to_lowercase()),
+                };
+                violations.push((
+                    field.clone(),
+                    format!("front matter field `{field}` should be {expected}"),
+                ));
+            }
+        }
+
+        for (field, allowed) in &self.allowed {
+            let values = match get(field) {
+                Some(Value::Sequence(values)) => values.iter().collect(),
+                Some(value) => vec![value],
+                None => continue,
+            };
+            for value in values {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    value => libs::serde_yaml::to_string(value)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                };
+                if !allowed.contains(&value) {
+                    violations.push((
+                        field.clone(),
+                        format!(
+                            "`{value}` is not allowed in front matter field `{field}`, allowed values are: {}",
+                            allowed.join(", ")
+                        ),
+                    ));
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+/// Validates the front matter of the page at `specifier` against the schema
+/// of its collection, the directory below `content/` it is in, and reports
+/// every violation as an error.
+pub fn validate_front_matter(specifier: &ModuleSpecifier, source: &str, ctx: &ShortcodeContext) {
+    if ctx.schemas.is_empty() {
+        return;
+    }
+    let maybe_schema = specifier
+        .to_file_path()
+        .ok()
+        .and_then(|path| ctx.content_index.read().collection(&path))
+        .and_then(|collection| ctx.schemas.get(&collection));
+    let schema = match maybe_schema {
+        Some(schema) => schema,
+        None => return,
+    };
+    let front_matter = match libs::serde_yaml::from_str(&extract_yaml(source)) {
+        Ok(Value::Mapping(front_matter)) => front_matter,
+        _ => Mapping::new(),
+    };
+
+    // Violations are reported on the key of their field in the front matter,
+    // or else on its first line.
+    let (format, range) = match split_front_matter(source) {
+        Some((format, _, range)) => (Some(format), range),
+        None => (None, 0..0),
+    };
+    let lines = source[range.clone()]
+        .split_inclusive('\n')
+        .scan(range.start, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .collect::<Vec<_>>();
+    for (field, message) in schema.validate(&front_matter) {
+        let (key, separator) = match format {
+            Some(FrontMatterFormat::Json) => (format!("\"{field}\""), ':'),
+            Some(FrontMatterFormat::Toml) => (field.clone(), '='),
+            _ => (field.clone(), ':'),
+        };
+        let maybe_start = lines.iter().find_map(|(start, line)| {
+            // only the keys of JSON are indented
+            let indent = match format {
+                Some(FrontMatterFormat::Json) => line.len() - line.trim_start().len(),
+                _ => 0,
+            };
+            line[indent..]
+                .strip_prefix(key.as_str())
+                .filter(|rest| rest.trim_start().starts_with(separator))
+                .map(|_| start + indent)
+        });
+        let (start, text) = match maybe_start {
+            Some(start) => (start, key.as_str()),
+            None => (
+                range.start,
+                source[range.start..].lines().next().unwrap_or_default(),
+            ),
+        };
+        ctx.diagnostics.push(diagnostic_at(
+            specifier,
+            source,
+            Severity::Error,
+            start,
+            text,
+            message,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let schema: FrontMatterSchema = libs::toml::from_str(
+            r#"
+            required = ["title", "author"]
+            types = { date = "date", draft = "boolean" }
+            allowed = { tags = ["rust", "org"] }
+            "#,
+        )
+        .unwrap();
+        let front_matter = libs::serde_yaml::from_str(
+            "title: A\ndate: 03.04.2023\ndraft: true\ntags: [rust, go]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema
+                .validate(&front_matter)
+                .into_iter()
+                .map(|(_, message)| message)
+                .collect::<Vec<_>>(),
+            vec![
+                "missing required front matter field `author`",
+                "front matter field `date` should be a date in the format `%Y-%m-%d`",
+                "`go` is not allowed in front matter field `tags`, allowed values are: rust, org",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_on_the_key_of_the_field() {
+        let content_path = std::path::PathBuf::from("/site/content");
+        let schema: FrontMatterSchema =
+            libs::toml::from_str(r#"types = { date = "date" }"#).unwrap();
+        let ctx = ShortcodeContext {
+            content_index: std::sync::Arc::new(libs::parking_lot::RwLock::new(
+                berlin_core::ContentIndex::build(
+                    &content_path,
+                    &[content_path.join("notes/a.md")],
+                    &berlin_core::PermalinkOptions::default(),
+                )
+                .unwrap(),
+            )),
+            schemas: [("notes".to_string(), schema)].into(),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+
+        for (source, (line, column, snippet)) in [
+            (
+                "---\ntitle: A\ndate: soon\n---\ndate: in the body\n",
+                (3, 1, "date: soon"),
+            ),
+            (
+                "+++\ntitle = \"A\"\ndate = \"soon\"\n+++\ndate: in the body\n",
+                (3, 1, "date = \"soon\""),
+            ),
+            (
+                "{\n  \"title\": \"A\",\n  \"date\": \"soon\"\n}\ndate: in the body\n",
+                (3, 3, "  \"date\": \"soon\""),
+            ),
+        ] {
+            validate_front_matter(&specifier, source, &ctx);
+            let diagnostics = ctx.diagnostics.take();
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(
+                (
+                    diagnostic.line,
+                    diagnostic.column,
+                    diagnostic.snippet.as_str()
+                ),
+                (line, column, snippet)
+            );
+        }
+    }
+}
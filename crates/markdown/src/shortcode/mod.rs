@@ -1,3 +1,59 @@
 mod parser;
+mod templates;
 
-pub use parser::parse_for_shortcodes;
+use crate::highlight::Highlighter;
+use crate::markdown::MarkdownOptions;
+use crate::raw_html::RawHtml;
+use crate::schema::FrontMatterSchema;
+use berlin_core::{Diagnostics, SharedContentIndex, Taxonomies};
+use images::ImageProcessor;
+use libs::parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub(crate) use parser::{diagnostic_at, page_context, replace_shortcodes};
+pub use parser::{parse_for_shortcodes, SourceMap};
+pub use templates::ShortcodeTemplates;
+
+/// Services available to shortcodes while a page is rendered.
+#[derive(Clone, Default)]
+pub struct ShortcodeContext {
+    /// Generates the responsive variants of images used by `figure`.
+    pub maybe_image_processor: Option<Arc<ImageProcessor>>,
+    /// The shortcodes defined by the site, taking precedence over the
+    /// built-in `figure` and `relref`. A `callout` template replaces the
+    /// built-in markup of callouts.
+    pub maybe_templates: Option<Arc<ShortcodeTemplates>>,
+    /// Resolves `relref` and links to other content files to permalinks.
+    pub content_index: SharedContentIndex,
+    /// Highlights the code blocks, built once per build from `[markdown.highlight]`.
+    pub maybe_highlighter: Option<Arc<Highlighter>>,
+    /// The options of the site, see [`crate::page_options`].
+    pub markdown_options: MarkdownOptions,
+    /// The front matter schemas of the collections, by the directory below
+    /// `content/`.
+    pub schemas: HashMap<String, FrontMatterSchema>,
+    /// The taxonomies the pages are listed under by their front matter.
+    pub taxonomies: Taxonomies,
+    /// Collects the problems found while resolving shortcodes.
+    pub diagnostics: Diagnostics,
+    /// The HTML generated for the page before it is converted.
+    pub raw_html: RawHtml,
+    /// The permalinks the bodies of the block shortcodes of the page link to.
+    pub body_links: BodyLinks,
+}
+
+/// The permalinks found while converting the bodies of block shortcodes,
+/// added to the links of the page once it is converted.
+#[derive(Clone, Debug, Default)]
+pub struct BodyLinks(Arc<Mutex<Vec<String>>>);
+
+impl BodyLinks {
+    pub(crate) fn extend(&self, links: Vec<String>) {
+        self.0.lock().extend(links);
+    }
+
+    pub(crate) fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.lock())
+    }
+}
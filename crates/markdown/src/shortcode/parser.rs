@@ -1,13 +1,17 @@
-use berlin_core::{FrontMatter, ModuleSpecifier};
+use crate::highlight::escape_html;
+use crate::markdown::{body_to_html, front_matter_options, MarkdownOptions};
+use crate::raw_html::RawHtml;
+use crate::shortcode::{ShortcodeContext, ShortcodeTemplates};
+use berlin_core::{extract_yaml, Diagnostic, Diagnostics, ModuleSpecifier, Severity};
 use errors::error::generic_error;
+use images::ResponsiveImage;
 use libs::anyhow::Error;
-use libs::slugify::slugify;
 use std::ops::Range;
-use std::path::{Path, PathBuf};
 
 use libs::tera;
+use pest::error::InputLocation;
 use pest::iterators::Pair;
-use pest::{Parser as PestParser, Span};
+use pest::Parser as PestParser;
 use pest_derive::Parser as PestParser;
 
 #[derive(PestParser)]
@@ -56,9 +60,14 @@ fn parse_kwarg_value(pair: Pair<Rule>) -> tera::Value {
 }
 
 /// Returns (shortcode_name, kwargs)
+///
+/// Positional arguments are passed as the array `args`. For compatibility,
+/// a positional string is also passed under the name of the shortcode, so
+/// `{{< relref "note.md" >}}` has `relref = "note.md"`.
 fn parse_shortcode_call(pair: Pair<Rule>) -> (String, tera::Value) {
     let mut name = None;
     let mut args = tera::Map::new();
+    let mut positional = Vec::new();
 
     for p in pair.into_inner() {
         match p.as_rule() {
@@ -77,9 +86,10 @@ fn parse_shortcode_call(pair: Pair<Rule>) -> (String, tera::Value) {
                             arg_val = Some(parse_kwarg_value(p2));
                         }
                         Rule::string => {
+                            let value = tera::Value::String(replace_string_markers(p2.as_str()));
+                            positional.push(value.clone());
                             arg_name = name.clone();
-                            arg_val =
-                                Some(tera::Value::String(replace_string_markers(p2.as_str())));
+                            arg_val = Some(value);
                         }
 
                         _ => unreachable!("Got something unexpected in a kwarg: {:?}", p2),
@@ -90,90 +100,512 @@ fn parse_shortcode_call(pair: Pair<Rule>) -> (String, tera::Value) {
                     args.insert(name, value);
                 }
             }
+            Rule::positional_arg => {
+                for p2 in p.into_inner() {
+                    positional.push(parse_kwarg_value(p2));
+                }
+            }
             _ => unreachable!("Got something unexpected in a shortcode: {:?}", p),
         }
     }
+    if !positional.is_empty() {
+        args.insert("args".to_string(), tera::Value::Array(positional));
+    }
     (name.unwrap(), tera::Value::Object(args))
 }
 
+/// The page whose shortcodes are resolved.
+struct Page<'a> {
+    specifier: &'a ModuleSpecifier,
+    /// The full content of the page, used to locate diagnostics.
+    content: &'a str,
+    /// The front matter and path of the page, passed to shortcode templates.
+    context: tera::Value,
+    /// The options the page is converted with, used for the bodies of block
+    /// shortcodes.
+    options: MarkdownOptions,
+}
+
+impl<'a> Page<'a> {
+    /// Creates a diagnostic for `text` found at the byte offset `start` of the page.
+    fn diagnostic(
+        &self,
+        severity: Severity,
+        start: usize,
+        text: &str,
+        message: String,
+    ) -> Diagnostic {
+        diagnostic_at(self.specifier, self.content, severity, start, text, message)
+    }
+}
+
+/// Creates a diagnostic for `text` found at the byte offset `start` of `content`.
+pub(crate) fn diagnostic_at(
+    specifier: &ModuleSpecifier,
+    content: &str,
+    severity: Severity,
+    start: usize,
+    text: &str,
+    message: String,
+) -> Diagnostic {
+    let before = &content[..start];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+
+    Diagnostic {
+        severity,
+        specifier: specifier.clone(),
+        message,
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        snippet: content[line_start..line_end].to_string(),
+        length: text.lines().next().map_or(0, |l| l.chars().count()),
+    }
+}
+
 pub fn parse_for_shortcodes(
     specifier: &ModuleSpecifier,
     content: &str,
+    ctx: &ShortcodeContext,
+) -> Result<(String, Vec<Shortcode>), Error> {
+    let page = Page {
+        specifier,
+        content,
+        context: match ctx.maybe_templates {
+            Some(_) => page_context(specifier, content),
+            None => tera::Value::Null,
+        },
+        // invalid overrides are reported when the page is converted
+        options: front_matter_options(content, &ctx.markdown_options)
+            .unwrap_or_else(|_| ctx.markdown_options.clone()),
+    };
+    parse_with_page(&page, content, 0, ctx)
+}
+
+/// Resolves the shortcodes of `content`, which starts at the byte offset
+/// `offset` of the page.
+fn parse_with_page(
+    page: &Page,
+    content: &str,
+    offset: usize,
+    ctx: &ShortcodeContext,
 ) -> Result<(String, Vec<Shortcode>), Error> {
     let mut shortcodes: Vec<Shortcode> = Vec::new();
     let mut output = String::with_capacity(content.len());
     let mut pairs = match ContentParser::parse(Rule::page, content) {
         Ok(p) => p,
-        Err(_e) => {
-            return Err(generic_error("parsing failed"));
+        Err(e) => {
+            let start = match e.location {
+                InputLocation::Pos(pos) => pos,
+                InputLocation::Span((start, _)) => start,
+            };
+            let message = format!("Cannot parse shortcodes: {}", e.variant.message());
+            ctx.diagnostics.push(page.diagnostic(
+                Severity::Error,
+                offset + start,
+                "",
+                message.clone(),
+            ));
+            return Err(generic_error(message));
         }
     };
 
-    for p in pairs.next().unwrap().into_inner() {
-        match p.as_rule() {
-            Rule::inline_shortcode | Rule::ignored_inline_shortcode => {
-                let span = p.as_span();
-                let (name, args) = parse_shortcode_call(p);
+    let tags: Vec<_> = pairs.next().unwrap().into_inner().collect();
+    let closing_tags = match_blocks(&tags);
+    let mut block_end = 0;
+    for (p, closing_tag) in tags.into_iter().zip(closing_tags) {
+        let span = p.as_span();
+        if span.start() < block_end {
+            // resolved with the body of the enclosing block
+            continue;
+        }
+        let mut report = |severity: Severity, message: String| {
+            ctx.diagnostics.push(page.diagnostic(
+                severity,
+                offset + span.start(),
+                span.as_str(),
+                message,
+            ))
+        };
+
+        let (name, args, maybe_body, end) = match (p.as_rule(), closing_tag) {
+            (Rule::inline_shortcode, Some(closing_tag)) => {
+                let (name, mut args) = parse_shortcode_call(p);
+                let body = &content[span.end()..closing_tag.start];
 
-                match name.as_str() {
-                    "figure" => {
-                        output.push_str(&name);
-                        handle_figure(name, args, &span, &mut shortcodes)
+                let maybe_body = match ctx.maybe_templates.as_ref().filter(|t| t.has(&name)) {
+                    Some(templates) => {
+                        let html = render_body(page, body, offset + span.end(), ctx);
+                        args["body"] = tera::Value::String(html);
+                        handle_template(templates, &name, &args, page, &mut report)
                     }
-                    "relref" => {
-                        output.push_str(&name);
-                        handle_relref(name, args, &span, specifier, &mut shortcodes);
+                    None => {
+                        report(
+                            Severity::Warning,
+                            format!("Unknown block shortcode `{name}`"),
+                        );
+                        None
                     }
-                    _ => println!("Unknown identifier {name}"),
+                };
+                block_end = closing_tag.end;
+                (name, args, maybe_body, closing_tag.end)
+            }
+            (Rule::inline_shortcode, None) => {
+                let (name, args) = parse_shortcode_call(p);
+
+                let maybe_body = match ctx.maybe_templates.as_ref().filter(|t| t.has(&name)) {
+                    Some(templates) => handle_template(templates, &name, &args, page, &mut report),
+                    None => match name.as_str() {
+                        "figure" => {
+                            output.push_str(&name);
+                            handle_figure(&args, ctx, &mut report)
+                        }
+                        "relref" => {
+                            output.push_str(&name);
+                            handle_relref(&args, page.specifier, ctx, &mut report)
+                        }
+                        _ => {
+                            report(Severity::Warning, format!("Unknown shortcode `{name}`"));
+                            None
+                        }
+                    },
+                };
+                (name, args, maybe_body, span.end())
+            }
+            (Rule::shortcode_end, _) => {
+                let name = tag_name(&p);
+                report(
+                    Severity::Warning,
+                    format!("Closing tag of shortcode `{name}` without an opening tag"),
+                );
+                continue;
+            }
+            // `{{</* name */>}}` is written as `{{< name >}}`, e.g. to
+            // document shortcodes
+            (Rule::ignored_inline_shortcode | Rule::ignored_shortcode_end, _) => {
+                let tag = span.as_str();
+                let literal = format!("{{{{<{}>}}}}", &tag[5..tag.len() - 5]);
+                (
+                    tag_name(&p).to_string(),
+                    tera::Value::Null,
+                    Some(literal),
+                    span.end(),
+                )
+            }
+            _ => continue,
+        };
+
+        if let Some(body) = maybe_body {
+            let body = match body.trim_start().starts_with('<') {
+                // kept from the markdown, e.g. so it isn't omitted as raw HTML
+                true => {
+                    let source = &content[span.start()..end];
+                    let before = content[..span.start()].rsplit('\n').next().unwrap_or("");
+                    let after = content[end..].split('\n').next().unwrap_or("");
+                    let block = before.trim().is_empty()
+                        && after.trim().is_empty()
+                        && RawHtml::is_block(&body);
+                    ctx.raw_html.insert(body, block, source.lines().count())
+                }
+                false => body,
+            };
+            shortcodes.push(Shortcode {
+                name,
+                args,
+                span: span.start()..end,
+                body: Some(body),
+            });
+        }
+    }
+
+    Ok((output, shortcodes))
+}
+
+/// Returns the name of the shortcode of a tag.
+fn tag_name<'i>(tag: &Pair<'i, Rule>) -> &'i str {
+    tag.clone()
+        .into_inner()
+        .find(|p| p.as_rule() == Rule::ident)
+        .map_or("", |p| p.as_str())
+}
+
+/// Matches the opening tags with the closing tags of the same name, innermost
+/// first, and returns the span of the closing tag of every opening tag that
+/// has one. The other opening tags are inline shortcodes.
+fn match_blocks(tags: &[Pair<Rule>]) -> Vec<Option<Range<usize>>> {
+    let mut closing_tags = vec![None; tags.len()];
+    let mut open = Vec::new();
+    for (i, tag) in tags.iter().enumerate() {
+        match tag.as_rule() {
+            Rule::inline_shortcode => open.push((i, tag_name(tag))),
+            Rule::shortcode_end => {
+                let name = tag_name(tag);
+                if let Some(pos) = open.iter().rposition(|(_, n)| *n == name) {
+                    let span = tag.as_span();
+                    closing_tags[open[pos].0] = Some(span.start()..span.end());
+                    open.truncate(pos);
                 }
             }
             _ => {}
         }
     }
+    closing_tags
+}
 
-    Ok((output, shortcodes))
+/// Maps the byte offsets of a page whose shortcodes were replaced by
+/// [`replace_shortcodes`] back to the page as written, so diagnostics found
+/// after the replacement point at the right line.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// The span of every replaced shortcode in the page as written and the
+    /// length of its replacement, in order.
+    replacements: Vec<(Range<usize>, usize)>,
 }
 
-fn handle_figure(name: String, value: tera::Value, span: &Span, shortcodes: &mut Vec<Shortcode>) {
-    if let Some(src) = get_string("src", &value) {
-        let template = if let Some(caption) = get_string("caption", &value) {
-            format!(
-                r#"<figure><img style="max-width:100%;" src="/static{src}"><figcaption>{caption}</figcaption></figure>"#,
+impl SourceMap {
+    pub(crate) fn new(shortcodes: &[Shortcode]) -> Self {
+        Self {
+            replacements: shortcodes
+                .iter()
+                .filter_map(|sc| Some((sc.span.clone(), sc.body.as_ref()?.len())))
+                .collect(),
+        }
+    }
+
+    /// Returns the offset in the page as written of `offset` in the page with
+    /// its shortcodes replaced. Offsets in a replacement map to the start of
+    /// its shortcode.
+    fn original_offset(&self, offset: usize) -> usize {
+        let mut delta = 0isize;
+        for (span, len) in &self.replacements {
+            let start = span.start.saturating_add_signed(delta);
+            if offset < start {
+                break;
+            }
+            if offset < start + len {
+                return span.start;
+            }
+            delta += *len as isize - span.len() as isize;
+        }
+        offset.saturating_add_signed(-delta)
+    }
+
+    /// Moves `diagnostic`, found in `replaced`, to the same text in the page
+    /// as written `original`.
+    pub fn remap(&self, diagnostic: Diagnostic, replaced: &str, original: &str) -> Diagnostic {
+        if self.replacements.is_empty() {
+            return diagnostic;
+        }
+        let offset = offset_of(replaced, &diagnostic);
+        let start = self.original_offset(offset).min(original.len());
+        Diagnostic {
+            length: diagnostic.length,
+            ..diagnostic_at(
+                &diagnostic.specifier,
+                original,
+                diagnostic.severity,
+                start,
+                "",
+                diagnostic.message,
             )
-        } else {
+        }
+    }
+}
+
+/// Returns the byte offset in `content` of the line and column of `diagnostic`.
+fn offset_of(content: &str, diagnostic: &Diagnostic) -> usize {
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(diagnostic.line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    content[line_start..]
+        .char_indices()
+        .nth(diagnostic.column.saturating_sub(1))
+        .map_or(content.len(), |(i, _)| line_start + i)
+}
+
+/// Replaces the shortcodes in `content` by their rendered bodies.
+pub(crate) fn replace_shortcodes(content: &mut String, mut shortcodes: Vec<Shortcode>) {
+    // the ranges of the shortcodes are computed based on the original file
+    // and differences in ranges after a rendering step of a short code
+    // are not considered.
+    // So applying the shortcodes sequentially without taking the
+    // change of ranges into considerations leads to malformed output.
+    // We could add logic to update the ranges by an offset that gets updated
+    // after the application of each shortcode
+    // OR
+    // we simply reverse the array and update the file bottom-up instead of
+    // top-down.
+    shortcodes.reverse();
+    for sc in shortcodes {
+        if let Some(ref body) = sc.body {
+            content.replace_range(sc.span, body);
+        }
+    }
+}
+
+/// Renders the content of a block shortcode, including the shortcodes nested
+/// in it, to HTML.
+///
+/// Nested shortcodes are resolved against the body alone, so their spans stay
+/// relative to it and the block is replaced as a whole in the page.
+///
+/// Its links are resolved and reported like those of the page.
+fn render_body(page: &Page, body: &str, offset: usize, ctx: &ShortcodeContext) -> String {
+    let mut content = body.to_string();
+    let mut source_map = SourceMap::default();
+    if let Ok((_name, shortcodes)) = parse_with_page(page, &content, offset, ctx) {
+        source_map = SourceMap::new(&shortcodes);
+        replace_shortcodes(&mut content, shortcodes);
+    }
+    let body_ctx = ShortcodeContext {
+        diagnostics: Diagnostics::default(),
+        ..ctx.clone()
+    };
+    let (html, links) = body_to_html(page.specifier, &content, &page.options, &body_ctx);
+    ctx.body_links.extend(links);
+    for diagnostic in body_ctx.diagnostics.take() {
+        let diagnostic = source_map.remap(diagnostic, &content, body);
+        let start = offset_of(body, &diagnostic);
+        let text = body[start..]
+            .chars()
+            .take(diagnostic.length)
+            .collect::<String>();
+        ctx.diagnostics.push(page.diagnostic(
+            diagnostic.severity,
+            offset + start,
+            &text,
+            diagnostic.message,
+        ));
+    }
+    html
+}
+
+/// The context shared by all shortcodes of a page: its front matter and path.
+pub(crate) fn page_context(specifier: &ModuleSpecifier, content: &str) -> tera::Value {
+    let mut page = libs::serde_yaml::from_str::<tera::Value>(&extract_yaml(content))
+        .ok()
+        .filter(|v| v.is_object())
+        .unwrap_or_else(|| tera::Value::Object(tera::Map::new()));
+    page["path"] = tera::Value::String(specifier.path().to_string());
+    page
+}
+
+type Report<'a> = dyn FnMut(Severity, String) + 'a;
+
+fn handle_template(
+    templates: &ShortcodeTemplates,
+    name: &str,
+    value: &tera::Value,
+    page: &Page,
+    report: &mut Report,
+) -> Option<String> {
+    match templates.render(name, value, &page.context) {
+        Ok(body) => Some(body),
+        Err(e) => {
+            report(Severity::Error, e.to_string());
+            None
+        }
+    }
+}
+
+fn handle_figure(
+    value: &tera::Value,
+    ctx: &ShortcodeContext,
+    report: &mut Report,
+) -> Option<String> {
+    let src = match get_string("src", value) {
+        Some(src) => src,
+        None => {
+            report(Severity::Error, "`figure` requires a `src`".to_string());
+            return None;
+        }
+    };
+    let maybe_caption = get_string("caption", value);
+    let maybe_image =
+        ctx.maybe_image_processor
+            .as_ref()
+            .and_then(|processor| match processor.responsive(src) {
+                Ok(image) => Some(image),
+                Err(e) => {
+                    report(
+                        Severity::Warning,
+                        format!("Cannot process image {src}: {e}"),
+                    );
+                    None
+                }
+            });
+
+    let template = match (maybe_caption, maybe_image) {
+        (Some(caption), Some(image)) => format!(
+            "<figure>{}<figcaption>{}</figcaption></figure>",
+            render_picture(&image, "max-width:100%;", caption),
+            escape_html(caption)
+        ),
+        (None, Some(image)) => {
+            render_picture(&image, "width:456px;margin-top:5px;margin-bottom:5px;", "")
+        }
+        (Some(caption), None) => format!(
+            r#"<figure><img style="max-width:100%;" src="/static{src}"><figcaption>{}</figcaption></figure>"#,
+            escape_html(caption)
+        ),
+        (None, None) => {
             format!(r#"<img style="width:456px;margin-top:5px;margin-bottom:5px;" src="{src}">"#)
-        };
+        }
+    };
+
+    Some(template)
+}
 
-        shortcodes.push(Shortcode {
-            name,
-            args: value,
-            span: span.start()..span.end(),
-            body: Some(template.to_string()),
-        });
+/// Renders a `<picture>` offering every generated format, falling back to the
+/// original image.
+fn render_picture(image: &ResponsiveImage, style: &str, alt: &str) -> String {
+    let mut html = String::from("<picture>");
+    for source in image.sources.iter() {
+        html.push_str(&format!(
+            r#"<source type="{}" srcset="{}">"#,
+            source.format.mime_type(),
+            source.srcset()
+        ));
     }
+    html.push_str(&format!(
+        r#"<img style="{style}" src="{}" width="{}" height="{}" alt="{}" loading="lazy" decoding="async"></picture>"#,
+        image.url,
+        image.width,
+        image.height,
+        escape_html(alt)
+    ));
+    html
 }
 
 fn handle_relref(
-    name: String,
-    value: tera::Value,
-    span: &Span,
+    value: &tera::Value,
     specifier: &ModuleSpecifier,
-    shortcodes: &mut Vec<Shortcode>,
-) {
-    let maybe_path = specifier.to_file_path().ok();
-    let maybe_relref = get_string("relref", &value);
-
-    if let Some((Some(file_name), Some(path))) = Some((maybe_relref, maybe_path)) {
-        if let Some(title) = join(path, file_name).and_then(read_title_from_content_of_file) {
-            let template = format!("/notes/{}.html", slugify!(&title));
-            shortcodes.push(Shortcode {
-                name,
-                args: value,
-                span: span.start()..span.end(),
-                body: Some(template.to_string()),
-            });
+    ctx: &ShortcodeContext,
+    report: &mut Report,
+) -> Option<String> {
+    let file_name = match get_string("relref", value) {
+        Some(file_name) => file_name,
+        None => {
+            report(Severity::Error, "`relref` requires a file name".to_string());
+            return None;
         }
     };
+    let referrer = specifier.to_file_path().ok()?;
+
+    match ctx.content_index.read().resolve(&referrer, file_name) {
+        Some(permalink) => Some(permalink),
+        None => {
+            report(
+                Severity::Error,
+                format!("relref target not found: {file_name}"),
+            );
+            None
+        }
+    }
 }
 
 fn replace_string_markers(input: &str) -> String {
@@ -189,39 +621,279 @@ fn get_string<'a>(name: &str, value: &'a tera::Value) -> Option<&'a str> {
     value.get(name).and_then(|v| v.as_str())
 }
 
-fn join<P: AsRef<Path>>(path: PathBuf, file_name: P) -> Option<PathBuf> {
-    path.parent().map(|p| p.join(file_name))
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::{handle_shortcodes, markdown_to_html};
+    use berlin_core::{ContentIndex, PermalinkOptions};
+    use images::{ImageFormat, ImageSource, ImageVariant};
+    use libs::parking_lot::RwLock;
+    use libs::tera::Tera;
+    use std::path::PathBuf;
+    use std::sync::Arc;
 
-fn read_title_from_content_of_file(path: PathBuf) -> Option<String> {
-    ModuleSpecifier::from_file_path(path)
-        .ok()
-        .and_then(|p| std::fs::read_to_string(p.path()).ok())
-        .and_then(|s| extract_yaml(&s).ok())
-        .and_then(|s| libs::serde_yaml::from_str::<FrontMatter>(&s).ok())
-        .and_then(|fm| fm.title)
-}
+    fn to_html(specifier: &ModuleSpecifier, content: String, ctx: &ShortcodeContext) -> String {
+        let (_, html, _, _) =
+            markdown_to_html(specifier, content.into(), &ctx.markdown_options, ctx);
+        String::from_utf8(html).unwrap()
+    }
 
-fn extract_yaml(markdown: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let mut front_matter = String::default();
-    let mut sentinel = false;
-    let lines = markdown.lines();
+    #[test]
+    fn test_nested_block_shortcodes() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            (
+                "note.tera",
+                r#"<aside class="{{ type }}">{{ body }}</aside>"#,
+            ),
+            ("badge.tera", r#"<span>{{ args | join(sep="/") }}</span>"#),
+        ])
+        .unwrap();
+        let ctx = ShortcodeContext {
+            maybe_templates: Some(Arc::new(ShortcodeTemplates::from_tera(tera))),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let mut content = concat!(
+            "Before {{< badge \"a\" 1 >}}\n",
+            "{{< note type=\"warning\" >}}\n",
+            "**Careful**\n\n{{< note type=\"tip\" >}}inner{{< /note >}}\n",
+            "{{< /note >}}\n",
+            "After",
+        )
+        .to_string();
 
-    for line in lines {
-        if line.trim() == "---" {
-            if sentinel {
-                break;
-            }
+        let (_, shortcodes) = parse_for_shortcodes(&specifier, &content, &ctx).unwrap();
+        replace_shortcodes(&mut content, shortcodes);
 
-            sentinel = true;
-            continue;
-        }
+        assert_eq!(
+            to_html(&specifier, content, &ctx),
+            concat!(
+                "<p>Before <span>a/1</span></p>\n",
+                "<aside class=\"warning\"><p><strong>Careful</strong></p>\n<aside class=\"tip\"><p>inner</p>\n</aside>\n</aside>\n",
+                "<p>After</p>\n",
+            )
+        );
+    }
 
-        if sentinel {
-            front_matter.push_str(line);
-            front_matter.push('\n');
-        }
+    #[test]
+    fn test_block_body_with_page_options() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![("note.tera", "<aside>{{ body }}</aside>")])
+            .unwrap();
+        let ctx = ShortcodeContext {
+            maybe_templates: Some(Arc::new(ShortcodeTemplates::from_tera(tera))),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let mut content = concat!(
+            "---\nmarkdown: { smart_punctuation: true }\n---\n",
+            "{{< note >}}a -- b{{< /note >}}",
+        )
+        .to_string();
+
+        let (_, shortcodes) = parse_for_shortcodes(&specifier, &content, &ctx).unwrap();
+        replace_shortcodes(&mut content, shortcodes);
+
+        assert!(
+            to_html(&specifier, content, &ctx).ends_with("<aside><p>a \u{2013} b</p>\n</aside>\n")
+        );
+    }
+
+    #[test]
+    fn test_ignored_and_unclosed_shortcodes() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("note.tera", r#"<aside>{{ body }}</aside>"#),
+            ("badge.tera", r#"<span>{{ args.0 }}</span>"#),
+        ])
+        .unwrap();
+        let ctx = ShortcodeContext {
+            maybe_templates: Some(Arc::new(ShortcodeTemplates::from_tera(tera))),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let mut content = concat!(
+            "{{</* note */>}}{{</* badge \"a\" */>}}{{</* /note */>}}\n",
+            "{{< note >}}{{< badge \"b\" >}}{{< /note >}}\n",
+            "{{< badge \"c\" >}} {{< /badge >}}{{< /badge >}}",
+        )
+        .to_string();
+
+        let (_, shortcodes) = parse_for_shortcodes(&specifier, &content, &ctx).unwrap();
+        replace_shortcodes(&mut content, shortcodes);
+
+        assert_eq!(
+            to_html(&specifier, content, &ctx),
+            concat!(
+                "<p>{{&lt; note &gt;}}{{&lt; badge &quot;a&quot; &gt;}}{{&lt; /note &gt;}}</p>\n",
+                "<aside><p><span>b</span></p>\n</aside>\n",
+                "<p><span>c</span>{{&lt; /badge &gt;}}</p>\n",
+            )
+        );
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].column, 33);
+    }
+
+    #[test]
+    fn test_remap_diagnostics_after_shortcodes() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![("note.tera", "1\n2\n3\n4\n5")])
+            .unwrap();
+        let ctx = ShortcodeContext {
+            maybe_templates: Some(Arc::new(ShortcodeTemplates::from_tera(tera))),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let source = "{{< note >}}\n\nSee [b](b.md).\n";
+        let mut content = source.to_string();
+
+        let source_map = handle_shortcodes(&specifier, &mut content, &ctx);
+        markdown_to_html(
+            &specifier,
+            content.as_str().into(),
+            &ctx.markdown_options,
+            &ctx,
+        );
+
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics[0].line, 7);
+        let diagnostic = source_map.remap(diagnostics[0].clone(), &content, source);
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 9));
+        assert_eq!(diagnostic.snippet, "See [b](b.md).");
+        assert_eq!(diagnostic.length, 4);
+        assert_eq!(source_map.original_offset(3), 0);
+    }
+
+    #[test]
+    fn test_render_picture() {
+        let variant = |format, width| ImageVariant {
+            url: format!("/static/processed_images/cat.{width}w.{format}"),
+            width,
+            height: width / 2,
+            format,
+        };
+        let image = ResponsiveImage {
+            url: "/static/cat.jpg".to_string(),
+            width: 960,
+            height: 480,
+            sources: vec![ImageSource {
+                format: ImageFormat::Avif,
+                variants: vec![
+                    variant(ImageFormat::Avif, 480),
+                    variant(ImageFormat::Avif, 960),
+                ],
+            }],
+        };
+
+        assert_eq!(
+            render_picture(&image, "max-width:100%;", "Cats & <dogs>"),
+            concat!(
+                r#"<picture><source type="image/avif" srcset="/static/processed_images/cat.480w.avif 480w, /static/processed_images/cat.960w.avif 960w">"#,
+                r#"<img style="max-width:100%;" src="/static/cat.jpg" width="960" height="480" alt="Cats &amp; &lt;dogs&gt;" loading="lazy" decoding="async"></picture>"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_report_missing_relref() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///notes/a.md").unwrap();
+        let content = "---\ntitle: A\n---\nSee {{< relref \"missing.md\" >}}.\n";
+
+        let (_, shortcodes) = parse_for_shortcodes(&specifier, content, &ctx).unwrap();
+
+        assert!(shortcodes.is_empty());
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert!(diagnostic.is_error());
+        assert_eq!(diagnostic.message, "relref target not found: missing.md");
+        assert_eq!(
+            (diagnostic.line, diagnostic.column, diagnostic.length),
+            (4, 5, 27)
+        );
+        assert_eq!(diagnostic.snippet, "See {{< relref \"missing.md\" >}}.");
     }
 
-    Ok(front_matter)
+    #[test]
+    fn test_resolve_links_through_content_index() {
+        let content_path = PathBuf::from("/site/content");
+        let ctx = ShortcodeContext {
+            content_index: Arc::new(RwLock::new(
+                ContentIndex::build(
+                    &content_path,
+                    &[
+                        content_path.join("notes/a.md"),
+                        content_path.join("notes/rust-in-2023.md"),
+                        content_path.join("about.md"),
+                    ],
+                    &PermalinkOptions::default(),
+                )
+                .unwrap(),
+            )),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+        let mut content =
+            "[Rust]({{< relref \"rust-in-2023.md\" >}}) [about](../about.md#me) [x](x.md)\n"
+                .to_string();
+
+        handle_shortcodes(&specifier, &mut content, &ctx);
+        let (_, html, links, _) =
+            markdown_to_html(&specifier, Arc::from(content), &ctx.markdown_options, &ctx);
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains(r#"<a href="/notes/rust-in-2023.html">Rust</a>"#));
+        assert!(html.contains(r#"<a href="/about.html#me">about</a>"#));
+        assert_eq!(links, vec!["/notes/rust-in-2023.html", "/about.html#me"]);
+
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "link target not found: x.md");
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (1, 62));
+    }
+
+    #[test]
+    fn test_resolve_links_in_block_bodies() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![("note.tera", "<aside>{{ body }}</aside>")])
+            .unwrap();
+        let content_path = PathBuf::from("/site/content");
+        let ctx = ShortcodeContext {
+            maybe_templates: Some(Arc::new(ShortcodeTemplates::from_tera(tera))),
+            content_index: Arc::new(RwLock::new(
+                ContentIndex::build(
+                    &content_path,
+                    &[
+                        content_path.join("notes/a.md"),
+                        content_path.join("notes/b.md"),
+                    ],
+                    &PermalinkOptions::default(),
+                )
+                .unwrap(),
+            )),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+        let mut content = concat!(
+            "---\ntitle: A\n---\n",
+            "{{< note >}}\nSee [b](b.md).\n\nAnd [x](x.md).\n{{< /note >}}\n",
+        )
+        .to_string();
+
+        handle_shortcodes(&specifier, &mut content, &ctx);
+        let (_, html, links, _) =
+            markdown_to_html(&specifier, Arc::from(content), &ctx.markdown_options, &ctx);
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains(r#"<aside><p>See <a href="/notes/b.html">b</a>.</p>"#));
+        assert_eq!(links, vec!["/notes/b.html"]);
+
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "link target not found: x.md");
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (7, 9));
+        assert_eq!(diagnostics[0].snippet, "And [x](x.md).");
+    }
 }
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use errors::error::generic_error;
+use libs::anyhow::Error;
+use libs::tera::{self, Tera};
+
+/// Shortcodes defined by the site, one template per shortcode.
+///
+/// `{{< name key=value >}}` renders `name.tera` with its arguments and the
+/// front matter of the page as `page`.
+pub struct ShortcodeTemplates {
+    tera: Tera,
+}
+
+impl ShortcodeTemplates {
+    pub fn new(path: &Path) -> Result<Self, Error> {
+        let pattern = format!("{}/**/*.tera", path.display());
+        let mut tera = Tera::new(&pattern)
+            .map_err(|e| generic_error(format!("Cannot load shortcodes: {e}")))?;
+        tera.autoescape_on(vec![]);
+
+        Ok(Self { tera })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_tera(tera: Tera) -> Self {
+        Self { tera }
+    }
+
+    fn template_name(name: &str) -> String {
+        format!("{name}.tera")
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        let template_name = Self::template_name(name);
+        self.tera.get_template_names().any(|n| n == template_name)
+    }
+
+    pub fn render(
+        &self,
+        name: &str,
+        args: &tera::Value,
+        page: &tera::Value,
+    ) -> Result<String, Error> {
+        let mut context = tera::Context::from_value(args.clone())?;
+        context.insert("page", page);
+
+        self.tera
+            .render(&Self::template_name(name), &context)
+            .map_err(|e| match std::error::Error::source(&e) {
+                Some(reason) => {
+                    generic_error(format!("Cannot render shortcode `{name}`: {e}: {reason}"))
+                }
+                None => generic_error(format!("Cannot render shortcode `{name}`: {e}")),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_shortcode_template() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("youtube.tera", r#"<iframe src="https://www.youtube.com/embed/{{ id }}" title="{{ page.title }}"></iframe>"#)
+            .unwrap();
+        let templates = ShortcodeTemplates::from_tera(tera);
+
+        let args = libs::serde_json::json!({ "id": "dQw4w9WgXcQ" });
+        let page = libs::serde_json::json!({ "title": "Videos" });
+
+        assert!(templates.has("youtube"));
+        assert!(!templates.has("figure"));
+        assert_eq!(
+            templates.render("youtube", &args, &page).unwrap(),
+            r#"<iframe src="https://www.youtube.com/embed/dQw4w9WgXcQ" title="Videos"></iframe>"#
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::markdown::MarkdownOptions;
+use libs::lazy_static;
+use libs::regex::Regex;
+
+lazy_static::lazy_static! {
+    static ref MORE_RE: Regex = Regex::new(r"(?m)^<!--\s*more\s*-->").unwrap();
+    static ref PARAGRAPH_RE: Regex = Regex::new(r#"(?s)<p(?: data-sourcepos="[^"]*")?>(.*?)</p>"#).unwrap();
+    static ref ANNOTATION_RE: Regex = Regex::new(r"(?s)<annotation\b.*?</annotation>").unwrap();
+    static ref TAG_RE: Regex = Regex::new(r"(?s)<!--.*?-->|<[^>]*>").unwrap();
+    static ref ENTITY_RE: Regex = Regex::new(r"&#?\w+;").unwrap();
+}
+
+/// The summary and the length of a rendered page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// The HTML before a `<!-- more -->` line, or else the first
+    /// `summary_words` words of its paragraphs, in a paragraph.
+    pub html: String,
+    pub word_count: usize,
+    /// The minutes it takes to read the page, rounded up.
+    pub reading_time: usize,
+}
+
+/// Summarizes the rendered page `html`, with the `summary_words` and
+/// `words_per_minute` of `options`.
+pub fn summarize(html: &str, options: &MarkdownOptions) -> Summary {
+    let word_count = words(html).len();
+
+    let html = match MORE_RE.find(html) {
+        Some(more) => html[..more.start()].trim_end().to_string(),
+        None => {
+            // headings, code and the like don't make sense in a sentence
+            let paragraphs = PARAGRAPH_RE
+                .captures_iter(html)
+                .map(|caps| caps[1].to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let words = words(&paragraphs);
+            match words.len() {
+                0 => String::new(),
+                n if n > options.summary_words => {
+                    format!("<p>{}…</p>", words[..options.summary_words].join(" "))
+                }
+                _ => format!("<p>{}</p>", words.join(" ")),
+            }
+        }
+    };
+    Summary {
+        html,
+        word_count,
+        reading_time: word_count.div_ceil(options.words_per_minute.max(1)).max(1),
+    }
+}
+
+/// Whether the HTML block `html` is a `<!-- more -->` line, which is kept when
+/// the raw HTML of the page is omitted.
+pub(crate) fn is_more_marker(html: &str) -> bool {
+    let html = html.trim();
+    MORE_RE
+        .find(html)
+        .is_some_and(|more| more.end() == html.len())
+}
+
+/// The words of the text of `html`, leaving out the annotations of formulas
+/// and punctuation on its own.
+fn words(html: &str) -> Vec<String> {
+    let html = ANNOTATION_RE.replace_all(html, "");
+    let text = TAG_RE.replace_all(&html, " ");
+    text.split_whitespace()
+        .filter(|word| {
+            ENTITY_RE
+                .replace_all(word, "")
+                .chars()
+                .any(|c| c.is_alphanumeric())
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let options = MarkdownOptions {
+            summary_words: 3,
+            ..MarkdownOptions::default()
+        };
+
+        let summary = summarize(
+            "<h1 id=\"a\">A title</h1>\n<p>Fish &amp; chips, <em>twice</em> - done.</p>\n",
+            &options,
+        );
+        assert_eq!(
+            summary,
+            Summary {
+                html: "<p>Fish chips, twice…</p>".to_string(),
+                word_count: 6,
+                reading_time: 1,
+            }
+        );
+
+        let summary = summarize("<p>Intro.</p>\n<!-- more -->\n<p>Rest.</p>\n", &options);
+        assert_eq!(summary.html, "<p>Intro.</p>");
+        assert_eq!(summary.word_count, 2);
+
+        let summary = summarize(
+            "<h2>Setup</h2>\n<pre><code>cargo run</code></pre>\n<p>Run it.</p>\n",
+            &options,
+        );
+        assert_eq!(summary.html, "<p>Run it.</p>");
+
+        let options = MarkdownOptions {
+            words_per_minute: 200,
+            ..MarkdownOptions::default()
+        };
+        let summary = summarize(&format!("<p>{}</p>", "word ".repeat(399)), &options);
+        assert_eq!(summary.reading_time, 2);
+        assert_eq!(summarize("", &options).reading_time, 1);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::shortcode::{diagnostic_at, ShortcodeContext};
+use berlin_core::{split_front_matter, MediaType, ModuleSpecifier, Severity};
+use libs::lazy_static;
+use libs::regex::{Captures, Regex};
+use std::ops::Range;
+
+lazy_static::lazy_static! {
+    static ref WIKI_LINK_RE: Regex = Regex::new(r"\[\[(?P<target>[^\[\]|]+)(?:\|(?P<label>[^\[\]]+))?\]\]").unwrap();
+}
+
+/// Replaces the wiki links `[[Note Title]]` and `[[file|label]]` in `content`
+/// by links to the permalinks of their targets.
+///
+/// Markdown gets `[label](/notes/note.html)`, org gets
+/// `[[/notes/note.html][label]]`. Links in the front matter, code blocks and
+/// inline code are left alone, as are the native org links like `[[*Heading]]` or `[[file:note.org]]`. Targets
+/// not found in the content index are reported as errors.
+pub fn handle_wiki_links(
+    specifier: &ModuleSpecifier,
+    content: &mut String,
+    media_type: MediaType,
+    ctx: &ShortcodeContext,
+) {
+    let maybe_referrer = specifier.to_file_path().ok();
+    let content_index = ctx.content_index.read();
+    let mut output = String::with_capacity(content.len());
+    let mut offset = 0;
+    let mut in_code_block = false;
+    let front_matter_end = split_front_matter(content).map_or(0, |(_, _, range)| range.end);
+
+    for line in content.split_inclusive('\n') {
+        if offset < front_matter_end {
+            output.push_str(line);
+            offset += line.len();
+            continue;
+        }
+        if is_code_fence(line, media_type, in_code_block) {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block {
+            output.push_str(line);
+            offset += line.len();
+            continue;
+        }
+
+        let code_spans = inline_code_spans(line, media_type);
+        let replaced = WIKI_LINK_RE.replace_all(line, |caps: &Captures| {
+            let target = &caps["target"];
+            let start = caps.get(0).unwrap().start();
+            if code_spans.iter().any(|span| span.contains(&start))
+                || media_type == MediaType::Org
+                    && (target.starts_with(['*', '#']) || target.contains(':'))
+            {
+                return caps[0].to_string();
+            }
+
+            let label = caps
+                .name("label")
+                .map_or(target, |label| label.as_str())
+                .trim();
+            match maybe_referrer
+                .as_ref()
+                .and_then(|referrer| content_index.resolve_wiki_link(referrer, target))
+            {
+                Some(permalink) if media_type == MediaType::Org => {
+                    format!("[[{permalink}][{label}]]")
+                }
+                Some(permalink) => format!("[{label}]({permalink})"),
+                None => {
+                    ctx.diagnostics.push(diagnostic_at(
+                        specifier,
+                        content,
+                        Severity::Error,
+                        offset + start,
+                        &caps[0],
+                        format!("wiki link target not found: {}", target.trim()),
+                    ));
+                    caps[0].to_string()
+                }
+            }
+        });
+        output.push_str(&replaced);
+        offset += line.len();
+    }
+
+    *content = output;
+}
+
+/// Returns the byte ranges of the inline code of `line`: the code spans
+/// between backticks in markdown, the `~code~` and `=verbatim=` in org.
+fn inline_code_spans(line: &str, media_type: MediaType) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut rest = 0;
+    match media_type {
+        MediaType::Org => {
+            for (start, c) in line.char_indices() {
+                let is_marker = matches!(c, '~' | '=')
+                    && start >= rest
+                    && line[..start]
+                        .chars()
+                        .next_back()
+                        .is_none_or(|p| p.is_whitespace() || "({'\"".contains(p));
+                if !is_marker || line[start + 1..].starts_with(char::is_whitespace) {
+                    continue;
+                }
+                if let Some(end) = line[start + 1..]
+                    .find(c)
+                    .map(|i| start + 1 + i)
+                    .filter(|end| *end > start + 1)
+                {
+                    spans.push(start..end + 1);
+                    rest = end + 1;
+                }
+            }
+        }
+        _ => {
+            while let Some(start) = line[rest..].find('`').map(|i| rest + i) {
+                let ticks = line[start..].len() - line[start..].trim_start_matches('`').len();
+                let fence = &line[start..start + ticks];
+                let mut end = None;
+                let mut search = start + ticks;
+                while let Some(i) = line[search..].find(fence).map(|i| search + i) {
+                    let run = line[i..].len() - line[i..].trim_start_matches('`').len();
+                    if run == ticks {
+                        end = Some(i + ticks);
+                        break;
+                    }
+                    search = i + run;
+                }
+                match end {
+                    Some(end) => {
+                        spans.push(start..end);
+                        rest = end;
+                    }
+                    None => rest = start + ticks,
+                }
+            }
+        }
+    }
+    spans
+}
+
+pub(crate) fn is_code_fence(line: &str, media_type: MediaType, in_code_block: bool) -> bool {
+    let line = line.trim_start().to_lowercase();
+    match media_type {
+        MediaType::Org if in_code_block => line.starts_with("#+end_"),
+        MediaType::Org => line.starts_with("#+begin_src") || line.starts_with("#+begin_example"),
+        _ => line.starts_with("```") || line.starts_with("~~~"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use berlin_core::{ContentIndex, PermalinkOptions};
+    use libs::parking_lot::RwLock;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_handle_wiki_links() {
+        let content_path = PathBuf::from("/site/content");
+        let ctx = ShortcodeContext {
+            content_index: Arc::new(RwLock::new(
+                ContentIndex::build(
+                    &content_path,
+                    &[
+                        content_path.join("notes/a.md"),
+                        content_path.join("notes/digital-garden.md"),
+                    ],
+                    &PermalinkOptions::default(),
+                )
+                .unwrap(),
+            )),
+            ..ShortcodeContext::default()
+        };
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+        let mut content = "See [[Digital Garden]] and [[a#intro|the intro]].\n\
+                           ```\n[[Digital Garden]]\n```\n\
+                           [[Missing]] `[[a]]` ``[[a]] ` [[a]]``\n"
+            .to_string();
+
+        handle_wiki_links(&specifier, &mut content, MediaType::Markdown, &ctx);
+
+        assert_eq!(
+            content,
+            "See [Digital Garden](/notes/digital-garden.html) and [the intro](/notes/a.html#intro).\n\
+             ```\n[[Digital Garden]]\n```\n\
+             [[Missing]] `[[a]]` ``[[a]] ` [[a]]``\n"
+        );
+        let diagnostics = ctx.diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "wiki link target not found: Missing"
+        );
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (5, 1));
+    }
+
+    #[test]
+    fn test_skip_front_matter() {
+        let ctx = ShortcodeContext::default();
+        let specifier = ModuleSpecifier::parse("file:///site/content/notes/a.md").unwrap();
+        for front_matter in [
+            "---\nup: \"[[Index]]\"\naliases: [[old]]\n---\n",
+            "+++\nup = \"[[Index]]\"\n+++\n",
+            "{\n\"up\": \"[[Index]]\"\n}\n",
+        ] {
+            let mut content = front_matter.to_string();
+            handle_wiki_links(&specifier, &mut content, MediaType::Markdown, &ctx);
+            assert_eq!(content, front_matter);
+        }
+        assert!(ctx.diagnostics.take().is_empty());
+    }
+
+    #[test]
+    fn test_inline_code_spans() {
+        let line = "`a` [[b]] ``c ` d`` `unclosed";
+        assert_eq!(
+            inline_code_spans(line, MediaType::Markdown),
+            vec![0..3, 10..19]
+        );
+        let line = "~[[a]]~ [[b]] =[[c]]= a=b= ~ [[d]]~";
+        assert_eq!(inline_code_spans(line, MediaType::Org), vec![0..7, 14..21]);
+    }
+}
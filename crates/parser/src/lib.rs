@@ -1,3 +1,3 @@
 mod parser;
 
-pub use parser::{CapturingParser, ParsedSourceStore, Parser};
+pub use parser::{CapturingParser, DefaultMarkdownParser, ParsedSourceStore, Parser};
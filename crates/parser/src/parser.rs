@@ -1,7 +1,9 @@
-use berlin_core::{MediaType, ModuleSpecifier, ParsedSource, ParsedSourceBuilder};
+use berlin_core::{Diagnostics, MediaType, ModuleSpecifier, ParsedSource, ParsedSourceBuilder};
 use errors::error::generic_error;
 use libs::anyhow::Error;
-use markdown::handle_shortcodes;
+use markdown::{
+    handle_math, handle_shortcodes, handle_wiki_links, BodyLinks, RawHtml, ShortcodeContext,
+};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::path::Path;
@@ -45,13 +47,19 @@ impl ParsedSourceStore for DefaultParsedSourceStore {
 
 pub struct CapturingParser<'a> {
     _parser: Option<&'a dyn Parser>,
+    markdown_parser: &'a DefaultMarkdownParser,
     store: &'a dyn ParsedSourceStore,
 }
 
 impl<'a> CapturingParser<'a> {
-    pub fn new(parser: Option<&'a dyn Parser>, store: &'a dyn ParsedSourceStore) -> Self {
+    pub fn new(
+        parser: Option<&'a dyn Parser>,
+        markdown_parser: &'a DefaultMarkdownParser,
+        store: &'a dyn ParsedSourceStore,
+    ) -> Self {
         Self {
             _parser: parser,
+            markdown_parser,
             store,
         }
     }
@@ -74,15 +82,13 @@ impl<'a> Parser for CapturingParser<'a> {
         media_type: MediaType,
     ) -> Result<ParsedSource, Error> {
         if let Some(parsed_source) = self.get_from_store_if_matches(specifier, media_type) {
+            self.markdown_parser.report(&parsed_source);
             Ok(parsed_source)
         } else {
             let parsed_source = match media_type {
-                MediaType::Org => {
-                    DefaultOrgParser::default().parse(specifier, source, media_type)?
-                }
-                MediaType::Markdown => {
-                    DefaultMarkdownParser::default().parse(specifier, source, media_type)?
-                }
+                MediaType::Org => DefaultOrgParser::new(self.markdown_parser.clone())
+                    .parse(specifier, source, media_type)?,
+                MediaType::Markdown => self.markdown_parser.parse(specifier, source, media_type)?,
                 MediaType::Tera => ParsedSourceBuilder::new(specifier.to_string(), media_type)
                     .content(source.as_ref().to_string())
                     .build(),
@@ -97,6 +103,7 @@ impl<'a> Parser for CapturingParser<'a> {
 
             self.store
                 .set_parsed_source(specifier.clone(), parsed_source.clone());
+            self.markdown_parser.report(&parsed_source);
 
             Ok(parsed_source)
         }
@@ -113,10 +120,47 @@ pub trait Parser {
 }
 
 #[derive(Default, Clone)]
-pub struct DefaultOrgParser;
+pub struct DefaultOrgParser {
+    markdown_parser: DefaultMarkdownParser,
+}
+
+impl DefaultOrgParser {
+    pub fn new(markdown_parser: DefaultMarkdownParser) -> Self {
+        Self { markdown_parser }
+    }
+}
 
 #[derive(Default, Clone)]
-pub struct DefaultMarkdownParser;
+pub struct DefaultMarkdownParser {
+    shortcode_context: ShortcodeContext,
+}
+
+impl DefaultMarkdownParser {
+    pub fn new(shortcode_context: ShortcodeContext) -> Self {
+        Self { shortcode_context }
+    }
+
+    /// Returns a parser collecting the diagnostics and the generated HTML of a
+    /// single source. The diagnostics are kept on the parsed source.
+    fn for_source(&self) -> Self {
+        Self {
+            shortcode_context: ShortcodeContext {
+                diagnostics: Diagnostics::default(),
+                raw_html: RawHtml::default(),
+                body_links: BodyLinks::default(),
+                ..self.shortcode_context.clone()
+            },
+        }
+    }
+
+    /// Reports the diagnostics of `parsed_source` to the diagnostics of the
+    /// build.
+    fn report(&self, parsed_source: &ParsedSource) {
+        for diagnostic in parsed_source.diagnostics() {
+            self.shortcode_context.diagnostics.push(diagnostic.clone());
+        }
+    }
+}
 
 #[derive(Default, Clone)]
 pub struct DefaultCssParser;
@@ -131,8 +175,23 @@ impl Parser for DefaultOrgParser {
         source: Arc<str>,
         media_type: MediaType,
     ) -> Result<ParsedSource, Error> {
-        match org::parse(source) {
-            Ok(data) => DefaultMarkdownParser {}.parse(specifier, Arc::from(data), media_type),
+        let markdown_parser = self.markdown_parser.for_source();
+        let mut source = source.to_string();
+        handle_wiki_links(
+            specifier,
+            &mut source,
+            MediaType::Org,
+            &markdown_parser.shortcode_context,
+        );
+        handle_math(
+            specifier,
+            &mut source,
+            MediaType::Org,
+            &markdown_parser.shortcode_context,
+        );
+
+        match org::parse(Arc::from(source)) {
+            Ok(data) => markdown_parser.parse_source(specifier, Arc::from(data), media_type),
             Err(e) => Err(generic_error(format!(
                 "Cannot convert file {} to {}\nReason: {}",
                 specifier,
@@ -148,20 +207,88 @@ impl Parser for DefaultMarkdownParser {
         &self,
         specifier: &ModuleSpecifier,
         source: Arc<str>,
-        _media_type: MediaType,
+        media_type: MediaType,
+    ) -> Result<ParsedSource, Error> {
+        self.for_source()
+            .parse_source(specifier, source, media_type)
+    }
+}
+
+impl DefaultMarkdownParser {
+    fn parse_source(
+        &self,
+        specifier: &ModuleSpecifier,
+        source: Arc<str>,
+        media_type: MediaType,
     ) -> Result<ParsedSource, Error> {
         // preprocess source
         let mut content = source.to_string();
-        handle_shortcodes(&specifier, &mut content);
+        let source_map = handle_shortcodes(&specifier, &mut content, &self.shortcode_context);
+        // The diagnostics found so far point into `source`, the next ones
+        // into the content with its shortcodes replaced.
+        let mut diagnostics = self.shortcode_context.diagnostics.take();
+        let replaced = content.clone();
+        // The wiki links and math of org notes are converted before pandoc.
+        if media_type == MediaType::Markdown {
+            handle_wiki_links(specifier, &mut content, media_type, &self.shortcode_context);
+            handle_math(specifier, &mut content, media_type, &self.shortcode_context);
+        }
 
         // process source
-        let (maybe_front_matter, data) =
-            markdown::markdown_to_html(Arc::from(content), markdown::MarkdownOptions::default());
+        let options = markdown::page_options(specifier, &content, &self.shortcode_context);
+        markdown::validate_front_matter(specifier, &content, &self.shortcode_context);
+        let (maybe_front_matter, data, links, toc) = markdown::markdown_to_html(
+            specifier,
+            Arc::from(content),
+            &options,
+            &self.shortcode_context,
+        );
+        let maybe_description_html = maybe_front_matter
+            .as_ref()
+            .and_then(|fm| fm.description.as_ref())
+            .map(|description| markdown::string_to_html(description, &options));
+        let data = String::from_utf8(data).unwrap();
+        let summary = markdown::summarize(&data, &options);
         let metadata = std::fs::metadata(Path::new(specifier.path()))?;
+        let maybe_permalink = specifier.to_file_path().ok().and_then(|path| {
+            self.shortcode_context
+                .content_index
+                .read()
+                .get(&path)
+                .map(|p| p.to_string())
+        });
+        let terms = self
+            .shortcode_context
+            .taxonomies
+            .iter()
+            .map(|(name, taxonomy)| {
+                let values = maybe_front_matter
+                    .as_ref()
+                    .map(|fm| fm.values(taxonomy.key(name)))
+                    .unwrap_or_default();
+                (name.clone(), taxonomy.terms(name, &values))
+            })
+            .collect();
+        diagnostics.extend(
+            self.shortcode_context
+                .diagnostics
+                .take()
+                .into_iter()
+                .map(|diagnostic| source_map.remap(diagnostic, &replaced, &source)),
+        );
         let parsed_source = ParsedSourceBuilder::new(specifier.to_string(), MediaType::Html)
-            .content(String::from_utf8(data).unwrap())
+            .content(data)
             .maybe_front_matter(maybe_front_matter)
             .metadata(metadata)
+            .maybe_permalink(maybe_permalink)
+            .terms(terms)
+            .links(links)
+            .maybe_description_html(maybe_description_html)
+            .toc(toc)
+            .maybe_summary_html(Some(summary.html).filter(|html| !html.is_empty()))
+            .word_count(summary.word_count)
+            .reading_time(summary.reading_time)
+            .diagnostics(diagnostics)
             .build();
         Ok(parsed_source)
     }
@@ -227,4 +354,28 @@ mod tests {
             .unwrap();
         print!("{:?}", parsed_source.data());
     }
+
+    #[test]
+    fn test_report_diagnostics_of_cached_sources() {
+        let path = std::env::temp_dir().join(format!("bln-diagnostics-{}.md", std::process::id()));
+        let source = "---\ntitle: A\n---\nSee [b](b.md).\n";
+        std::fs::write(&path, source).unwrap();
+        let specifier = ModuleSpecifier::from_file_path(&path).unwrap();
+        let ctx = ShortcodeContext::default();
+        let markdown_parser = DefaultMarkdownParser::new(ctx.clone());
+        let store = DefaultParsedSourceStore::default();
+        let parser = CapturingParser::new(None, &markdown_parser, &store);
+
+        for _ in 0..2 {
+            let parsed_source = parser
+                .parse(&specifier, source.into(), MediaType::Markdown)
+                .unwrap();
+            assert_eq!(parsed_source.diagnostics().len(), 1);
+            let diagnostics = ctx.diagnostics.take();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].message, "link target not found: b.md");
+            assert_eq!(diagnostics[0].line, 4);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
 }
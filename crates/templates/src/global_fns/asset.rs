@@ -0,0 +1,148 @@
+use libs::base64::{engine::general_purpose::STANDARD, Engine};
+use libs::parking_lot::RwLock;
+use libs::sha2::{Digest, Sha256, Sha384};
+use libs::tera;
+use libs::tera::{Function, Value};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The number of hex digits of the content hash used in fingerprinted names.
+const FINGERPRINT_LENGTH: usize = 6;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AssetEntry {
+    /// The path the asset was written to, relative to the target directory.
+    pub path: String,
+    /// The subresource-integrity hash of the asset.
+    pub integrity: String,
+}
+
+/// Maps logical asset paths like `css/styles.css` to the files actually
+/// written to the target directory.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AssetManifest(BTreeMap<String, AssetEntry>);
+
+pub type SharedAssetManifest = Arc<RwLock<AssetManifest>>;
+
+impl AssetManifest {
+    /// Adds an asset to the manifest and returns the path it should be written to.
+    pub fn insert(&mut self, logical_path: &str, content: &[u8], fingerprint: bool) -> String {
+        let path = if fingerprint {
+            fingerprinted_path(logical_path, content)
+        } else {
+            logical_path.to_string()
+        };
+        let integrity = format!("sha384-{}", STANDARD.encode(Sha384::digest(content)));
+
+        self.0.insert(
+            logical_path.to_string(),
+            AssetEntry {
+                path: path.clone(),
+                integrity,
+            },
+        );
+
+        path
+    }
+
+    pub fn get(&self, logical_path: &str) -> Option<&AssetEntry> {
+        self.0.get(logical_path.trim_start_matches('/'))
+    }
+}
+
+/// Inserts the content hash in front of the extension, so `css/styles.css`
+/// becomes `css/styles.3f9a1c.css`.
+fn fingerprinted_path(logical_path: &str, content: &[u8]) -> String {
+    let hash = Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    let hash = &hash[..FINGERPRINT_LENGTH];
+
+    let path = Path::new(logical_path);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!(
+                "{}.{}.{}",
+                stem.to_string_lossy(),
+                hash,
+                ext.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .to_string(),
+        _ => format!("{logical_path}.{hash}"),
+    }
+}
+
+fn lookup(
+    manifest: &SharedAssetManifest,
+    fn_name: &str,
+    args: &HashMap<String, Value>,
+) -> tera::Result<AssetEntry> {
+    let path = match args.get("path").and_then(|v| v.as_str()) {
+        Some(path) => path,
+        None => {
+            return Err(tera::Error::msg(format!(
+                "Function `{fn_name}` requires a string argument `path`"
+            )))
+        }
+    };
+
+    manifest.read().get(path).cloned().ok_or_else(|| {
+        tera::Error::msg(format!(
+            "Function `{fn_name}`: asset `{path}` not found in the asset manifest"
+        ))
+    })
+}
+
+/// `asset(path="css/styles.css")` resolves a logical path to the URL of the
+/// written, possibly fingerprinted, file.
+pub(crate) struct Asset(pub(crate) SharedAssetManifest);
+
+impl Function for Asset {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let entry = lookup(&self.0, "asset", args)?;
+        Ok(Value::String(format!("/{}", entry.path)))
+    }
+}
+
+/// `asset_integrity(path="css/styles.css")` returns the subresource-integrity
+/// hash to be used in the `integrity` attribute.
+pub(crate) struct AssetIntegrity(pub(crate) SharedAssetManifest);
+
+impl Function for AssetIntegrity {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let entry = lookup(&self.0, "asset_integrity", args)?;
+        Ok(Value::String(entry.integrity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_fingerprinted_asset() {
+        let mut manifest = AssetManifest::default();
+
+        let path = manifest.insert("css/styles.css", b"h1{color:red}", true);
+
+        assert!(path.starts_with("css/styles."));
+        assert!(path.ends_with(".css"));
+        assert_eq!(path.len(), "css/styles.css".len() + FINGERPRINT_LENGTH + 1);
+        let entry = manifest.get("/css/styles.css").unwrap();
+        assert_eq!(entry.path, path);
+        assert!(entry.integrity.starts_with("sha384-"));
+    }
+
+    #[test]
+    fn test_insert_asset_without_fingerprint() {
+        let mut manifest = AssetManifest::default();
+
+        let path = manifest.insert("static/js/main.js", b"", false);
+
+        assert_eq!(path, "static/js/main.js");
+    }
+}
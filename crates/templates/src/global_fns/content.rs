@@ -1,9 +1,15 @@
-use berlin_core::ParsedSource;
+use berlin_core::{ParsedSource, SharedContentIndex};
 use errors::error::generic_error;
+use images::ImageProcessor;
 use libs::anyhow::Error;
 use libs::tera;
 use libs::tera::{Context, Function, Tera, Value};
 use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::asset::{Asset, AssetIntegrity, SharedAssetManifest};
+use super::images::ResizeImage;
+use super::permalink::Permalink;
 
 #[derive(Clone)]
 struct Content(String);
@@ -33,6 +39,35 @@ impl Hera {
         }
     }
 
+    /// Registers the `asset` and `asset_integrity` functions resolving
+    /// logical paths through `manifest`.
+    pub fn register_asset_manifest(&mut self, manifest: SharedAssetManifest) {
+        self.inner
+            .tera
+            .register_function("asset", Asset(manifest.clone()));
+        self.inner
+            .tera
+            .register_function("asset_integrity", AssetIntegrity(manifest));
+    }
+
+    /// Registers the `resize_image` function.
+    pub fn register_image_processor(&mut self, processor: Arc<ImageProcessor>) {
+        self.inner
+            .tera
+            .register_function("resize_image", ResizeImage(processor));
+    }
+
+    /// Registers the `permalink` function.
+    pub fn register_content_index(&mut self, content_index: SharedContentIndex) {
+        self.inner
+            .tera
+            .register_function("permalink", Permalink(content_index));
+    }
+
+    pub fn has_template(&self, name: &str) -> bool {
+        self.inner.tera.get_template_names().any(|n| n == name)
+    }
+
     pub fn render_parsed_source_with_context(
         &mut self,
         file_path: &str,
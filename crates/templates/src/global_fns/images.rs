@@ -0,0 +1,42 @@
+use images::ImageProcessor;
+use libs::tera;
+use libs::tera::{Function, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// `resize_image(path="pics/cat.jpg", width=800, format="webp")` resizes an
+/// image below `static/` and returns its `url`, `width` and `height`.
+pub(crate) struct ResizeImage(pub(crate) Arc<ImageProcessor>);
+
+impl Function for ResizeImage {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let path = match args.get("path").and_then(|v| v.as_str()) {
+            Some(path) => path,
+            None => {
+                return Err(tera::Error::msg(
+                    "Function `resize_image` requires a string argument `path`",
+                ))
+            }
+        };
+        let width = args.get("width").and_then(|v| v.as_u64()).map(|v| v as u32);
+        let height = args
+            .get("height")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32);
+        let maybe_format = match args.get("format").and_then(|v| v.as_str()) {
+            Some(format) => Some(
+                format
+                    .parse()
+                    .map_err(|e| tera::Error::msg(format!("{e}")))?,
+            ),
+            None => None,
+        };
+
+        let variant = self
+            .0
+            .resize(path, width, height, maybe_format)
+            .map_err(|e| tera::Error::msg(format!("Function `resize_image`: {e}")))?;
+
+        tera::to_value(variant).map_err(tera::Error::from)
+    }
+}
@@ -1,3 +1,7 @@
+mod asset;
 mod content;
+mod images;
+mod permalink;
 
+pub use self::asset::{AssetEntry, AssetManifest, SharedAssetManifest};
 pub use self::content::Hera;
@@ -0,0 +1,28 @@
+use berlin_core::SharedContentIndex;
+use libs::tera;
+use libs::tera::{Function, Value};
+use std::collections::HashMap;
+
+/// `permalink(path="notes/rust.md")` returns the URL of the page rendered
+/// from a file below `content/`.
+pub(crate) struct Permalink(pub(crate) SharedContentIndex);
+
+impl Function for Permalink {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let path = match args.get("path").and_then(|v| v.as_str()) {
+            Some(path) => path,
+            None => {
+                return Err(tera::Error::msg(
+                    "Function `permalink` requires a string argument `path`",
+                ))
+            }
+        };
+
+        match self.0.read().get_relative(path) {
+            Some(permalink) => Ok(Value::String(permalink.to_string())),
+            None => Err(tera::Error::msg(format!(
+                "Function `permalink`: `{path}` not found in the content directory"
+            ))),
+        }
+    }
+}
@@ -3,7 +3,7 @@ pub mod global_fns;
 use libs::once_cell::sync::Lazy;
 use libs::tera::Tera;
 
-pub use global_fns::Hera;
+pub use global_fns::{AssetEntry, AssetManifest, Hera, SharedAssetManifest};
 
 pub static BLN_TERA: Lazy<Tera> = Lazy::new(|| {
     let tera = Tera::default();